@@ -0,0 +1,719 @@
+//! Content sniffing for `validate_data`.
+//!
+//! Binary formats are recognised by their magic numbers. Anything else is
+//! decoded as text, going by a byte order mark or else trying UTF-16,
+//! UTF-8 and Latin-1, and then checked for JSON, XML and CSV in that
+//! order. Structured text is parsed in full; a failure is reported with
+//! its line and column, both counted from 1, columns in characters.
+
+use serde::Serialize;
+
+/// Share of control characters above which decoded text counts as binary.
+const MAX_CONTROL_SHARE: f64 = 0.01;
+/// Delimiters tried when sniffing CSV, most common first.
+const CSV_DELIMITERS: [char; 4] = [',', ';', '\t', '|'];
+/// Magic numbers, checked at the start of the data.
+const MAGIC: &[(&[u8], ContentType)] = &[
+    (b"\x89PNG\r\n\x1a\n", ContentType::Png),
+    (b"%PDF-", ContentType::Pdf),
+    (b"PK\x03\x04", ContentType::Zip),
+    (b"PK\x05\x06", ContentType::Zip),
+    (b"PK\x07\x08", ContentType::Zip),
+    (b"\x1f\x8b", ContentType::Gzip),
+    (b"\x7fELF", ContentType::Elf),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentType {
+    Png,
+    Pdf,
+    Zip,
+    Gzip,
+    Elf,
+    Json,
+    Csv,
+    Xml,
+    Text,
+    Binary,
+}
+
+impl ContentType {
+    pub fn mime(self) -> &'static str {
+        match self {
+            ContentType::Png => "image/png",
+            ContentType::Pdf => "application/pdf",
+            ContentType::Zip => "application/zip",
+            ContentType::Gzip => "application/gzip",
+            ContentType::Elf => "application/x-executable",
+            ContentType::Json => "application/json",
+            ContentType::Csv => "text/csv",
+            ContentType::Xml => "application/xml",
+            ContentType::Text => "text/plain",
+            ContentType::Binary => "application/octet-stream",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Encoding {
+    #[serde(rename = "ascii")]
+    Ascii,
+    #[serde(rename = "utf-8")]
+    Utf8,
+    /// UTF-8 behind a byte order mark.
+    #[serde(rename = "utf-8-bom")]
+    Utf8Bom,
+    #[serde(rename = "utf-16le")]
+    Utf16Le,
+    #[serde(rename = "utf-16be")]
+    Utf16Be,
+    /// Not UTF-8, read one byte per character.
+    #[serde(rename = "latin-1")]
+    Latin1,
+    #[serde(rename = "binary")]
+    Binary,
+}
+
+/// Where and why structured text failed to parse.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ParseError {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Parse result of JSON, XML or CSV text.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Structure {
+    pub valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ParseError>,
+    /// JSON: `object`, `array` or another value type. XML: the root
+    /// element name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root: Option<String>,
+    /// CSV: records, header included.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rows: Option<usize>,
+    /// CSV: fields per record.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub columns: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delimiter: Option<char>,
+}
+
+/// What `validate_data` returns.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DataReport {
+    pub size: usize,
+    pub is_empty: bool,
+    pub content_type: ContentType,
+    pub mime: &'static str,
+    pub encoding: Encoding,
+    pub is_utf8: bool,
+    /// Shannon entropy in bits per byte, from 0 to 8.
+    pub entropy: f64,
+    /// Text only; 0 for binary data.
+    pub line_count: usize,
+    pub word_count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub structure: Option<Structure>,
+}
+
+pub fn analyze(bytes: &[u8]) -> DataReport {
+    let magic = MAGIC
+        .iter()
+        .find(|(magic, _)| bytes.starts_with(magic))
+        .map(|(_, content_type)| *content_type);
+    let (encoding, text) = match magic {
+        Some(_) => (Encoding::Binary, None),
+        None => decode_text(bytes),
+    };
+    let (content_type, structure) = match (magic, &text) {
+        (Some(content_type), _) => (content_type, None),
+        (None, Some(text)) => sniff_text(text),
+        (None, None) => (ContentType::Binary, None),
+    };
+    let text = text.as_deref().unwrap_or_default();
+    DataReport {
+        size: bytes.len(),
+        is_empty: bytes.is_empty(),
+        content_type,
+        mime: content_type.mime(),
+        encoding,
+        is_utf8: std::str::from_utf8(bytes).is_ok(),
+        entropy: entropy(bytes),
+        line_count: text.lines().count(),
+        word_count: text.split_whitespace().count(),
+        structure,
+    }
+}
+
+/// Shannon entropy of the byte distribution, in bits per byte.
+pub fn entropy(bytes: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for &byte in bytes {
+        counts[byte as usize] += 1;
+    }
+    let len = bytes.len() as f64;
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// The text in `bytes` and how it was encoded, or `None` when it does not
+/// read as text.
+fn decode_text(bytes: &[u8]) -> (Encoding, Option<String>) {
+    let decoded = if let Some(rest) = bytes.strip_prefix(b"\xef\xbb\xbf") {
+        std::str::from_utf8(rest)
+            .ok()
+            .map(|text| (Encoding::Utf8Bom, text.to_string()))
+    } else if let Some(rest) = bytes.strip_prefix(b"\xff\xfe") {
+        decode_utf16(rest, u16::from_le_bytes).map(|text| (Encoding::Utf16Le, text))
+    } else if let Some(rest) = bytes.strip_prefix(b"\xfe\xff") {
+        decode_utf16(rest, u16::from_be_bytes).map(|text| (Encoding::Utf16Be, text))
+    } else if let Some(encoding) = utf16_without_bom(bytes) {
+        let text = match encoding {
+            Encoding::Utf16Le => decode_utf16(bytes, u16::from_le_bytes),
+            _ => decode_utf16(bytes, u16::from_be_bytes),
+        };
+        text.map(|text| (encoding, text))
+    } else if let Ok(text) = std::str::from_utf8(bytes) {
+        let encoding = match text.is_ascii() {
+            true => Encoding::Ascii,
+            false => Encoding::Utf8,
+        };
+        Some((encoding, text.to_string()))
+    } else {
+        let text: String = bytes.iter().map(|&byte| char::from(byte)).collect();
+        Some((Encoding::Latin1, text))
+    };
+    match decoded {
+        Some((encoding, text)) if looks_like_text(&text) => (encoding, Some(text)),
+        _ => (Encoding::Binary, None),
+    }
+}
+
+fn decode_utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> Option<String> {
+    if !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    let units = bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+    char::decode_utf16(units).collect::<Result<_, _>>().ok()
+}
+
+/// UTF-16 of mostly ASCII text has a zero in every other byte. Checked
+/// before UTF-8, which it also is: zero bytes are valid UTF-8.
+fn utf16_without_bom(bytes: &[u8]) -> Option<Encoding> {
+    if bytes.len() < 4 || !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    let zeros = |parity: usize| {
+        bytes
+            .iter()
+            .skip(parity)
+            .step_by(2)
+            .filter(|&&byte| byte == 0)
+            .count()
+    };
+    let half = bytes.len() / 2;
+    let (even, odd) = (zeros(0), zeros(1));
+    if odd * 10 >= half * 9 && even == 0 {
+        Some(Encoding::Utf16Le)
+    } else if even * 10 >= half * 9 && odd == 0 {
+        Some(Encoding::Utf16Be)
+    } else {
+        None
+    }
+}
+
+/// Few control characters besides whitespace, form feeds and escapes.
+fn looks_like_text(text: &str) -> bool {
+    let mut chars = 0usize;
+    let mut controls = 0usize;
+    for c in text.chars() {
+        chars += 1;
+        if c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x0c' | '\x1b') {
+            controls += 1;
+        }
+    }
+    controls as f64 <= chars as f64 * MAX_CONTROL_SHARE
+}
+
+fn sniff_text(text: &str) -> (ContentType, Option<Structure>) {
+    let trimmed = text.trim_start_matches('\u{feff}').trim_start();
+    if trimmed.starts_with(['{', '[']) {
+        return (ContentType::Json, Some(check_json(text)));
+    }
+    if trimmed.starts_with('<') {
+        return (ContentType::Xml, Some(check_xml(text)));
+    }
+    match sniff_delimiter(text) {
+        Some(delimiter) => (ContentType::Csv, Some(check_csv(text, delimiter))),
+        None => (ContentType::Text, None),
+    }
+}
+
+fn check_json(text: &str) -> Structure {
+    match serde_json::from_str::<serde_json::Value>(text) {
+        Ok(value) => Structure {
+            root: Some(
+                match value {
+                    serde_json::Value::Object(_) => "object",
+                    serde_json::Value::Array(_) => "array",
+                    _ => "value",
+                }
+                .to_string(),
+            ),
+            ..Structure::valid()
+        },
+        Err(e) => Structure::invalid(ParseError {
+            message: e.to_string(),
+            line: e.line(),
+            column: e.column(),
+        }),
+    }
+}
+
+impl Structure {
+    fn valid() -> Self {
+        Self {
+            valid: true,
+            error: None,
+            root: None,
+            rows: None,
+            columns: None,
+            delimiter: None,
+        }
+    }
+
+    fn invalid(error: ParseError) -> Self {
+        Self {
+            valid: false,
+            error: Some(error),
+            ..Self::valid()
+        }
+    }
+}
+
+/// Line and column of byte `offset` in `text`.
+fn position(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+fn error_at(text: &str, offset: usize, message: impl Into<String>) -> ParseError {
+    let (line, column) = position(text, offset);
+    ParseError {
+        message: message.into(),
+        line,
+        column,
+    }
+}
+
+/// The delimiter that splits the first two records into the same number
+/// of fields, more than one.
+fn sniff_delimiter(text: &str) -> Option<char> {
+    let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+    let (first, second) = (lines.next()?, lines.next()?);
+    CSV_DELIMITERS.into_iter().find(|&delimiter| {
+        let fields = |line: &str| unquoted(line, delimiter);
+        fields(first) > 0 && fields(first) == fields(second)
+    })
+}
+
+/// Occurrences of `delimiter` in `line` outside double quotes.
+fn unquoted(line: &str, delimiter: char) -> usize {
+    let mut quoted = false;
+    line.chars()
+        .filter(|&c| {
+            if c == '"' {
+                quoted = !quoted;
+            }
+            !quoted && c == delimiter
+        })
+        .count()
+}
+
+/// Parses RFC 4180 CSV: fields may be quoted, quotes inside doubled, and
+/// every record must have as many fields as the first. Blank lines are
+/// ignored.
+fn check_csv(text: &str, delimiter: char) -> Structure {
+    let mut rows = 0usize;
+    let mut columns: Option<usize> = None;
+    let mut fields = 1usize;
+    let mut record_start = 0usize;
+    let mut field_empty = true;
+    let mut chars = text.char_indices().peekable();
+    let mut end_record = |fields: usize, start: usize, blank: bool| -> Result<(), ParseError> {
+        if blank {
+            return Ok(());
+        }
+        rows += 1;
+        match *columns.get_or_insert(fields) {
+            expected if expected != fields => Err(error_at(
+                text,
+                start,
+                format!("Expected {} fields, found {}", expected, fields),
+            )),
+            _ => Ok(()),
+        }
+    };
+
+    let result = (|| {
+        while let Some((offset, c)) = chars.next() {
+            match c {
+                '"' if field_empty => {
+                    // Quoted field: runs to the next quote not doubled.
+                    loop {
+                        match chars.next() {
+                            None => {
+                                return Err(error_at(text, offset, "Unterminated quoted field"))
+                            }
+                            Some((_, '"')) if chars.peek().map(|&(_, c)| c) == Some('"') => {
+                                chars.next();
+                            }
+                            Some((_, '"')) => break,
+                            Some(_) => {}
+                        }
+                    }
+                    field_empty = false;
+                    match chars.peek() {
+                        None | Some((_, '\n' | '\r')) => {}
+                        Some(&(_, c)) if c == delimiter => {}
+                        Some(&(after, _)) => {
+                            return Err(error_at(
+                                text,
+                                after,
+                                "Expected a delimiter after the closing quote",
+                            ))
+                        }
+                    }
+                }
+                '"' => return Err(error_at(text, offset, "Quote inside an unquoted field")),
+                c if c == delimiter => {
+                    fields += 1;
+                    field_empty = true;
+                }
+                '\r' if chars.peek().map(|&(_, c)| c) == Some('\n') => {}
+                '\n' | '\r' => {
+                    let blank = fields == 1 && field_empty;
+                    end_record(fields, record_start, blank)?;
+                    fields = 1;
+                    field_empty = true;
+                    record_start = offset + 1;
+                }
+                _ => field_empty = false,
+            }
+        }
+        end_record(fields, record_start, fields == 1 && field_empty)
+    })();
+
+    match result {
+        Ok(()) => Structure {
+            rows: Some(rows),
+            columns,
+            delimiter: Some(delimiter),
+            ..Structure::valid()
+        },
+        Err(error) => Structure {
+            delimiter: Some(delimiter),
+            ..Structure::invalid(error)
+        },
+    }
+}
+
+/// Checks that XML is well formed: one root element, properly nested and
+/// closed tags, quoted attributes and complete comments, CDATA sections,
+/// processing instructions and entity references. Names and DTDs are not
+/// validated further.
+fn check_xml(text: &str) -> Structure {
+    let mut open: Vec<(&str, usize)> = Vec::new();
+    let mut root: Option<&str> = None;
+    let mut offset = 0usize;
+
+    let result = (|| {
+        while offset < text.len() {
+            let rest = &text[offset..];
+            let Some(tag) = rest.find('<') else {
+                check_text(text, offset, rest, open.is_empty())?;
+                break;
+            };
+            check_text(text, offset, &rest[..tag], open.is_empty())?;
+            let start = offset + tag;
+            let rest = &text[start..];
+            let close = |end: &str, what: &str| {
+                rest.find(end)
+                    .map(|at| start + at + end.len())
+                    .ok_or_else(|| error_at(text, start, format!("Unterminated {}", what)))
+            };
+            offset = if rest.starts_with("<?") {
+                close("?>", "processing instruction")?
+            } else if rest.starts_with("<!--") {
+                close("-->", "comment")?
+            } else if rest.starts_with("<![CDATA[") {
+                if open.is_empty() {
+                    return Err(error_at(text, start, "CDATA outside the root element"));
+                }
+                close("]]>", "CDATA section")?
+            } else if rest.starts_with("<!") {
+                match rest.find(['[', '>']) {
+                    Some(at) if rest.as_bytes()[at] == b'[' => close("]>", "document type")?,
+                    _ => close(">", "document type")?,
+                }
+            } else if let Some(body) = rest.strip_prefix("</") {
+                let end = body
+                    .find('>')
+                    .ok_or_else(|| error_at(text, start, "Unterminated closing tag"))?;
+                let name = body[..end].trim_end();
+                match open.pop() {
+                    Some((expected, _)) if expected == name => {}
+                    Some((expected, _)) => {
+                        return Err(error_at(
+                            text,
+                            start,
+                            format!("Expected </{}>, found </{}>", expected, name),
+                        ))
+                    }
+                    None => {
+                        return Err(error_at(
+                            text,
+                            start,
+                            format!("Closing tag </{}> without an opening tag", name),
+                        ))
+                    }
+                }
+                start + 2 + end + 1
+            } else {
+                let (name, end, self_closing) = start_tag(text, start)?;
+                if open.is_empty() {
+                    if root.is_some() {
+                        return Err(error_at(text, start, "More than one root element"));
+                    }
+                    root = Some(name);
+                }
+                if !self_closing {
+                    open.push((name, start));
+                }
+                end
+            };
+        }
+        if let Some((name, at)) = open.last() {
+            return Err(error_at(
+                text,
+                *at,
+                format!("Element <{}> is not closed", name),
+            ));
+        }
+        if root.is_none() {
+            return Err(error_at(text, text.len(), "No root element"));
+        }
+        Ok(())
+    })();
+
+    match result {
+        Ok(()) => Structure {
+            root: root.map(str::to_string),
+            ..Structure::valid()
+        },
+        Err(error) => Structure {
+            root: root.map(str::to_string),
+            ..Structure::invalid(error)
+        },
+    }
+}
+
+/// Parses the start tag at `start`: its name, the offset after it and
+/// whether it closes itself.
+fn start_tag(text: &str, start: usize) -> Result<(&str, usize, bool), ParseError> {
+    let unterminated = || error_at(text, start, "Unterminated tag");
+    let is_name_char = |c: char| !c.is_whitespace() && !matches!(c, '/' | '>' | '=' | '<');
+    let body = &text[start + 1..];
+    let name_len = body.find(|c| !is_name_char(c)).unwrap_or(body.len());
+    let name = &body[..name_len];
+    if !name.starts_with(|c: char| c.is_alphabetic() || c == '_' || c == ':') {
+        return Err(error_at(text, start + 1, "Invalid element name"));
+    }
+    let mut at = start + 1 + name_len;
+    loop {
+        let rest = &text[at..];
+        let skipped = rest.len() - rest.trim_start().len();
+        at += skipped;
+        let rest = &text[at..];
+        if rest.starts_with("/>") {
+            return Ok((name, at + 2, true));
+        }
+        if rest.starts_with('>') {
+            return Ok((name, at + 1, false));
+        }
+        if rest.is_empty() {
+            return Err(unterminated());
+        }
+        if skipped == 0 {
+            return Err(error_at(
+                text,
+                at,
+                "Expected whitespace before an attribute",
+            ));
+        }
+        let attribute_len = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+        if attribute_len == 0 {
+            return Err(error_at(text, at, "Invalid attribute"));
+        }
+        at += attribute_len;
+        let rest = text[at..].trim_start();
+        at = text.len() - rest.len();
+        let Some(rest) = rest.strip_prefix('=') else {
+            return Err(error_at(text, at, "Expected = after the attribute name"));
+        };
+        let value = rest.trim_start();
+        at = text.len() - value.len();
+        let Some(quote) = value.chars().next().filter(|c| matches!(c, '"' | '\'')) else {
+            return Err(error_at(text, at, "Attribute values must be quoted"));
+        };
+        let end = value[1..].find(quote).ok_or_else(unterminated)?;
+        check_entities(text, at + 1, &value[1..1 + end])?;
+        at += 1 + end + 1;
+    }
+}
+
+/// Character data between tags: only whitespace outside the root element,
+/// and no stray `&` or `<` anywhere.
+fn check_text(text: &str, offset: usize, content: &str, outside: bool) -> Result<(), ParseError> {
+    if outside {
+        if let Some(at) = content.find(|c: char| !c.is_whitespace()) {
+            return Err(error_at(text, offset + at, "Text outside the root element"));
+        }
+    }
+    check_entities(text, offset, content)
+}
+
+fn check_entities(text: &str, offset: usize, content: &str) -> Result<(), ParseError> {
+    for (at, _) in content.match_indices('&') {
+        let reference = &content[at + 1..];
+        let end = reference.find(';').filter(|&end| {
+            let name = &reference[..end];
+            !name.is_empty()
+                && name
+                    .trim_start_matches('#')
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+        });
+        if end.is_none() {
+            return Err(error_at(
+                text,
+                offset + at,
+                "Unescaped & or malformed entity",
+            ));
+        }
+    }
+    if let Some(at) = content.find('<') {
+        return Err(error_at(text, offset + at, "Unescaped <"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn structure(text: &str) -> Structure {
+        analyze(text.as_bytes()).structure.unwrap()
+    }
+
+    fn error_position(structure: Structure) -> (usize, usize) {
+        let error = structure.error.unwrap();
+        (error.line, error.column)
+    }
+
+    #[test]
+    fn recognises_binary_formats_by_magic() {
+        let png = analyze(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR");
+        assert_eq!(
+            (png.content_type, png.mime),
+            (ContentType::Png, "image/png")
+        );
+        assert_eq!(png.encoding, Encoding::Binary);
+        assert_eq!(analyze(b"%PDF-1.7\n").content_type, ContentType::Pdf);
+        assert_eq!(analyze(b"PK\x03\x04rest").content_type, ContentType::Zip);
+        assert_eq!(
+            analyze(&[0, 159, 146, 150, 1, 2, 3]).content_type,
+            ContentType::Binary
+        );
+        assert!(analyze(b"").is_empty);
+    }
+
+    #[test]
+    fn detects_text_encodings() {
+        assert_eq!(analyze(b"plain").encoding, Encoding::Ascii);
+        assert_eq!(analyze("naïve".as_bytes()).encoding, Encoding::Utf8);
+        assert_eq!(analyze(b"\xef\xbb\xbfbom").encoding, Encoding::Utf8Bom);
+        assert_eq!(analyze(b"\xff\xfeh\0i\0").encoding, Encoding::Utf16Le);
+        assert_eq!(analyze(b"\0h\0e\0y\0!").encoding, Encoding::Utf16Be);
+        let latin = analyze(b"caf\xe9 cr\xe8me");
+        assert_eq!((latin.encoding, latin.is_utf8), (Encoding::Latin1, false));
+        assert_eq!(latin.word_count, 2);
+    }
+
+    #[test]
+    fn parses_json_and_reports_where_it_breaks() {
+        let report = analyze(b"{\"a\": [1, 2]}");
+        assert_eq!(report.content_type, ContentType::Json);
+        assert_eq!(structure("[1, 2]").root.as_deref(), Some("array"));
+        let broken = structure("{\n  \"a\": 1,\n  \"b\" 2\n}");
+        assert!(!broken.valid);
+        assert_eq!(error_position(broken), (3, 7));
+    }
+
+    #[test]
+    fn parses_csv_with_quoted_fields() {
+        let csv = structure("name;note\nann;\"a;b \"\"c\"\"\"\r\nbob;x\n\n");
+        assert!(csv.valid);
+        assert_eq!(
+            (csv.rows, csv.columns, csv.delimiter),
+            (Some(3), Some(2), Some(';'))
+        );
+        let ragged = structure("a,b\n1,2\n3,4,5\n");
+        assert!(!ragged.valid);
+        assert_eq!(error_position(ragged), (3, 1));
+        let unterminated = structure("a,b\n1,\"open\n");
+        assert_eq!(error_position(unterminated), (2, 3));
+    }
+
+    #[test]
+    fn checks_that_xml_is_well_formed() {
+        let xml = structure(
+            "<?xml version=\"1.0\"?>\n<!-- c -->\n<root a='1'><b>x &amp; y</b><c/>\
+             <![CDATA[<raw>]]></root>\n",
+        );
+        assert!(xml.valid);
+        assert_eq!(xml.root.as_deref(), Some("root"));
+        assert_eq!(error_position(structure("<a>\n  <b></c>\n</a>")), (2, 6));
+        assert_eq!(error_position(structure("<a><b></b>")), (1, 1));
+        assert!(!structure("<a/><b/>").valid);
+        assert!(!structure("<a x=1/>").valid);
+        assert!(!structure("<a>fish & chips</a>").valid);
+    }
+
+    #[test]
+    fn measures_entropy_and_counts() {
+        assert_eq!(entropy(b""), 0.0);
+        assert_eq!(entropy(b"aaaa"), 0.0);
+        assert_eq!(entropy(b"abab"), 1.0);
+        let all: Vec<u8> = (0..=255).collect();
+        assert_eq!(entropy(&all), 8.0);
+        let text = analyze(b"one two\nthree\n");
+        assert_eq!((text.line_count, text.word_count), (2, 3));
+        assert_eq!(text.content_type, ContentType::Text);
+    }
+}
@@ -0,0 +1,230 @@
+//! Animated GIF and APNG export of a stream.
+//!
+//! Every screen of [`sender::screens`] becomes one animation frame, in the
+//! order a live sender would show them. Header symbols can be a larger
+//! version than the data symbols, and grid screens are larger still, so
+//! all screens are centred on a canvas the size of the largest one. Both
+//! formats are lossless and the images only hold black, white and the
+//! colours of layered screens, so the file decodes exactly like a live
+//! display.
+//!
+//! [`sender::screens`]: crate::sender::screens
+
+use serde::Deserialize;
+
+use crate::color::{self, ColorMosaic};
+
+/// Same as the delay between frames of the live sender.
+pub const DEFAULT_DELAY_MS: u16 = 250;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnimationFormat {
+    #[default]
+    Gif,
+    Apng,
+}
+
+impl AnimationFormat {
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            AnimationFormat::Gif => "image/gif",
+            AnimationFormat::Apng => "image/apng",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            AnimationFormat::Gif => "gif",
+            AnimationFormat::Apng => "png",
+        }
+    }
+}
+
+/// Options of the `create_stream_animation` command.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AnimationOptions {
+    pub format: AnimationFormat,
+    /// Time each frame is shown. GIF rounds it to 10 ms.
+    pub delay_ms: Option<u16>,
+    /// How many times the animation plays; 0 or unset loops forever.
+    pub loop_count: Option<u16>,
+}
+
+/// One canvas-sized image of module masks per screen (0 white, 7 black).
+fn canvas_frames(screens: &[ColorMosaic], scale: usize) -> (usize, usize, Vec<Vec<u8>>) {
+    let sizes: Vec<(usize, usize)> = screens
+        .iter()
+        .map(|screen| screen.image_size(scale))
+        .collect();
+    let width = sizes.iter().map(|size| size.0).max().unwrap_or(0);
+    let height = sizes.iter().map(|size| size.1).max().unwrap_or(0);
+    let images = screens
+        .iter()
+        .zip(sizes)
+        .map(|(screen, (screen_width, screen_height))| {
+            let masks = screen.to_pixel_masks(scale);
+            if (screen_width, screen_height) == (width, height) {
+                return masks;
+            }
+            let (left, top) = ((width - screen_width) / 2, (height - screen_height) / 2);
+            let mut canvas = vec![0u8; width * height];
+            for (y, row) in masks.chunks(screen_width).enumerate() {
+                let start = (y + top) * width + left;
+                canvas[start..start + screen_width].copy_from_slice(row);
+            }
+            canvas
+        })
+        .collect();
+    (width, height, images)
+}
+
+/// Renders `screens` as an animation at `scale` pixels per module.
+pub fn render_animation(
+    screens: &[ColorMosaic],
+    scale: usize,
+    options: &AnimationOptions,
+) -> Result<Vec<u8>, String> {
+    if screens.is_empty() {
+        return Err("No frames to animate".to_string());
+    }
+    let (width, height, images) = canvas_frames(screens, scale.max(1));
+    let (width, height) = match (u16::try_from(width), u16::try_from(height)) {
+        (Ok(width), Ok(height)) => (width, height),
+        _ => {
+            return Err(format!(
+                "{}×{} pixel frames are too large to animate",
+                width, height
+            ))
+        }
+    };
+    let delay_ms = options.delay_ms.unwrap_or(DEFAULT_DELAY_MS);
+    let plays = options.loop_count.unwrap_or(0);
+    let gray = screens.iter().all(ColorMosaic::is_gray);
+
+    let format = options.format;
+    let failed =
+        |e: &dyn std::fmt::Display| format!("Failed to encode {:?} animation: {}", format, e);
+    match format {
+        AnimationFormat::Gif => {
+            write_gif(width, height, &images, delay_ms, plays).map_err(|e| failed(&e))
+        }
+        AnimationFormat::Apng => {
+            write_apng(width, height, &images, gray, delay_ms, plays).map_err(|e| failed(&e))
+        }
+    }
+}
+
+fn write_gif(
+    width: u16,
+    height: u16,
+    images: &[Vec<u8>],
+    delay_ms: u16,
+    plays: u16,
+) -> Result<Vec<u8>, gif::EncodingError> {
+    // Palette index i is the colour of module mask i.
+    let palette: Vec<u8> = (0..8).flat_map(color::color).collect();
+
+    let mut output = Vec::new();
+    {
+        let mut encoder = gif::Encoder::new(&mut output, width, height, &palette)?;
+        // The NETSCAPE extension counts repetitions after the first play.
+        encoder.set_repeat(match plays {
+            0 => gif::Repeat::Infinite,
+            plays => gif::Repeat::Finite(plays - 1),
+        })?;
+        for image in images {
+            let mut frame = gif::Frame::from_indexed_pixels(width, height, image.clone(), None);
+            frame.delay = delay_ms.div_ceil(10);
+            encoder.write_frame(&frame)?;
+        }
+    }
+    Ok(output)
+}
+
+fn write_apng(
+    width: u16,
+    height: u16,
+    images: &[Vec<u8>],
+    gray: bool,
+    delay_ms: u16,
+    plays: u16,
+) -> Result<Vec<u8>, png::EncodingError> {
+    let mut output = Vec::new();
+    let mut encoder = png::Encoder::new(&mut output, width as u32, height as u32);
+    encoder.set_color(if gray {
+        png::ColorType::Grayscale
+    } else {
+        png::ColorType::Rgb
+    });
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(images.len() as u32, plays as u32)?;
+    encoder.set_frame_delay(delay_ms, 1000)?;
+    let mut writer = encoder.write_header()?;
+    for image in images {
+        let pixels: Vec<u8> = if gray {
+            image.iter().map(|&mask| color::color(mask)[0]).collect()
+        } else {
+            image.iter().flat_map(|&mask| color::color(mask)).collect()
+        };
+        writer.write_image_data(&pixels)?;
+    }
+    writer.finish()?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::offline;
+    use crate::protocol::StreamStore;
+    use crate::sender::{self, SendOptions};
+
+    /// Renders `data` as an animation and scans every frame back in.
+    fn round_trip(data: &[u8], send: &SendOptions, format: AnimationFormat) -> Vec<u8> {
+        let (stream_id, screens) = sender::stream_screens(data, send).unwrap();
+        let pictures: Vec<ColorMosaic> = screens.iter().map(|screen| screen.picture()).collect();
+        let options = AnimationOptions {
+            format,
+            ..AnimationOptions::default()
+        };
+        let file = render_animation(&pictures, 3, &options).unwrap();
+
+        let mut store = StreamStore::new();
+        let frames = offline::read_frames(&file, |width, height, rgba| {
+            for symbol in color::scan(width, height, rgba, 4) {
+                store.process_payload(&symbol.content.unwrap()).unwrap();
+            }
+        })
+        .unwrap();
+        assert_eq!(frames, screens.len());
+        store.reconstruct(&stream_id.to_string()).unwrap().data
+    }
+
+    #[test]
+    fn gif_frames_decode_to_the_stream() {
+        let data: Vec<u8> = (0..1500u32).map(|i| (i % 241) as u8).collect();
+        let send = SendOptions {
+            version: Some(6),
+            ..SendOptions::default()
+        };
+        assert_eq!(round_trip(&data, &send, AnimationFormat::Gif), data);
+    }
+
+    #[test]
+    fn layered_apng_frames_decode_to_the_stream() {
+        let data: Vec<u8> = (0..1500u32).map(|i| (i % 239) as u8).collect();
+        let send = SendOptions {
+            version: Some(6),
+            layered: true,
+            ..SendOptions::default()
+        };
+        assert_eq!(round_trip(&data, &send, AnimationFormat::Apng), data);
+    }
+
+    #[test]
+    fn refuses_an_empty_animation() {
+        assert!(render_animation(&[], 3, &AnimationOptions::default()).is_err());
+    }
+}
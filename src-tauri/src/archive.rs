@@ -0,0 +1,437 @@
+//! Folder transfers: a tar archive with a manifest, sent as one stream.
+//!
+//! The sender packs the regular files of a folder into a tar, led by a
+//! JSON manifest listing every path with its size and SHA-256. The archive
+//! is an ordinary payload, so it is chunked, compressed and encrypted like
+//! any file; the stream's file metadata names it `<folder>.tar` with the
+//! [`MIME`] type, which is how receivers tell it apart.
+//!
+//! Extraction goes through [`OutputDir`], so archive paths are cleaned and
+//! cannot leave the output folder. Every file is checked against the
+//! manifest before it is written, and the report says per file what
+//! happened. Links, devices and other special entries are skipped.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::metadata::FileMetadata;
+use crate::output::{self, OutputDir, Overwrite, SavedFile};
+
+/// MIME type of folder streams.
+pub const MIME: &str = "application/x-tar";
+/// First entry of every archive.
+pub const MANIFEST_NAME: &str = ".streaming-qr-manifest.json";
+const MANIFEST_VERSION: u32 = 1;
+/// Most entries read from one archive.
+pub const MAX_ENTRIES: usize = 10_000;
+/// Most `name (n)` folders tried before giving up.
+const MAX_SUFFIX: usize = 9999;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub files: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestEntry {
+    /// Relative path with `/` separators.
+    pub path: String,
+    pub size: u64,
+    /// Hex SHA-256 of the contents.
+    pub sha256: String,
+    /// Seconds since the Unix epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileStatus {
+    /// Written, and matches the manifest.
+    Verified,
+    /// Written, but the archive has no manifest entry for it.
+    Unlisted,
+    /// Not written: size or hash differ from the manifest.
+    Mismatch,
+    /// Listed in the manifest but not in the archive.
+    Missing,
+    /// Not a regular file.
+    Skipped,
+    /// Could not be written, see `error`.
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtractedFile {
+    /// Path inside the archive.
+    pub path: String,
+    pub status: FileStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub saved: Option<SavedFile>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ExtractedFile {
+    fn new(path: String, status: FileStatus) -> Self {
+        Self {
+            path,
+            status,
+            saved: None,
+            error: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtractReport {
+    /// Canonical path of the folder extracted into.
+    pub folder: String,
+    /// Whether the archive had a manifest to check against.
+    pub manifest: bool,
+    /// Every file is verified and none is missing.
+    pub verified: bool,
+    pub files: Vec<ExtractedFile>,
+}
+
+/// Stream metadata of the folder at `dir`.
+pub fn metadata(dir: &Path) -> io::Result<FileMetadata> {
+    let mut file = FileMetadata::from_path(dir)?;
+    file.name = file.name.map(|name| format!("{}.tar", name));
+    file.mime = Some(MIME.to_string());
+    file.size = None;
+    Ok(file)
+}
+
+/// Packs the regular files under `dir`, manifest first. Symbolic links are
+/// left out so that nothing outside `dir` is sent.
+pub fn pack(dir: &Path) -> Result<Vec<u8>, String> {
+    let mut paths = Vec::new();
+    collect(dir, "", &mut paths).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+    paths.sort();
+
+    let mut files = Vec::with_capacity(paths.len());
+    let mut entries = Vec::with_capacity(paths.len());
+    for path in paths {
+        let full = dir.join(&path);
+        let read_error = |e: io::Error| format!("Failed to read {}: {}", full.display(), e);
+        let data = fs::read(&full).map_err(read_error)?;
+        let metadata = fs::metadata(&full).map_err(read_error)?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|age| age.as_secs());
+        entries.push(ManifestEntry {
+            path: path.clone(),
+            size: data.len() as u64,
+            sha256: hex::encode(Sha256::digest(&data)),
+            modified,
+        });
+        files.push((path, data, modified));
+    }
+
+    let manifest = Manifest {
+        version: MANIFEST_VERSION,
+        files: entries,
+    };
+    let manifest = serde_json::to_vec_pretty(&manifest).expect("manifests serialize");
+    let mut builder = tar::Builder::new(Vec::new());
+    let mut append = |path: &str, data: &[u8], modified: Option<u64>| {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(modified.unwrap_or(0));
+        builder.append_data(&mut header, path, data)
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|age| age.as_secs())
+        .ok();
+    let packed = append(MANIFEST_NAME, &manifest, now).and_then(|()| {
+        files
+            .iter()
+            .try_for_each(|(path, data, modified)| append(path, data, *modified))
+    });
+    packed
+        .and_then(|()| builder.into_inner())
+        .map_err(|e| format!("Failed to pack {}: {}", dir.display(), e))
+}
+
+/// Adds the relative paths of the regular files under `dir` to `paths`.
+fn collect(dir: &Path, prefix: &str, paths: &mut Vec<String>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let path = format!("{}{}", prefix, name);
+        let kind = entry.file_type()?;
+        if kind.is_dir() {
+            collect(&entry.path(), &format!("{}/", path), paths)?;
+        } else if kind.is_file() && path != MANIFEST_NAME {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Unpacks `data` into `folder` under the output folder. With
+/// [`Overwrite::Suffix`] an existing folder is left alone and the files go
+/// to `folder (1)` and so on; otherwise `overwrite` applies per file.
+pub fn extract(
+    output: &OutputDir,
+    folder: &str,
+    data: &[u8],
+    overwrite: Overwrite,
+) -> Result<ExtractReport, String> {
+    let folder = match overwrite {
+        Overwrite::Suffix => free_folder(output, folder)?,
+        _ => folder.to_string(),
+    };
+    let invalid = |e: io::Error| format!("Invalid archive: {}", e);
+    let manifest = read_manifest(data).map_err(invalid)?;
+    let mut listed: HashMap<&str, &ManifestEntry> = manifest
+        .iter()
+        .flat_map(|manifest| &manifest.files)
+        .map(|entry| (entry.path.as_str(), entry))
+        .collect();
+
+    let mut files = Vec::new();
+    let mut archive = tar::Archive::new(data);
+    for entry in archive.entries().map_err(invalid)?.take(MAX_ENTRIES) {
+        let mut entry = entry.map_err(invalid)?;
+        let path = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+        let kind = entry.header().entry_type();
+        if path == MANIFEST_NAME || kind.is_dir() {
+            continue;
+        }
+        let expected = listed.remove(path.as_str());
+        if !kind.is_file() {
+            files.push(ExtractedFile::new(path, FileStatus::Skipped));
+            continue;
+        }
+        // Checked on its own, since below the folder an absolute path
+        // would just be one more subfolder.
+        if let Err(error) = output::sanitize_path(&path) {
+            files.push(ExtractedFile {
+                error: Some(error),
+                ..ExtractedFile::new(path, FileStatus::Failed)
+            });
+            continue;
+        }
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents).map_err(invalid)?;
+        let sha256 = hex::encode(Sha256::digest(&contents));
+        let status = match expected {
+            None => FileStatus::Unlisted,
+            Some(listed) if listed.size == contents.len() as u64 && listed.sha256 == sha256 => {
+                FileStatus::Verified
+            }
+            Some(_) => {
+                files.push(ExtractedFile::new(path, FileStatus::Mismatch));
+                continue;
+            }
+        };
+        let modified = entry
+            .header()
+            .mtime()
+            .ok()
+            .filter(|&mtime| mtime > 0)
+            .and_then(|mtime| UNIX_EPOCH.checked_add(Duration::from_secs(mtime)));
+        let name = format!("{}/{}", folder, path);
+        files.push(match output.write(&name, &contents, overwrite, modified) {
+            Ok(saved) => ExtractedFile {
+                saved: Some(saved),
+                ..ExtractedFile::new(path, status)
+            },
+            Err(error) => ExtractedFile {
+                error: Some(error),
+                ..ExtractedFile::new(path, FileStatus::Failed)
+            },
+        });
+    }
+    let mut missing: Vec<&str> = listed.into_keys().collect();
+    missing.sort_unstable();
+    files.extend(
+        missing
+            .into_iter()
+            .map(|path| ExtractedFile::new(path.to_string(), FileStatus::Missing)),
+    );
+
+    let folder = output.resolve(&folder)?;
+    fs::create_dir_all(&folder).map_err(|e| format!("Failed to create folder: {}", e))?;
+    Ok(ExtractReport {
+        folder: folder.to_string_lossy().into_owned(),
+        manifest: manifest.is_some(),
+        verified: manifest.is_some()
+            && files.iter().all(|file| file.status == FileStatus::Verified),
+        files,
+    })
+}
+
+/// The manifest entry of the archive, wherever it is, if there is one.
+fn read_manifest(data: &[u8]) -> io::Result<Option<Manifest>> {
+    let mut archive = tar::Archive::new(data);
+    for entry in archive.entries()?.take(MAX_ENTRIES) {
+        let mut entry = entry?;
+        if entry.path_bytes().as_ref() == MANIFEST_NAME.as_bytes() {
+            let mut json = Vec::new();
+            entry.read_to_end(&mut json)?;
+            return serde_json::from_slice(&json)
+                .map(Some)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+        }
+    }
+    Ok(None)
+}
+
+/// `folder`, or the first `folder (n)` not yet in the output folder.
+fn free_folder(output: &OutputDir, folder: &str) -> Result<String, String> {
+    for n in 0..=MAX_SUFFIX {
+        let candidate = match n {
+            0 => folder.to_string(),
+            n => format!("{} ({})", folder, n),
+        };
+        if fs::symlink_metadata(output.resolve(&candidate)?).is_err() {
+            return Ok(candidate);
+        }
+    }
+    Err(format!("Every numbered {} folder is taken", folder))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "streaming-qr-archive-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// A tar of `entries`, written raw so that any path goes in.
+    fn tar_of(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, data) in entries {
+            let mut header = tar::Header::new_old();
+            header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_size(data.len() as u64);
+            header.set_cksum();
+            builder.append(&header, *data).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn entry(path: &str, data: &[u8]) -> ManifestEntry {
+        ManifestEntry {
+            path: path.to_string(),
+            size: data.len() as u64,
+            sha256: hex::encode(Sha256::digest(data)),
+            modified: None,
+        }
+    }
+
+    fn statuses(report: &ExtractReport) -> Vec<(&str, FileStatus)> {
+        report
+            .files
+            .iter()
+            .map(|file| (file.path.as_str(), file.status))
+            .collect()
+    }
+
+    #[test]
+    fn packs_and_extracts_a_folder() {
+        let base = scratch("round-trip");
+        let source = base.join("config");
+        fs::create_dir_all(source.join("nested")).unwrap();
+        fs::write(source.join("a.txt"), b"first").unwrap();
+        fs::write(source.join("nested/b.bin"), [0u8, 1, 2]).unwrap();
+        assert_eq!(
+            metadata(&source).unwrap().name.as_deref(),
+            Some("config.tar")
+        );
+
+        let data = pack(&source).unwrap();
+        let output = OutputDir::new(base.join("out"));
+        let report = extract(&output, "config", &data, Overwrite::Suffix).unwrap();
+        assert!(report.manifest && report.verified);
+        assert_eq!(
+            statuses(&report),
+            [
+                ("a.txt", FileStatus::Verified),
+                ("nested/b.bin", FileStatus::Verified)
+            ]
+        );
+        let folder = Path::new(&report.folder);
+        assert_eq!(fs::read(folder.join("nested/b.bin")).unwrap(), [0, 1, 2]);
+
+        // A second copy goes next to the first.
+        let again = extract(&output, "config", &data, Overwrite::Suffix).unwrap();
+        assert!(again.folder.ends_with("config (1)"));
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn checks_every_file_against_the_manifest() {
+        let base = scratch("manifest");
+        let manifest = Manifest {
+            version: MANIFEST_VERSION,
+            files: vec![
+                entry("good.txt", b"good"),
+                entry("changed.txt", b"original"),
+                entry("gone.txt", b"gone"),
+            ],
+        };
+        let manifest = serde_json::to_vec(&manifest).unwrap();
+        let data = tar_of(&[
+            (MANIFEST_NAME, &manifest),
+            ("good.txt", b"good"),
+            ("changed.txt", b"tampered"),
+            ("extra.txt", b"extra"),
+            ("../escape.txt", b"escape"),
+        ]);
+        let output = OutputDir::new(base.join("out"));
+        let report = extract(&output, "files", &data, Overwrite::Fail).unwrap();
+        assert!(report.manifest && !report.verified);
+        assert_eq!(
+            statuses(&report),
+            [
+                ("good.txt", FileStatus::Verified),
+                ("changed.txt", FileStatus::Mismatch),
+                ("extra.txt", FileStatus::Unlisted),
+                ("../escape.txt", FileStatus::Failed),
+                ("gone.txt", FileStatus::Missing),
+            ]
+        );
+        let folder = Path::new(&report.folder);
+        assert!(!folder.join("changed.txt").exists());
+        assert!(!base.join("out/escape.txt").exists());
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn extracts_archives_without_a_manifest_unverified() {
+        let base = scratch("unlisted");
+        let data = tar_of(&[("plain.txt", b"plain")]);
+        let report = extract(&OutputDir::new(&base), "plain", &data, Overwrite::Fail).unwrap();
+        assert!(!report.manifest && !report.verified);
+        assert_eq!(statuses(&report), [("plain.txt", FileStatus::Unlisted)]);
+        assert!(extract(&OutputDir::new(&base), "bad", b"not a tar", Overwrite::Fail).is_err());
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,77 @@
+//! CRC32 helpers for the per-chunk `checksum` field and SHA-256 helpers for
+//! the stream-level `digest`.
+//!
+//! Senders write the checksum of the decoded chunk bytes either as an
+//! 8-digit hex string (`"1c291ca3"`, optionally `0x`-prefixed) or as a
+//! plain JSON number. Digests are always 64-digit hex strings.
+
+use sha2::{Digest, Sha256};
+
+pub fn crc32(bytes: &[u8]) -> u32 {
+    crc32fast::hash(bytes)
+}
+
+/// Formats a checksum the way `createQRChunk` writes it.
+pub fn format_crc32(crc: u32) -> String {
+    format!("{:08x}", crc)
+}
+
+/// Parses a hex checksum string, returning `None` when it is not a CRC32.
+pub fn parse_crc32(text: &str) -> Option<u32> {
+    let text = text.trim();
+    let hex = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+    if hex.is_empty() || hex.len() > 8 {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok()
+}
+
+pub fn sha256(bytes: &[u8]) -> [u8; 32] {
+    Sha256::digest(bytes).into()
+}
+
+/// Parses a hex SHA-256 digest, returning `None` when it is malformed.
+pub fn parse_sha256(text: &str) -> Option<[u8; 32]> {
+    let mut digest = [0u8; 32];
+    hex::decode_to_slice(text.trim(), &mut digest).ok()?;
+    Some(digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_matches_the_standard_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn parses_crc32_as_written_by_senders() {
+        assert_eq!(format_crc32(0x1c29_1ca3), "1c291ca3");
+        assert_eq!(format_crc32(0xab), "000000ab");
+        assert_eq!(parse_crc32("1c291ca3"), Some(0x1c29_1ca3));
+        assert_eq!(parse_crc32(" 0X1C291CA3 "), Some(0x1c29_1ca3));
+        assert_eq!(parse_crc32("ab"), Some(0xab));
+        assert_eq!(parse_crc32(""), None);
+        assert_eq!(parse_crc32("0x"), None);
+        assert_eq!(parse_crc32("123456789"), None);
+        assert_eq!(parse_crc32("xyz"), None);
+    }
+
+    #[test]
+    fn parses_sha256_digests() {
+        let digest = sha256(b"abc");
+        assert_eq!(
+            hex::encode(digest),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(parse_sha256(&hex::encode(digest)), Some(digest));
+        assert_eq!(parse_sha256(&hex::encode(&digest[..31])), None);
+        assert_eq!(parse_sha256("not hex"), None);
+    }
+}
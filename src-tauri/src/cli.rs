@@ -0,0 +1,896 @@
+//! Headless command line front end, for scripts and CI.
+//!
+//! Runs the same sender, scanner and protocol code as the app:
+//!
+//! ```text
+//! streaming-qr-cli encode <file|dir> --out <dir>             numbered PNG or SVG frames
+//! streaming-qr-cli encode <file> --out <file> --format gif   one animation
+//! streaming-qr-cli decode <frames>... --out <file|dir>
+//! streaming-qr-cli inspect <frames>...
+//! streaming-qr-cli verify <frames>... [--against <file>]
+//! ```
+//!
+//! Frames are image files, animations or folders of them. A folder given
+//! to `encode` is sent as a tar archive with a manifest. A `-` file reads
+//! the input of `encode` from stdin or writes the output of `decode` to
+//! stdout; a folder `--out` for `decode` keeps the name the sender gave.
+//! `decode` writes files atomically and, unless told otherwise, never
+//! replaces one: it fails on an existing `--out` file and numbers the name
+//! inside a folder.
+//! Reports are JSON on stdout (stderr while stdout carries data),
+//! and the exit code tells scripts what went wrong, see [`exit`].
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use serde_json::{json, Value};
+
+use streaming_qr_lib::animation::{self, AnimationFormat, AnimationOptions};
+use streaming_qr_lib::archive;
+use streaming_qr_lib::checksum;
+use streaming_qr_lib::compression::Compression;
+use streaming_qr_lib::encoder::{self, ParityOptions};
+use streaming_qr_lib::keyring::Keyring;
+use streaming_qr_lib::metadata::FileMetadata;
+use streaming_qr_lib::offline::{self, DecodedStream, OfflineReport};
+use streaming_qr_lib::output::{OutputDir, Overwrite};
+use streaming_qr_lib::protocol::{
+    self, Credentials, Frame, Grid, ProtocolError, SignatureStatus, StreamHeader, StreamMode,
+    StreamStore, Verification,
+};
+use streaming_qr_lib::qr::EccLevel;
+use streaming_qr_lib::recipient::ReceiverKey;
+use streaming_qr_lib::sender::{self, SendOptions};
+use streaming_qr_lib::{color, scanner};
+
+/// Process exit codes.
+mod exit {
+    /// Reading or writing a file failed.
+    pub const FAILURE: u8 = 1;
+    /// The command line is wrong.
+    pub const USAGE: u8 = 2;
+    /// Frames are missing: no stream was found, or it is incomplete.
+    pub const INCOMPLETE: u8 = 3;
+    /// A stream is complete but failed or lacks verification, failed
+    /// decryption, or does not match the `--against` file.
+    pub const INVALID: u8 = 4;
+}
+
+const USAGE: &str = "\
+Usage:
+  streaming-qr-cli encode <file|dir> --out <path> [options]
+      --format png|svg|gif|apng   numbered frames in the --out folder, or one
+                                  animation file (default png)
+      --qr-version <1-40>         --ecc L|M|Q|H    --chunk-size <bytes>
+      --compression zstd|deflate|brotli
+      --mode chunks|lt|raptorq    --overhead <percent>   (extra coded frames)
+      --parity <chunks>           --group-size <chunks>  (parity per group)
+      --passphrase <text>         --recipient <receiver key>
+      --grid <columns>x<rows>     --layered        --scale <pixels>
+      --delay <ms>                --loop <plays>   (animations)
+  streaming-qr-cli decode <frames>... --out <file|dir> [options]
+      --overwrite fail|replace|suffix   when the file exists (default fail,
+                                  suffix for a folder --out)
+  streaming-qr-cli verify <frames>... [--against <file>] [options]
+      --passphrase <text>         --keyring <trusted_senders.json>
+      --receiver-key <receiver.key>                 --stream <id>
+  streaming-qr-cli inspect <frames>...
+
+Exit codes: 0 ok, 1 I/O error, 2 usage error, 3 frames missing,
+4 verification failed. verify also fails streams without a digest and,
+with --keyring, streams no trusted sender signed.
+";
+
+#[derive(Debug)]
+struct Failure {
+    code: u8,
+    message: String,
+}
+
+impl Failure {
+    fn new(code: u8, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn usage(message: impl Into<String>) -> Self {
+        Self::new(exit::USAGE, message)
+    }
+}
+
+impl From<&ProtocolError> for Failure {
+    fn from(error: &ProtocolError) -> Self {
+        let code = match error {
+            ProtocolError::Incomplete { .. }
+            | ProtocolError::StreamNotFound { .. }
+            | ProtocolError::HeaderMissing { .. } => exit::INCOMPLETE,
+            _ => exit::INVALID,
+        };
+        Self::new(code, error.to_string())
+    }
+}
+
+/// Options taking a value and flags a command accepts, without `--`.
+struct Spec {
+    options: &'static [&'static str],
+    flags: &'static [&'static str],
+}
+
+const ENCODE: Spec = Spec {
+    options: &[
+        "out",
+        "format",
+        "qr-version",
+        "ecc",
+        "chunk-size",
+        "mode",
+        "overhead",
+        "parity",
+        "group-size",
+        "compression",
+        "passphrase",
+        "recipient",
+        "grid",
+        "scale",
+        "delay",
+        "loop",
+    ],
+    flags: &["layered"],
+};
+const DECODE: Spec = Spec {
+    options: &[
+        "out",
+        "overwrite",
+        "passphrase",
+        "keyring",
+        "receiver-key",
+        "stream",
+    ],
+    flags: &[],
+};
+const VERIFY: Spec = Spec {
+    options: &["against", "passphrase", "keyring", "receiver-key", "stream"],
+    flags: &[],
+};
+const INSPECT: Spec = Spec {
+    options: &[],
+    flags: &[],
+};
+
+struct Args {
+    positional: Vec<String>,
+    options: Vec<(String, String)>,
+    flags: Vec<String>,
+}
+
+impl Args {
+    fn parse(args: &[String], spec: &Spec) -> Result<Self, Failure> {
+        let mut parsed = Args {
+            positional: Vec::new(),
+            options: Vec::new(),
+            flags: Vec::new(),
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                parsed.positional.push(arg.clone());
+                continue;
+            };
+            if name.is_empty() {
+                parsed.positional.extend(args.by_ref().cloned());
+                break;
+            }
+            let (name, inline) = match name.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (name, None),
+            };
+            if spec.flags.contains(&name) && inline.is_none() {
+                parsed.flags.push(name.to_string());
+            } else if spec.options.contains(&name) {
+                let value = inline
+                    .or_else(|| args.next().cloned())
+                    .ok_or_else(|| Failure::usage(format!("--{} needs a value", name)))?;
+                parsed.options.push((name.to_string(), value));
+            } else {
+                return Err(Failure::usage(format!("Unknown option --{}", name)));
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// The last value given for `--name`.
+    fn value(&self, name: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .rev()
+            .find(|(option, _)| option == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn required(&self, name: &str) -> Result<&str, Failure> {
+        self.value(name)
+            .ok_or_else(|| Failure::usage(format!("--{} is required", name)))
+    }
+
+    fn parsed<T: FromStr>(&self, name: &str) -> Result<Option<T>, Failure> {
+        self.value(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| Failure::usage(format!("Invalid --{} {}", name, value)))
+            })
+            .transpose()
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|flag| flag == name)
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("encode") => Args::parse(&args[1..], &ENCODE).and_then(|args| encode(&args)),
+        Some("decode") => Args::parse(&args[1..], &DECODE).and_then(|args| decode(&args)),
+        Some("verify") => Args::parse(&args[1..], &VERIFY).and_then(|args| verify(&args)),
+        Some("inspect") => Args::parse(&args[1..], &INSPECT).and_then(|args| inspect(&args)),
+        Some("help" | "--help" | "-h") => {
+            print!("{}", USAGE);
+            Ok(())
+        }
+        Some(command) => Err(Failure::usage(format!("Unknown command {}", command))),
+        None => Err(Failure::usage("No command given")),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) => {
+            eprintln!("streaming-qr-cli: {}", failure.message);
+            if failure.code == exit::USAGE {
+                eprint!("\n{}", USAGE);
+            }
+            ExitCode::from(failure.code)
+        }
+    }
+}
+
+fn read_input(path: &str) -> Result<Vec<u8>, Failure> {
+    let mut data = Vec::new();
+    let result = match path {
+        "-" => io::stdin().read_to_end(&mut data).map(|_| ()),
+        path => fs::read(path).map(|bytes| data = bytes),
+    };
+    result.map_err(|e| Failure::new(exit::FAILURE, format!("Failed to read {}: {}", path, e)))?;
+    Ok(data)
+}
+
+fn write_output(path: &Path, data: &[u8]) -> Result<(), Failure> {
+    let result = match path.to_str() {
+        Some("-") => io::stdout().write_all(data),
+        _ => fs::write(path, data),
+    };
+    result.map_err(|e| {
+        Failure::new(
+            exit::FAILURE,
+            format!("Failed to write {}: {}", path.display(), e),
+        )
+    })
+}
+
+/// Prints a JSON report on stdout, or on stderr when stdout carries data.
+fn print_report(value: &impl serde::Serialize, to_stderr: bool) {
+    let text = serde_json::to_string_pretty(value).expect("reports serialize");
+    if to_stderr {
+        eprintln!("{}", text);
+    } else {
+        println!("{}", text);
+    }
+}
+
+fn parse_overwrite(value: &str) -> Result<Overwrite, Failure> {
+    match value {
+        "fail" => Ok(Overwrite::Fail),
+        "replace" => Ok(Overwrite::Replace),
+        "suffix" => Ok(Overwrite::Suffix),
+        _ => Err(Failure::usage(format!("Invalid --overwrite {}", value))),
+    }
+}
+
+fn parse_ecc(value: &str) -> Result<EccLevel, Failure> {
+    match value.to_ascii_uppercase().as_str() {
+        "L" => Ok(EccLevel::L),
+        "M" => Ok(EccLevel::M),
+        "Q" => Ok(EccLevel::Q),
+        "H" => Ok(EccLevel::H),
+        _ => Err(Failure::usage(format!("Invalid --ecc {}", value))),
+    }
+}
+
+/// Parses `<columns>x<rows>`, such as `2x2`.
+fn parse_grid(value: &str) -> Result<Grid, Failure> {
+    value
+        .split_once(['x', 'X'])
+        .and_then(|(columns, rows)| {
+            Some(Grid {
+                columns: columns.parse().ok()?,
+                rows: rows.parse().ok()?,
+            })
+        })
+        .ok_or_else(|| Failure::usage(format!("Invalid --grid {}", value)))
+}
+
+fn encode(args: &Args) -> Result<(), Failure> {
+    let [input] = args.positional.as_slice() else {
+        return Err(Failure::usage("encode takes one input file"));
+    };
+    let out = PathBuf::from(args.required("out")?);
+    let format = args.value("format").unwrap_or("png");
+    let animation = match format {
+        "png" | "svg" => None,
+        "gif" => Some(AnimationFormat::Gif),
+        "apng" => Some(AnimationFormat::Apng),
+        _ => return Err(Failure::usage(format!("Invalid --format {}", format))),
+    };
+    let options = SendOptions {
+        version: args.parsed("qr-version")?,
+        ecc: args
+            .value("ecc")
+            .map(parse_ecc)
+            .transpose()?
+            .unwrap_or_default(),
+        chunk_size: args.parsed("chunk-size")?,
+        mode: args
+            .value("mode")
+            .map(|name| {
+                StreamMode::parse(name)
+                    .ok_or_else(|| Failure::usage(format!("Invalid --mode {}", name)))
+            })
+            .transpose()?,
+        repair_overhead: args.parsed("overhead")?,
+        parity: match args.parsed("parity")? {
+            Some(parity) => Some(ParityOptions {
+                group_size: args
+                    .parsed("group-size")?
+                    .unwrap_or(encoder::DEFAULT_GROUP_SIZE),
+                parity,
+            }),
+            None => None,
+        },
+        compression: args
+            .value("compression")
+            .map(|name| {
+                Compression::parse(name)
+                    .ok_or_else(|| Failure::usage(format!("Invalid --compression {}", name)))
+            })
+            .transpose()?,
+        passphrase: args.value("passphrase").map(str::to_string),
+        recipient: args.value("recipient").map(str::to_string),
+        scale: args.parsed("scale")?,
+        grid: args.value("grid").map(parse_grid).transpose()?,
+        layered: args.flag("layered"),
+        file: match input.as_str() {
+            "-" => None,
+            path if Path::new(path).is_dir() => archive::metadata(Path::new(path)).ok(),
+            path => FileMetadata::from_path(Path::new(path)).ok(),
+        },
+        ..SendOptions::default()
+    };
+
+    let data = match Path::new(input) {
+        dir if dir.is_dir() => archive::pack(dir).map_err(|e| Failure::new(exit::FAILURE, e))?,
+        _ => read_input(input)?,
+    };
+    let (stream_id, screens) = sender::stream_screens(&data, &options).map_err(Failure::usage)?;
+    let pictures: Vec<_> = screens.iter().map(sender::Screen::picture).collect();
+    let files = match animation {
+        Some(format) => {
+            let animation = AnimationOptions {
+                format,
+                delay_ms: args.parsed("delay")?,
+                loop_count: args.parsed("loop")?,
+            };
+            let file = animation::render_animation(&pictures, options.scale(), &animation)
+                .map_err(|e| Failure::new(exit::FAILURE, e))?;
+            write_output(&out, &file)?;
+            vec![out]
+        }
+        None => {
+            fs::create_dir_all(&out).map_err(|e| {
+                Failure::new(
+                    exit::FAILURE,
+                    format!("Failed to create {}: {}", out.display(), e),
+                )
+            })?;
+            let mut files = Vec::new();
+            for (index, picture) in pictures.iter().enumerate() {
+                let path = out.join(format!("frame-{:04}.{}", index + 1, format));
+                let file = match format {
+                    "svg" => picture.to_svg().into_bytes(),
+                    _ => picture.to_png(options.scale()),
+                };
+                write_output(&path, &file)?;
+                files.push(path);
+            }
+            files
+        }
+    };
+    print_report(
+        &json!({
+            "streamId": stream_id.to_string(),
+            "size": data.len(),
+            "frames": screens.iter().map(|screen| screen.frames.len()).sum::<usize>(),
+            "files": files,
+        }),
+        false,
+    );
+    Ok(())
+}
+
+/// Decodes every frame file into a fresh store and reconstructs the streams
+/// found, with the credentials given on the command line.
+fn scan_files(args: &Args) -> Result<OfflineReport, Failure> {
+    if args.positional.is_empty() {
+        return Err(Failure::usage("No frame files given"));
+    }
+    let paths: Vec<PathBuf> = args.positional.iter().map(PathBuf::from).collect();
+    if let Some(path) = paths.iter().find(|path| !path.exists()) {
+        return Err(Failure::new(
+            exit::FAILURE,
+            format!("{} does not exist", path.display()),
+        ));
+    }
+    let keyring = args
+        .value("keyring")
+        .map(Keyring::load)
+        .transpose()
+        .map_err(|e| Failure::new(exit::FAILURE, e))?;
+    let receiver = args
+        .value("receiver-key")
+        .map(|path| {
+            let text = read_input(path)?;
+            ReceiverKey::from_hex(&String::from_utf8_lossy(&text))
+                .map_err(|e| Failure::new(exit::FAILURE, e))
+        })
+        .transpose()?;
+    let credentials = Credentials {
+        passphrase: args.value("passphrase"),
+        keyring: keyring.as_ref(),
+        receiver: receiver.as_ref(),
+    };
+    offline::decode_files(&Mutex::new(StreamStore::new()), &paths, credentials)
+        .map_err(|e| Failure::new(exit::FAILURE, e))
+}
+
+/// The `--stream` stream, or the only one found.
+fn pick<'a>(report: &'a OfflineReport, id: Option<&str>) -> Result<&'a DecodedStream, Failure> {
+    match (id, report.streams.as_slice()) {
+        (Some(id), streams) => streams
+            .iter()
+            .find(|stream| stream.stream_id == id)
+            .ok_or_else(|| Failure::new(exit::INCOMPLETE, format!("Stream {} not found", id))),
+        (None, []) => Err(Failure::new(
+            exit::INCOMPLETE,
+            "No stream found in the frames",
+        )),
+        (None, [stream]) => Ok(stream),
+        (None, streams) => Err(Failure::usage(format!(
+            "Several streams found, pick one with --stream: {}",
+            streams
+                .iter()
+                .map(|stream| stream.stream_id.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))),
+    }
+}
+
+/// The payload of a stream, or why it could not be rebuilt.
+fn payload(stream: &DecodedStream) -> Result<&[u8], Failure> {
+    match (&stream.stream, &stream.error) {
+        (Some(reconstructed), _) => Ok(&reconstructed.data),
+        (None, Some(error)) => Err(Failure::from(error)),
+        (None, None) => Err(Failure::new(
+            exit::INCOMPLETE,
+            "Stream could not be rebuilt",
+        )),
+    }
+}
+
+fn decode(args: &Args) -> Result<(), Failure> {
+    let out = PathBuf::from(args.required("out")?);
+    let overwrite = args.value("overwrite").map(parse_overwrite).transpose()?;
+    let mut report = scan_files(args)?;
+    let stream = pick(&report, args.value("stream"))?;
+    let data = payload(stream)?;
+    let file = stream
+        .stream
+        .as_ref()
+        .and_then(|stream| stream.file.as_ref());
+    let saved = if out.to_str() == Some("-") {
+        write_output(&out, data)?;
+        None
+    } else {
+        let (dir, name, default) = if out.is_dir() {
+            let name = file
+                .and_then(|file| file.file_name())
+                .unwrap_or_else(|| format!("stream-{}", stream.stream_id));
+            (out.as_path(), name, Overwrite::Suffix)
+        } else {
+            let name = out.file_name().unwrap_or_default().to_string_lossy();
+            let dir = out.parent().filter(|dir| !dir.as_os_str().is_empty());
+            (
+                dir.unwrap_or(Path::new(".")),
+                name.into_owned(),
+                Overwrite::Fail,
+            )
+        };
+        let modified = file.and_then(|file| file.modified_time());
+        let saved = OutputDir::new(dir)
+            .write(&name, data, overwrite.unwrap_or(default), modified)
+            .map_err(|e| Failure::new(exit::FAILURE, e))?;
+        Some(saved)
+    };
+
+    let stream_id = stream.stream_id.clone();
+    report
+        .streams
+        .retain(|stream| stream.stream_id == stream_id);
+    for stream in &mut report.streams {
+        stream.data = None;
+    }
+    let mut printed = serde_json::to_value(&report.streams[0]).expect("reports serialize");
+    if let Some(saved) = saved {
+        printed["saved"] = json!(saved);
+    }
+    print_report(&printed, out.to_str() == Some("-"));
+    Ok(())
+}
+
+fn verify(args: &Args) -> Result<(), Failure> {
+    let against = args.value("against").map(read_input).transpose()?;
+    let mut report = scan_files(args)?;
+    let trusted_only = args.value("keyring").is_some();
+    let result = check(
+        &report,
+        args.value("stream"),
+        against.as_deref(),
+        trusted_only,
+    );
+    for stream in &mut report.streams {
+        stream.data = None;
+    }
+    print_report(&report, false);
+    result
+}
+
+/// Checks that every stream found, or the `--stream` one, was rebuilt and
+/// verified against its digest, with `trusted_only` that a sender in the
+/// keyring signed it, and with `against` that it is that exact file.
+fn check(
+    report: &OfflineReport,
+    id: Option<&str>,
+    against: Option<&[u8]>,
+    trusted_only: bool,
+) -> Result<(), Failure> {
+    let streams = match (id, against) {
+        (None, None) if !report.streams.is_empty() => report.streams.iter().collect(),
+        _ => vec![pick(report, id)?],
+    };
+    for stream in streams {
+        let data = payload(stream)?;
+        let reconstructed = stream.stream.as_ref().expect("payload was rebuilt");
+        let problem = if reconstructed.verification != Verification::Verified {
+            Some("carries no digest to verify it with")
+        } else if trusted_only && reconstructed.signature != SignatureStatus::SignedByKnown {
+            Some("is not signed by a sender in the keyring")
+        } else {
+            None
+        };
+        if let Some(problem) = problem {
+            return Err(Failure::new(
+                exit::INVALID,
+                format!("Stream {} {}", stream.stream_id, problem),
+            ));
+        }
+        if against.is_some_and(|expected| expected != data) {
+            return Err(Failure::new(
+                exit::INVALID,
+                format!(
+                    "Stream {} does not match the --against file",
+                    stream.stream_id
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn inspect(args: &Args) -> Result<(), Failure> {
+    if args.positional.is_empty() {
+        return Err(Failure::usage("No frame files given"));
+    }
+    let paths: Vec<PathBuf> = args.positional.iter().map(PathBuf::from).collect();
+    let files = offline::collect_files(&paths).map_err(|e| Failure::new(exit::FAILURE, e))?;
+    let mut stdout = io::stdout().lock();
+    // Stops printing once stdout is closed, as when piped into `head`.
+    let mut printed = Ok(());
+    let mut symbols = 0;
+    for path in &files {
+        let bytes = read_input(&path.to_string_lossy())?;
+        let mut number = 0;
+        offline::read_frames(&bytes, |width, height, rgba| {
+            number += 1;
+            for symbol in color::scan(width, height, rgba, 4) {
+                symbols += 1;
+                if printed.is_ok() {
+                    printed = writeln!(stdout, "{}", describe_symbol(path, number, symbol));
+                }
+            }
+        })
+        .map_err(|e| {
+            Failure::new(
+                exit::FAILURE,
+                format!("Failed to decode {}: {}", path.display(), e),
+            )
+        })?;
+        if printed.is_err() {
+            return Ok(());
+        }
+    }
+    if symbols == 0 {
+        return Err(Failure::new(exit::INCOMPLETE, "No QR codes found"));
+    }
+    Ok(())
+}
+
+/// One JSON line per symbol: where it was found and what its frame holds.
+fn describe_symbol(path: &Path, number: usize, symbol: scanner::Symbol) -> Value {
+    let mut value = json!({
+        "file": path,
+        "frame": number,
+        "corners": symbol.corners,
+    });
+    let parsed = symbol
+        .content
+        .map_err(|message| ProtocolError::Parse { message })
+        .and_then(|content| protocol::parse_payload(&content));
+    let details = match parsed {
+        Ok(frame) => describe_frame(&frame),
+        Err(error) => json!({ "error": error }),
+    };
+    if let (Value::Object(map), Value::Object(details)) = (&mut value, details) {
+        map.extend(details.into_iter().filter(|(_, field)| !field.is_null()));
+    }
+    value
+}
+
+fn describe_frame(frame: &Frame) -> Value {
+    let (mut value, header) = match frame {
+        Frame::Data(chunk) => (
+            json!({
+                "type": "data",
+                "streamId": chunk.stream_id,
+                "sequence": chunk.sequence,
+                "total": chunk.total,
+                "size": chunk.data.clone().decode().ok().map(|data| data.len()),
+                "checksum": chunk.checksum.map(checksum::format_crc32),
+                "seed": chunk.seed,
+                "cell": chunk.cell,
+            }),
+            &chunk.header,
+        ),
+        Frame::Header {
+            stream_id,
+            total,
+            header,
+        } => (
+            json!({
+                "type": match (&header.digest, &header.signature) {
+                    (None, Some(_)) => "trailer",
+                    _ => "header",
+                },
+                "streamId": stream_id,
+                "total": total,
+            }),
+            header,
+        ),
+    };
+    if let (Value::Object(map), Value::Object(fields)) = (&mut value, describe_header(header)) {
+        map.extend(fields);
+    }
+    value
+}
+
+/// The stream-level fields a frame carries.
+fn describe_header(header: &StreamHeader) -> Value {
+    json!({
+        "digest": header.digest.map(hex::encode),
+        "mode": header.mode,
+        "length": header.length,
+        "groupSize": header.group_size,
+        "parity": header.parity,
+        "compression": header.compression,
+        "encrypted": header.encryption.is_some().then_some(true),
+        "sealed": header.recipient.is_some().then_some(true),
+        "signed": header.signature.is_some().then_some(true),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use base64::Engine;
+    use streaming_qr_lib::signature;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn code<T>(result: Result<T, Failure>) -> u8 {
+        result.err().expect("expected a failure").code
+    }
+
+    /// Streams "signed" (complete, with digest and signature), "partial"
+    /// (a chunk missing) and "plain" (a JSON chunk without digest).
+    fn store() -> StreamStore {
+        let mut store = StreamStore::new();
+        let signed = encoder::EncodeOptions {
+            signing_key: Some([7; 32]),
+            ..encoder::EncodeOptions::default()
+        };
+        for frame in encoder::encode_stream("signed", b"signed data", &signed).unwrap() {
+            store.insert(frame).unwrap();
+        }
+        let chunked = encoder::EncodeOptions {
+            chunk_size: 10,
+            ..encoder::EncodeOptions::default()
+        };
+        let frames = encoder::encode_stream("partial", &[1; 30], &chunked).unwrap();
+        let lost = |frame: &Frame| matches!(frame, Frame::Data(chunk) if chunk.sequence == 1);
+        for frame in frames.into_iter().filter(|frame| !lost(frame)) {
+            store.insert(frame).unwrap();
+        }
+        let plain = json!({"id": "plain", "seq": 0, "total": 1, "data": BASE64.encode(b"plain")});
+        store.process_chunk(&plain.to_string()).unwrap();
+        store
+    }
+
+    fn report(ids: &[&str], keyring: Option<&Keyring>) -> OfflineReport {
+        let store = store();
+        let credentials = Credentials {
+            keyring,
+            ..Credentials::default()
+        };
+        let streams = ids
+            .iter()
+            .map(|id| {
+                let (stream, error) = match store.reconstruct_with(id, credentials) {
+                    Ok(stream) => (Some(stream), None),
+                    Err(e) => (None, Some(e)),
+                };
+                DecodedStream {
+                    stream_id: id.to_string(),
+                    progress: store.progress(id),
+                    stream,
+                    data: None,
+                    error,
+                }
+            })
+            .collect();
+        OfflineReport {
+            files: Vec::new(),
+            streams,
+        }
+    }
+
+    #[test]
+    fn maps_protocol_errors_to_exit_codes() {
+        let incomplete = [
+            ProtocolError::Incomplete {
+                received: 1,
+                total: 2,
+            },
+            ProtocolError::StreamNotFound {
+                stream_id: "s".to_string(),
+            },
+            ProtocolError::HeaderMissing { field: "digest" },
+        ];
+        for error in &incomplete {
+            assert_eq!(Failure::from(error).code, exit::INCOMPLETE);
+        }
+        for error in [
+            ProtocolError::SignatureInvalid,
+            ProtocolError::DecryptFailed,
+            ProtocolError::Tampered,
+        ] {
+            assert_eq!(Failure::from(&error).code, exit::INVALID);
+        }
+    }
+
+    #[test]
+    fn picks_the_stream_to_report_on() {
+        let report = report(&["signed", "plain"], None);
+        assert_eq!(pick(&report, Some("plain")).unwrap().stream_id, "plain");
+        assert_eq!(code(pick(&report, Some("other"))), exit::INCOMPLETE);
+        assert_eq!(code(pick(&report, None)), exit::USAGE);
+        assert_eq!(code(pick(&self::report(&[], None), None)), exit::INCOMPLETE);
+        let single = self::report(&["signed"], None);
+        assert_eq!(pick(&single, None).unwrap().stream_id, "signed");
+    }
+
+    #[test]
+    fn checks_streams_for_the_verify_exit_code() {
+        let report = report(&["signed", "partial", "plain"], None);
+        assert!(check(&report, Some("signed"), Some(b"signed data"), false).is_ok());
+        assert_eq!(
+            code(check(&report, Some("signed"), Some(b"other"), false)),
+            exit::INVALID
+        );
+        assert_eq!(
+            code(check(&report, Some("partial"), None, false)),
+            exit::INCOMPLETE
+        );
+        assert_eq!(
+            code(check(&report, Some("plain"), None, false)),
+            exit::INVALID
+        );
+        // Every stream is checked when none is named.
+        assert_eq!(code(check(&report, None, None, false)), exit::INCOMPLETE);
+        assert_eq!(
+            code(check(&report, Some("signed"), None, true)),
+            exit::INVALID
+        );
+
+        let dir = std::env::temp_dir().join(format!("streaming-qr-cli-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut keyring = Keyring::load(dir.join("trusted_senders.json")).unwrap();
+        let public_key = signature::public_key(&[7; 32]);
+        keyring.add("sender", &hex::encode(public_key)).unwrap();
+        let trusted = self::report(&["signed"], Some(&keyring));
+        assert!(check(&trusted, None, None, true).is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parses_options_flags_and_positionals() {
+        let parsed = Args::parse(
+            &args(&[
+                "in.txt",
+                "--out",
+                "dir",
+                "--ecc=Q",
+                "--layered",
+                "--",
+                "--raw",
+            ]),
+            &ENCODE,
+        )
+        .unwrap();
+        assert_eq!(parsed.positional, ["in.txt", "--raw"]);
+        assert_eq!(
+            (parsed.value("out"), parsed.value("ecc")),
+            (Some("dir"), Some("Q"))
+        );
+        assert!(parsed.flag("layered"));
+        assert_eq!(code(parsed.parsed::<u8>("ecc")), exit::USAGE);
+        assert_eq!(code(parsed.required("format")), exit::USAGE);
+
+        // Unknown, missing a value, a flag given a value, or a flag of
+        // another command.
+        let bad: [(&[&str], &Spec); 4] = [
+            (&["--unknown"], &ENCODE),
+            (&["--out"], &ENCODE),
+            (&["--layered=yes"], &ENCODE),
+            (&["--layered"], &DECODE),
+        ];
+        for (list, spec) in bad {
+            assert_eq!(code(Args::parse(&args(list), spec)), exit::USAGE);
+        }
+    }
+}
@@ -0,0 +1,407 @@
+//! Colour-layered screens: three QR layers in one image.
+//!
+//! A layered screen carries one symbol (or one grid of symbols) in each of
+//! the red, green and blue channels. A channel is dark wherever its layer
+//! has a dark module, so the image mixes the eight colours from white to
+//! black. All layers share one version, which puts their finder patterns
+//! on the same modules: whatever the data, finder patterns are pure black
+//! and white.
+//!
+//! The decoder locates symbols on the brightest channel of every pixel,
+//! which is only dark where all layers are, measures the black and white
+//! level of each channel on the finder patterns, stretches each channel
+//! between its two levels and scans it as an ordinary grayscale image.
+//! This corrects tinted light and camera white balance; light leaking from
+//! one channel into another is left to the error correction.
+
+use std::fmt::Write as _;
+
+use crate::qr::{Mosaic, QUIET_ZONE};
+use crate::scanner::{self, Location, Symbol};
+
+/// Layers per screen, in channel order: red, green, blue.
+pub const LAYERS: usize = 3;
+/// Mask of a module that is dark in every layer.
+const BLACK: u8 = (1 << LAYERS) - 1;
+/// Least difference between the black and white level of a channel for
+/// it to be scanned at all.
+const MIN_CONTRAST: u8 = 24;
+/// Modules per side of a finder pattern.
+const FINDER: usize = 7;
+
+/// Pixel colour of a module mask: each channel is 0 where its layer is
+/// dark and 255 where it is light.
+pub fn color(mask: u8) -> [u8; 3] {
+    std::array::from_fn(|channel| if mask & (1 << channel) != 0 { 0 } else { 255 })
+}
+
+/// A screen of modules that can differ per channel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColorMosaic {
+    /// Modules per row, without the outer quiet zone.
+    pub width: usize,
+    /// Modules per column, without the outer quiet zone.
+    pub height: usize,
+    /// Row-major; bit `c` is set where layer `c` is dark. Black and white
+    /// screens only use 0 and 7.
+    pub masks: Vec<u8>,
+}
+
+impl From<&Mosaic> for ColorMosaic {
+    fn from(mosaic: &Mosaic) -> Self {
+        Self {
+            width: mosaic.width,
+            height: mosaic.height,
+            masks: mosaic
+                .modules
+                .iter()
+                .map(|&dark| if dark { BLACK } else { 0 })
+                .collect(),
+        }
+    }
+}
+
+impl ColorMosaic {
+    /// Puts `layers` into the red, green and blue channels, in that order.
+    /// With fewer than three layers the first ones are repeated, so that
+    /// finder patterns stay black in every channel.
+    pub fn layered(layers: &[Mosaic]) -> Self {
+        let width = layers.iter().map(|layer| layer.width).max().unwrap_or(0);
+        let height = layers.iter().map(|layer| layer.height).max().unwrap_or(0);
+        let mut masks = vec![0u8; width * height];
+        if !layers.is_empty() {
+            for channel in 0..LAYERS {
+                let layer = &layers[channel % layers.len()];
+                for y in 0..layer.height {
+                    for x in 0..layer.width {
+                        if layer.is_dark(x, y) {
+                            masks[y * width + x] |= 1 << channel;
+                        }
+                    }
+                }
+            }
+        }
+        Self {
+            width,
+            height,
+            masks,
+        }
+    }
+
+    /// Whether every module is black or white.
+    pub fn is_gray(&self) -> bool {
+        self.masks.iter().all(|&mask| mask == 0 || mask == BLACK)
+    }
+
+    pub fn rows(&self) -> Vec<Vec<u8>> {
+        self.masks.chunks(self.width).map(<[u8]>::to_vec).collect()
+    }
+
+    /// Width and height in pixels when rendered at `scale` with the quiet
+    /// zone.
+    pub fn image_size(&self, scale: usize) -> (usize, usize) {
+        (
+            (self.width + 2 * QUIET_ZONE) * scale,
+            (self.height + 2 * QUIET_ZONE) * scale,
+        )
+    }
+
+    /// The module mask of every pixel, quiet zone included.
+    pub fn to_pixel_masks(&self, scale: usize) -> Vec<u8> {
+        let (image_width, image_height) = self.image_size(scale);
+        let mut pixels = vec![0u8; image_width * image_height];
+        for (y, row) in self.masks.chunks(self.width).enumerate() {
+            for (x, &mask) in row.iter().enumerate() {
+                for line in 0..scale {
+                    let start =
+                        ((y + QUIET_ZONE) * scale + line) * image_width + (x + QUIET_ZONE) * scale;
+                    pixels[start..start + scale].fill(mask);
+                }
+            }
+        }
+        pixels
+    }
+
+    /// 8-bit RGB pixels, quiet zone included.
+    pub fn to_rgb(&self, scale: usize) -> Vec<u8> {
+        self.to_pixel_masks(scale)
+            .into_iter()
+            .flat_map(color)
+            .collect()
+    }
+
+    /// Grayscale PNG for black and white screens, RGB otherwise.
+    pub fn to_png(&self, scale: usize) -> Vec<u8> {
+        let (width, height) = self.image_size(scale);
+        let (color_type, pixels) = if self.is_gray() {
+            let luma = self
+                .to_pixel_masks(scale)
+                .into_iter()
+                .map(|mask| color(mask)[0]);
+            (png::ColorType::Grayscale, luma.collect())
+        } else {
+            (png::ColorType::Rgb, self.to_rgb(scale))
+        };
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, width as u32, height as u32);
+        encoder.set_color(color_type);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&pixels))
+            .expect("writing a PNG into memory cannot fail");
+        png
+    }
+
+    /// SVG with one unit per module and one path per colour.
+    pub fn to_svg(&self) -> String {
+        let (width, height) = self.image_size(1);
+        let mut paths = String::new();
+        for mask in 1..=BLACK {
+            let mut path = String::new();
+            for (index, _) in self.masks.iter().enumerate().filter(|(_, &m)| m == mask) {
+                let (x, y) = (index % self.width, index / self.width);
+                let _ = write!(path, "M{} {}h1v1h-1z", x + QUIET_ZONE, y + QUIET_ZONE);
+            }
+            if !path.is_empty() {
+                let [r, g, b] = color(mask);
+                let _ = write!(
+                    paths,
+                    r##"<path fill="#{:02x}{:02x}{:02x}" d="{}"/>"##,
+                    r, g, b, path
+                );
+            }
+        }
+        format!(
+            concat!(
+                r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {width} {height}" "#,
+                r##"shape-rendering="crispEdges"><rect width="{width}" height="{height}" fill="#fff"/>"##,
+                "{paths}</svg>"
+            ),
+            width = width,
+            height = height,
+            paths = paths
+        )
+    }
+}
+
+/// Decodes every layer of an image with `channels` bytes per pixel (3 for
+/// RGB, 4 for RGBA), stored row by row.
+///
+/// Black and white images are scanned once. Otherwise each channel is
+/// calibrated and scanned on its own; a symbol read from several channels,
+/// like a header shown in black and white, is returned once.
+pub fn scan(width: usize, height: usize, pixels: &[u8], channels: usize) -> Vec<Symbol> {
+    let len = width * height;
+    if !(3..=4).contains(&channels) || len == 0 || pixels.len() < len * channels {
+        return Vec::new();
+    }
+    let mut planes = split(&pixels[..len * channels], channels);
+    if planes[0] == planes[1] && planes[1] == planes[2] {
+        return scanner::scan(width, height, &planes[0]);
+    }
+
+    let brightest: Vec<u8> = (0..len)
+        .map(|i| planes.iter().map(|plane| plane[i]).max().unwrap_or(0))
+        .collect();
+    let locations = scanner::locate(width, height, &brightest);
+    let Some(levels) = calibrate(width, height, &planes, &locations) else {
+        return Vec::new();
+    };
+
+    let mut symbols: Vec<Symbol> = Vec::new();
+    for (plane, (black, white)) in planes.iter_mut().zip(levels) {
+        if white.saturating_sub(black) < MIN_CONTRAST {
+            continue;
+        }
+        stretch(plane, black, white);
+        for symbol in scanner::scan(width, height, plane) {
+            let seen = symbol.content.is_ok()
+                && symbols.iter().any(|known| known.content == symbol.content);
+            if !seen {
+                symbols.push(symbol);
+            }
+        }
+    }
+    symbols
+}
+
+/// Splits interleaved pixels into red, green and blue planes. Alpha is
+/// flattened onto white, so transparent pixels read as quiet zone.
+fn split(pixels: &[u8], channels: usize) -> [Vec<u8>; LAYERS] {
+    std::array::from_fn(|channel| {
+        pixels
+            .chunks_exact(channels)
+            .map(|pixel| {
+                let value = u32::from(pixel[channel]);
+                let alpha = pixel.get(3).map_or(255, |&alpha| u32::from(alpha));
+                ((value * alpha + 255 * (255 - alpha)) / 255) as u8
+            })
+            .collect()
+    })
+}
+
+/// Black and white level of each channel: the medians over the dark core
+/// and the light ring of the finder patterns of every located symbol.
+fn calibrate(
+    width: usize,
+    height: usize,
+    planes: &[Vec<u8>; LAYERS],
+    locations: &[Location],
+) -> Option<[(u8, u8); LAYERS]> {
+    let mut dark: [Vec<u8>; LAYERS] = Default::default();
+    let mut light: [Vec<u8>; LAYERS] = Default::default();
+    for location in locations.iter().filter(|location| location.size >= FINDER) {
+        let far = location.size - FINDER;
+        for (left, top) in [(0, 0), (far, 0), (0, far)] {
+            for y in 0..FINDER {
+                for x in 0..FINDER {
+                    // Rings around the centre: 0 and 1 form the dark core,
+                    // 2 is light and 3 is the dark border.
+                    let ring = x.abs_diff(FINDER / 2).max(y.abs_diff(FINDER / 2));
+                    let samples = match ring {
+                        0 | 1 => &mut dark,
+                        2 => &mut light,
+                        _ => continue,
+                    };
+                    let Some(index) = module_centre(location, left + x, top + y, width, height)
+                    else {
+                        continue;
+                    };
+                    for (channel, plane) in planes.iter().enumerate() {
+                        samples[channel].push(plane[index]);
+                    }
+                }
+            }
+        }
+    }
+    if dark[0].is_empty() || light[0].is_empty() {
+        return None;
+    }
+    Some(std::array::from_fn(|channel| {
+        (median(&mut dark[channel]), median(&mut light[channel]))
+    }))
+}
+
+/// Pixel index of the centre of module (`x`, `y`) of a located symbol,
+/// interpolated between its corners.
+fn module_centre(
+    location: &Location,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+) -> Option<usize> {
+    let span = (location.size + 1) as f64;
+    let (u, v) = ((x as f64 + 0.5) / span, (y as f64 + 0.5) / span);
+    let weights = [(1.0 - u) * (1.0 - v), u * (1.0 - v), u * v, (1.0 - u) * v];
+    let (mut px, mut py) = (0.0, 0.0);
+    for (weight, [cx, cy]) in weights.iter().zip(location.corners) {
+        px += weight * cx as f64;
+        py += weight * cy as f64;
+    }
+    let (px, py) = (px.round(), py.round());
+    if px < 0.0 || py < 0.0 || px >= width as f64 || py >= height as f64 {
+        return None;
+    }
+    Some(py as usize * width + px as usize)
+}
+
+fn median(samples: &mut [u8]) -> u8 {
+    samples.sort_unstable();
+    samples[samples.len() / 2]
+}
+
+/// Maps `black` to 0 and `white` to 255, clamping beyond them.
+fn stretch(plane: &mut [u8], black: u8, white: u8) {
+    let range = u32::from(white - black);
+    for value in plane.iter_mut() {
+        let offset = u32::from(value.saturating_sub(black));
+        *value = (offset * 255 / range).min(255) as u8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qr::{EccLevel, QrSymbol};
+
+    const SCALE: usize = 4;
+
+    fn payloads() -> Vec<Vec<u8>> {
+        ["red layer", "green layer", "blue layer"]
+            .iter()
+            .map(|name| name.repeat(4).into_bytes())
+            .collect()
+    }
+
+    fn layered(payloads: &[Vec<u8>]) -> ColorMosaic {
+        let layers: Vec<Mosaic> = payloads
+            .iter()
+            .map(|data| Mosaic::from(&QrSymbol::encode(data, 4, EccLevel::M).unwrap()))
+            .collect();
+        ColorMosaic::layered(&layers)
+    }
+
+    fn contents(symbols: Vec<Symbol>) -> Vec<Vec<u8>> {
+        let mut contents: Vec<Vec<u8>> = symbols
+            .into_iter()
+            .map(|symbol| symbol.content.unwrap())
+            .collect();
+        contents.sort();
+        contents
+    }
+
+    #[test]
+    fn recovers_every_layer() {
+        let payloads = payloads();
+        let mosaic = layered(&payloads);
+        assert!(!mosaic.is_gray());
+        let (width, height) = mosaic.image_size(SCALE);
+        let mut expected = payloads.clone();
+        expected.sort();
+        assert_eq!(
+            contents(scan(width, height, &mosaic.to_rgb(SCALE), 3)),
+            expected
+        );
+    }
+
+    #[test]
+    fn calibrates_tinted_channels() {
+        let payloads = payloads();
+        let mosaic = layered(&payloads);
+        let (width, height) = mosaic.image_size(SCALE);
+        // A warm cast with lifted blacks, as a camera might see the screen,
+        // and an opaque alpha channel.
+        let ranges = [(60, 250), (40, 200), (20, 150)];
+        let rgba: Vec<u8> = mosaic
+            .to_rgb(SCALE)
+            .chunks_exact(3)
+            .flat_map(|pixel| {
+                let mut tinted = [255; 4];
+                for (channel, (black, white)) in ranges.iter().enumerate() {
+                    tinted[channel] = if pixel[channel] == 0 { *black } else { *white };
+                }
+                tinted
+            })
+            .collect();
+        let mut expected = payloads.clone();
+        expected.sort();
+        assert_eq!(contents(scan(width, height, &rgba, 4)), expected);
+    }
+
+    #[test]
+    fn scans_black_and_white_images_once() {
+        let data = b"gray".to_vec();
+        let mosaic = ColorMosaic::from(&Mosaic::from(
+            &QrSymbol::encode(&data, 2, EccLevel::M).unwrap(),
+        ));
+        assert!(mosaic.is_gray());
+        let (width, height) = mosaic.image_size(SCALE);
+        assert_eq!(
+            contents(scan(width, height, &mosaic.to_rgb(SCALE), 3)),
+            [data]
+        );
+        assert!(scan(width, height, &mosaic.to_rgb(SCALE), 2).is_empty());
+    }
+}
@@ -0,0 +1,530 @@
+//! Tauri commands and the app entry point, built with the `app` feature.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use tauri::ipc::{InvokeBody, Request, Response};
+use tauri::{Manager, State};
+
+use crate::analysis::DataReport;
+use crate::animation::AnimationOptions;
+use crate::archive::ExtractReport;
+use crate::journal::{Journal, StoredStream};
+use crate::keyring::{Keyring, TrustedSender};
+use crate::metadata::FileMetadata;
+use crate::offline::OfflineReport;
+use crate::output::{OutputDir, Overwrite, SavedFile};
+use crate::protocol::{
+    ChunkOutcome, Credentials, Progress, ProtocolError, StreamStore, StreamSummary,
+};
+use crate::qr::{EccLevel, QrSymbol};
+use crate::recipient::ReceiverKey;
+use crate::scanner::{ScannedSymbol, Symbol};
+use crate::sender::{Screen, SendOptions, SentStream};
+use crate::{
+    analysis, animation, archive, color, journal, keyring, offline, output, qr, recipient, scanner,
+    sender,
+};
+
+// Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
+#[tauri::command]
+fn greet(name: &str) -> String {
+    format!("Hello, {}! You've been greeted from Rust!", name)
+}
+
+fn default_file_name(extension: &str) -> String {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    format!("decoded_stream_{}.{}", timestamp, extension)
+}
+
+/// Saves `data` byte for byte under `filename`, or else the original name
+/// from `file`, dated with the original modification time.
+#[tauri::command]
+fn save_decoded_data(
+    output: State<'_, Mutex<OutputDir>>,
+    data: Vec<u8>,
+    filename: Option<String>,
+    overwrite: Option<Overwrite>,
+    file: Option<FileMetadata>,
+) -> Result<SavedFile, String> {
+    let file = file.unwrap_or_default();
+    let file_name = filename
+        .or_else(|| file.file_name())
+        .unwrap_or_else(|| default_file_name("bin"));
+    let overwrite = overwrite.unwrap_or_default();
+    output
+        .lock()
+        .unwrap()
+        .write(&file_name, &data, overwrite, file.modified_time())
+}
+
+/// Sniffs the content type, encoding and entropy of the raw request body,
+/// and checks that JSON, XML and CSV parse. A JSON body with a `data`
+/// string is analyzed as that text.
+#[tauri::command]
+fn validate_data(request: Request<'_>) -> Result<DataReport, String> {
+    match request.body() {
+        InvokeBody::Raw(bytes) => Ok(analysis::analyze(bytes)),
+        InvokeBody::Json(body) => body
+            .get("data")
+            .and_then(|data| data.as_str())
+            .map(|data| analysis::analyze(data.as_bytes()))
+            .ok_or_else(|| "Expected raw bytes or a data string".to_string()),
+    }
+}
+
+#[tauri::command]
+fn process_chunk(
+    store: State<'_, Mutex<StreamStore>>,
+    qr_string: &str,
+) -> Result<ChunkOutcome, ProtocolError> {
+    store.lock().unwrap().process_chunk(qr_string)
+}
+
+/// Takes a binary frame as the raw request body (a `Uint8Array` in JS).
+#[tauri::command]
+fn process_frame(
+    store: State<'_, Mutex<StreamStore>>,
+    request: Request<'_>,
+) -> Result<ChunkOutcome, ProtocolError> {
+    let InvokeBody::Raw(bytes) = request.body() else {
+        return Err(ProtocolError::InvalidFormat);
+    };
+    store.lock().unwrap().process_payload(bytes)
+}
+
+/// Width and height of a raw frame from the `X-Frame-Width` and
+/// `X-Frame-Height` headers, with the body holding `width × height` pixels
+/// of `bytes_per_pixel` bytes each.
+fn frame_size(request: &Request<'_>, len: usize, bytes_per_pixel: usize) -> Option<(usize, usize)> {
+    let dimension = |name: &str| {
+        request
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<usize>().ok())
+    };
+    let width = dimension(scanner::WIDTH_HEADER)?;
+    let height = dimension(scanner::HEIGHT_HEADER)?;
+    let pixels = width.checked_mul(height)?;
+    (pixels.checked_mul(bytes_per_pixel) == Some(len)).then_some((width, height))
+}
+
+/// Feeds the content of every decoded symbol to the store.
+fn insert_symbols(store: &Mutex<StreamStore>, symbols: Vec<Symbol>) -> Vec<ScannedSymbol> {
+    let mut store = store.lock().unwrap();
+    symbols
+        .into_iter()
+        .map(|symbol| {
+            let (payload, result) = match symbol.content {
+                Ok(content) => (
+                    Some(BASE64.encode(&content)),
+                    store.process_payload(&content),
+                ),
+                Err(message) => (None, Err(ProtocolError::Parse { message })),
+            };
+            let (outcome, error) = match result {
+                Ok(outcome) => (Some(outcome), None),
+                Err(e) => (None, Some(e)),
+            };
+            ScannedSymbol {
+                corners: symbol.corners,
+                payload,
+                outcome,
+                error,
+            }
+        })
+        .collect()
+}
+
+/// Decodes every QR code in a raw 8-bit grayscale camera frame (the request
+/// body, row by row) and feeds them to the store. The frame size comes in
+/// the `X-Frame-Width` and `X-Frame-Height` headers.
+#[tauri::command]
+async fn scan_frame(
+    store: State<'_, Mutex<StreamStore>>,
+    request: Request<'_>,
+) -> Result<Vec<ScannedSymbol>, ProtocolError> {
+    let InvokeBody::Raw(luma) = request.body() else {
+        return Err(ProtocolError::InvalidFormat);
+    };
+    let (width, height) =
+        frame_size(&request, luma.len(), 1).ok_or(ProtocolError::InvalidFormat)?;
+    Ok(insert_symbols(&store, scanner::scan(width, height, luma)))
+}
+
+/// Like `scan_frame` for an RGBA (or RGB) camera frame, separating the
+/// red, green and blue layers of layered screens.
+#[tauri::command]
+async fn scan_color_frame(
+    store: State<'_, Mutex<StreamStore>>,
+    request: Request<'_>,
+) -> Result<Vec<ScannedSymbol>, ProtocolError> {
+    let InvokeBody::Raw(pixels) = request.body() else {
+        return Err(ProtocolError::InvalidFormat);
+    };
+    let (channels, (width, height)) = [4, 3]
+        .into_iter()
+        .find_map(|channels| Some((channels, frame_size(&request, pixels.len(), channels)?)))
+        .ok_or(ProtocolError::InvalidFormat)?;
+    Ok(insert_symbols(&store, color::scan(width, height, pixels, channels)))
+}
+
+#[tauri::command]
+fn get_progress(store: State<'_, Mutex<StreamStore>>, stream_id: &str) -> Option<Progress> {
+    store.lock().unwrap().progress(stream_id)
+}
+
+#[tauri::command]
+fn get_missing_chunks(store: State<'_, Mutex<StreamStore>>, stream_id: &str) -> Vec<u32> {
+    store.lock().unwrap().missing_chunks(stream_id)
+}
+
+/// Rebuilds a stream once and returns it as raw bytes (an `ArrayBuffer` in
+/// JS): a little-endian `u32` length, the `ReconstructedStream` JSON of
+/// that length, then the payload.
+#[tauri::command]
+fn reconstruct_stream(
+    store: State<'_, Mutex<StreamStore>>,
+    keyring: State<'_, Mutex<Keyring>>,
+    receiver: State<'_, ReceiverKey>,
+    stream_id: &str,
+    passphrase: Option<String>,
+) -> Result<Response, ProtocolError> {
+    let keyring = keyring.lock().unwrap();
+    let credentials = Credentials {
+        passphrase: passphrase.as_deref(),
+        keyring: Some(&keyring),
+        receiver: Some(&receiver),
+    };
+    let stream = store
+        .lock()
+        .unwrap()
+        .reconstruct_with(stream_id, credentials)?;
+    let metadata = serde_json::to_vec(&stream).expect("stream metadata serializes");
+    let mut body = Vec::with_capacity(4 + metadata.len() + stream.data.len());
+    body.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+    body.extend_from_slice(&metadata);
+    body.extend_from_slice(&stream.data);
+    Ok(Response::new(body))
+}
+
+/// Writes the reconstructed payload to the output folder byte for byte,
+/// by default under the name and modification time the sender gave.
+#[tauri::command]
+fn save_stream(
+    store: State<'_, Mutex<StreamStore>>,
+    receiver: State<'_, ReceiverKey>,
+    output: State<'_, Mutex<OutputDir>>,
+    stream_id: &str,
+    filename: Option<String>,
+    passphrase: Option<String>,
+    overwrite: Option<Overwrite>,
+) -> Result<SavedFile, String> {
+    let credentials = Credentials {
+        passphrase: passphrase.as_deref(),
+        receiver: Some(&receiver),
+        ..Credentials::default()
+    };
+    let stream = store
+        .lock()
+        .unwrap()
+        .reconstruct_with(stream_id, credentials)
+        .map_err(|e| e.to_string())?;
+    let file = stream.file.unwrap_or_default();
+    let file_name = filename
+        .or_else(|| file.file_name())
+        .unwrap_or_else(|| default_file_name("bin"));
+    let overwrite = overwrite.unwrap_or_default();
+    let saved =
+        output
+            .lock()
+            .unwrap()
+            .write(&file_name, &stream.data, overwrite, file.modified_time())?;
+    store
+        .lock()
+        .unwrap()
+        .settle(stream_id)
+        .map_err(|e| format!("Saved {}, but: {}", saved.path, e))?;
+    Ok(saved)
+}
+
+/// Unpacks a folder stream into `folder` under the output folder, by
+/// default named after the folder sent, and checks every file against the
+/// archive manifest.
+#[tauri::command]
+fn extract_stream(
+    store: State<'_, Mutex<StreamStore>>,
+    receiver: State<'_, ReceiverKey>,
+    output: State<'_, Mutex<OutputDir>>,
+    stream_id: &str,
+    folder: Option<String>,
+    passphrase: Option<String>,
+    overwrite: Option<Overwrite>,
+) -> Result<ExtractReport, String> {
+    let credentials = Credentials {
+        passphrase: passphrase.as_deref(),
+        receiver: Some(&receiver),
+        ..Credentials::default()
+    };
+    let stream = store
+        .lock()
+        .unwrap()
+        .reconstruct_with(stream_id, credentials)
+        .map_err(|e| e.to_string())?;
+    let folder = folder
+        .or_else(|| {
+            let name = stream.file.as_ref()?.file_name()?;
+            Some(name.strip_suffix(".tar").unwrap_or(&name).to_string())
+        })
+        .unwrap_or_else(|| format!("stream_{}", stream_id));
+    let overwrite = overwrite.unwrap_or_default();
+    let report = archive::extract(&output.lock().unwrap(), &folder, &stream.data, overwrite)?;
+    store
+        .lock()
+        .unwrap()
+        .settle(stream_id)
+        .map_err(|e| format!("Extracted to {}, but: {}", report.folder, e))?;
+    Ok(report)
+}
+
+#[tauri::command]
+fn get_active_streams(store: State<'_, Mutex<StreamStore>>) -> Vec<StreamSummary> {
+    store.lock().unwrap().active_streams()
+}
+
+#[tauri::command]
+fn clear_stream(store: State<'_, Mutex<StreamStore>>, stream_id: &str) {
+    store.lock().unwrap().clear_stream(stream_id);
+}
+
+#[tauri::command]
+fn clear_all_streams(store: State<'_, Mutex<StreamStore>>) {
+    store.lock().unwrap().clear_all();
+}
+
+#[tauri::command]
+fn get_output_dir(output: State<'_, Mutex<OutputDir>>) -> String {
+    output.lock().unwrap().dir().to_string_lossy().into_owned()
+}
+
+/// Changes the folder saves go to; `None` restores the default.
+#[tauri::command]
+fn set_output_dir(
+    output: State<'_, Mutex<OutputDir>>,
+    path: Option<String>,
+) -> Result<String, String> {
+    let mut output = output.lock().unwrap();
+    let dir = output.set(path.as_deref())?;
+    Ok(dir.to_string_lossy().into_owned())
+}
+
+/// Streams kept in the journal, including ones restored at startup.
+#[tauri::command]
+fn list_stored_streams(store: State<'_, Mutex<StreamStore>>) -> Vec<StoredStream> {
+    store.lock().unwrap().stored_streams()
+}
+
+/// Reloads a stream from its journal, e.g. after it was cleared.
+#[tauri::command]
+fn resume_stored_stream(
+    store: State<'_, Mutex<StreamStore>>,
+    stream_id: &str,
+) -> Result<Progress, ProtocolError> {
+    store
+        .lock()
+        .unwrap()
+        .resume_stored(stream_id)
+        .ok_or_else(|| ProtocolError::StreamNotFound {
+            stream_id: stream_id.to_string(),
+        })
+}
+
+/// Deletes a stream from the journal and from memory.
+#[tauri::command]
+fn discard_stored_stream(
+    store: State<'_, Mutex<StreamStore>>,
+    stream_id: &str,
+) -> Result<bool, String> {
+    store.lock().unwrap().discard_stored(stream_id)
+}
+
+#[tauri::command]
+fn list_trusted_senders(keyring: State<'_, Mutex<Keyring>>) -> Vec<TrustedSender> {
+    keyring.lock().unwrap().list().to_vec()
+}
+
+/// Trusts an Ed25519 public key (hex or base64) under `name`.
+#[tauri::command]
+fn add_trusted_sender(
+    keyring: State<'_, Mutex<Keyring>>,
+    name: &str,
+    public_key: &str,
+) -> Result<TrustedSender, String> {
+    keyring.lock().unwrap().add(name, public_key)
+}
+
+/// Returns whether the key was trusted before.
+#[tauri::command]
+fn remove_trusted_sender(
+    keyring: State<'_, Mutex<Keyring>>,
+    public_key: &str,
+) -> Result<bool, String> {
+    keyring.lock().unwrap().remove(public_key)
+}
+
+/// This device's X25519 public key, for senders to seal streams to, plus
+/// the text and SVG of the QR code that shares it.
+#[tauri::command]
+fn get_receiver_key(receiver: State<'_, ReceiverKey>) -> Result<serde_json::Value, String> {
+    let share_text = receiver.share_text();
+    let qr_svg = QrSymbol::encode_fitting(share_text.as_bytes(), qr::MIN_VERSION, EccLevel::M)?
+        .to_svg();
+
+    Ok(serde_json::json!({
+        "public_key": hex::encode(receiver.public_key()),
+        "share_text": share_text,
+        "qr_svg": qr_svg
+    }))
+}
+
+/// Scans image files, animations and folders of them (`paths`) for stream
+/// frames and reconstructs every stream found. Frames land in the same
+/// store as scanned ones, so incomplete streams can be finished by camera.
+#[tauri::command]
+async fn decode_files(
+    store: State<'_, Mutex<StreamStore>>,
+    keyring: State<'_, Mutex<Keyring>>,
+    receiver: State<'_, ReceiverKey>,
+    paths: Vec<String>,
+    passphrase: Option<String>,
+) -> Result<OfflineReport, String> {
+    let paths: Vec<PathBuf> = paths.into_iter().map(PathBuf::from).collect();
+    let keyring = keyring.lock().unwrap();
+    let credentials = Credentials {
+        passphrase: passphrase.as_deref(),
+        keyring: Some(&keyring),
+        receiver: Some(&receiver),
+    };
+    offline::decode_files(&store, &paths, credentials)
+}
+
+/// The bytes to send: a file, a folder packed by [`archive::pack`], or
+/// `data` as given.
+fn read_input(path: Option<String>, data: Option<Vec<u8>>) -> Result<Vec<u8>, String> {
+    match (path, data) {
+        (Some(path), None) if Path::new(&path).is_dir() => archive::pack(Path::new(&path)),
+        (Some(path), None) => {
+            fs::read(&path).map_err(|e| format!("Failed to read {}: {}", path, e))
+        }
+        (None, Some(data)) => Ok(data),
+        _ => Err("Pass either a path or data".to_string()),
+    }
+}
+
+/// `options` with the file metadata of `path` unless the caller gave some.
+fn with_file_metadata(options: Option<SendOptions>, path: Option<&str>) -> SendOptions {
+    let mut options = options.unwrap_or_default();
+    if let (None, Some(path)) = (&options.file, path.map(Path::new)) {
+        options.file = match path.is_dir() {
+            true => archive::metadata(path).ok(),
+            false => FileMetadata::from_path(path).ok(),
+        };
+    }
+    options
+}
+
+/// Chunks a file (`path`) or byte buffer (`data`) into a stream and renders
+/// every frame as a QR code, in the order they should be shown.
+#[tauri::command]
+fn create_stream_frames(
+    path: Option<String>,
+    data: Option<Vec<u8>>,
+    options: Option<SendOptions>,
+) -> Result<SentStream, String> {
+    let options = with_file_metadata(options, path.as_deref());
+    let data = read_input(path, data)?;
+    sender::send(&data, &options)
+}
+
+/// Renders all frames of a stream into one animated GIF or APNG, returned
+/// as raw bytes. `options.format` is ignored.
+#[tauri::command]
+fn create_stream_animation(
+    path: Option<String>,
+    data: Option<Vec<u8>>,
+    options: Option<SendOptions>,
+    animation: Option<AnimationOptions>,
+) -> Result<Response, String> {
+    let options = with_file_metadata(options, path.as_deref());
+    let data = read_input(path, data)?;
+    let (_, screens) = sender::stream_screens(&data, &options)?;
+    let pictures: Vec<_> = screens.iter().map(Screen::picture).collect();
+    let animation = animation.unwrap_or_default();
+    let file = animation::render_animation(&pictures, options.scale(), &animation)?;
+    Ok(Response::new(file))
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+    tauri::Builder::default()
+        .plugin(tauri_plugin_opener::init())
+        .setup(|app| {
+            let data_dir = app.path().app_data_dir()?;
+            let journal = Journal::new(data_dir.join(journal::DIR_NAME));
+            let (store, errors) = StreamStore::with_journal(journal);
+            for error in errors {
+                eprintln!("{}", error);
+            }
+            app.manage(Mutex::new(store));
+            let keyring = Keyring::load(data_dir.join(keyring::FILE_NAME))?;
+            app.manage(Mutex::new(keyring));
+            let receiver = ReceiverKey::load_or_generate(&data_dir.join(recipient::FILE_NAME))?;
+            app.manage(receiver);
+            let downloads = app
+                .path()
+                .download_dir()
+                .unwrap_or_else(|_| data_dir.join(output::DEFAULT_DIR_NAME));
+            let output = OutputDir::load(data_dir.join(output::FILE_NAME), downloads)?;
+            app.manage(Mutex::new(output));
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            greet,
+            save_decoded_data,
+            validate_data,
+            process_chunk,
+            process_frame,
+            scan_frame,
+            scan_color_frame,
+            get_progress,
+            get_missing_chunks,
+            reconstruct_stream,
+            save_stream,
+            extract_stream,
+            get_active_streams,
+            clear_stream,
+            clear_all_streams,
+            get_output_dir,
+            set_output_dir,
+            list_stored_streams,
+            resume_stored_stream,
+            discard_stored_stream,
+            list_trusted_senders,
+            add_trusted_sender,
+            remove_trusted_sender,
+            get_receiver_key,
+            create_stream_frames,
+            create_stream_animation,
+            decode_files
+        ])
+        .run(tauri::generate_context!())
+        .expect("error while running tauri application");
+}
@@ -0,0 +1,162 @@
+//! Whole-payload compression applied by the sender before chunking.
+//!
+//! The algorithm travels in the stream header (`"compression": "zstd"` in
+//! JSON, two flag bits in binary frames). The digest and every chunk cover
+//! the compressed bytes, so the receiver verifies first and decompresses
+//! last, reading at most [`MAX_DECOMPRESSED_SIZE`] bytes of output.
+
+use std::io::{Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+/// Hard cap on the decompressed size of a stream, so a tiny stream cannot
+/// expand into gigabytes.
+pub const MAX_DECOMPRESSED_SIZE: usize = 256 * 1024 * 1024;
+
+const ZSTD_LEVEL: i32 = 19;
+const BROTLI_QUALITY: u32 = 11;
+const BROTLI_WINDOW: u32 = 22;
+const BUFFER_SIZE: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    Zstd,
+    /// Raw DEFLATE (RFC 1951), without zlib or gzip framing.
+    Deflate,
+    Brotli,
+}
+
+impl Compression {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "zstd" => Some(Compression::Zstd),
+            "deflate" => Some(Compression::Deflate),
+            "brotli" | "br" => Some(Compression::Brotli),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::Zstd => "zstd",
+            Compression::Deflate => "deflate",
+            Compression::Brotli => "brotli",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecompressError {
+    /// The output would exceed the size limit.
+    TooLarge { limit: usize },
+    Corrupt(String),
+}
+
+pub fn compress(data: &[u8], algorithm: Compression) -> Vec<u8> {
+    match algorithm {
+        Compression::Zstd => {
+            zstd::encode_all(data, ZSTD_LEVEL).expect("compressing into memory cannot fail")
+        }
+        Compression::Deflate => {
+            let mut encoder =
+                flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::best());
+            encoder
+                .write_all(data)
+                .expect("compressing into memory cannot fail");
+            encoder.finish().expect("compressing into memory cannot fail")
+        }
+        Compression::Brotli => {
+            let mut output = Vec::new();
+            {
+                let mut encoder = brotli::CompressorWriter::new(
+                    &mut output,
+                    BUFFER_SIZE,
+                    BROTLI_QUALITY,
+                    BROTLI_WINDOW,
+                );
+                encoder
+                    .write_all(data)
+                    .expect("compressing into memory cannot fail");
+            }
+            output
+        }
+    }
+}
+
+/// Decompresses `data`, failing once the output grows beyond `limit` bytes.
+pub fn decompress(
+    data: &[u8],
+    algorithm: Compression,
+    limit: usize,
+) -> Result<Vec<u8>, DecompressError> {
+    let reader: Box<dyn Read + '_> = match algorithm {
+        Compression::Zstd => Box::new(
+            zstd::stream::read::Decoder::new(data)
+                .map_err(|e| DecompressError::Corrupt(e.to_string()))?,
+        ),
+        Compression::Deflate => Box::new(flate2::read::DeflateDecoder::new(data)),
+        Compression::Brotli => Box::new(brotli::Decompressor::new(data, BUFFER_SIZE)),
+    };
+
+    let mut output = Vec::new();
+    reader
+        .take(limit as u64 + 1)
+        .read_to_end(&mut output)
+        .map_err(|e| DecompressError::Corrupt(e.to_string()))?;
+    if output.len() > limit {
+        return Err(DecompressError::TooLarge { limit });
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALGORITHMS: [Compression; 3] =
+        [Compression::Zstd, Compression::Deflate, Compression::Brotli];
+
+    #[test]
+    fn round_trips_every_algorithm() {
+        let data: Vec<u8> = b"streaming qr ".repeat(500);
+        for algorithm in ALGORITHMS {
+            let compressed = compress(&data, algorithm);
+            assert!(compressed.len() < data.len() / 10);
+            assert_eq!(
+                decompress(&compressed, algorithm, data.len()).unwrap(),
+                data
+            );
+            assert_eq!(Compression::parse(algorithm.name()), Some(algorithm));
+        }
+    }
+
+    #[test]
+    fn stops_at_the_size_limit() {
+        for algorithm in ALGORITHMS {
+            let compressed = compress(&[0; 1000], algorithm);
+            assert_eq!(
+                decompress(&compressed, algorithm, 999),
+                Err(DecompressError::TooLarge { limit: 999 })
+            );
+            assert!(matches!(
+                decompress(b"not compressed at all", algorithm, 1000),
+                Err(DecompressError::Corrupt(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn caps_a_compression_bomb_at_256_mib() {
+        let bomb =
+            zstd::stream::encode_all(std::io::repeat(0).take(MAX_DECOMPRESSED_SIZE as u64 + 1), 1)
+                .unwrap();
+        assert!(bomb.len() < 64 * 1024);
+        assert_eq!(
+            decompress(&bomb, Compression::Zstd, MAX_DECOMPRESSED_SIZE),
+            Err(DecompressError::TooLarge {
+                limit: MAX_DECOMPRESSED_SIZE
+            })
+        );
+    }
+}
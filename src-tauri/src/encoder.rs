@@ -0,0 +1,514 @@
+//! Sender side: turns a payload into the frames of a stream.
+//!
+//! This mirrors `splitDataIntoChunks`/`createQRChunk` on the JS side, but
+//! can compress, encrypt and seal the payload first and always emits a
+//! header frame with the digest, length, compression and encryption
+//! parameters and, when signing, the sender's Ed25519 signature over the
+//! digest, followed by one data frame per chunk. Data frames announce the
+//! digest, so the stream waits for that header and its signature. File
+//! metadata goes in front of the payload before any of that, see
+//! [`crate::metadata`].
+//!
+//! In fountain mode the data frames carry LT droplets instead of chunks,
+//! as many as it takes to decode plus a repair overhead. Every one of them
+//! repeats the mode and length, so the header frame is not needed to start
+//! decoding; see [`crate::fountain`]. RaptorQ mode sends the source symbols
+//! and the same overhead of repair symbols per source block, each frame
+//! repeating the mode and OTI; see [`crate::raptor`]. Plain chunk streams
+//! may add parity chunks after the data chunks, with the group size,
+//! parity count and length on every frame; see [`crate::parity`].
+
+use serde::Deserialize;
+
+use crate::checksum;
+use crate::compression::{self, Compression};
+use crate::encryption::Encryption;
+use crate::fountain::{LtDecoder, LtEncoder};
+use crate::metadata::{self, FileMetadata};
+use crate::parity::{self, ParityLayout};
+use crate::protocol::{
+    self, Announced, Cell, Chunk, Frame, Grid, Payload, StreamHeader, StreamMode,
+};
+use crate::raptor::RaptorEncoder;
+use crate::recipient::Recipient;
+use crate::signature::StreamSignature;
+
+/// Same default as `splitDataIntoChunks`.
+pub const DEFAULT_CHUNK_SIZE: usize = 2000;
+/// Extra fountain or RaptorQ frames, in percent of the source blocks.
+pub const DEFAULT_REPAIR_OVERHEAD: u32 = 50;
+/// Data chunks per parity group when only the parity count is given.
+pub const DEFAULT_GROUP_SIZE: u32 = 16;
+
+/// Reed–Solomon parity of a plain chunk stream, see [`crate::parity`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParityOptions {
+    /// Data chunks per group.
+    pub group_size: u32,
+    /// Parity chunks added to every group.
+    pub parity: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodeOptions {
+    /// Payload bytes per data frame.
+    pub chunk_size: usize,
+    /// Plain chunks, or fountain droplets or RaptorQ packets of at most
+    /// `chunk_size` bytes.
+    pub mode: StreamMode,
+    /// Fountain or RaptorQ frames beyond those needed to decode, in percent
+    /// of the source blocks or symbols.
+    pub repair_overhead: u32,
+    /// Adds Reed–Solomon parity chunks to a plain chunk stream.
+    pub parity: Option<ParityOptions>,
+    pub compression: Option<Compression>,
+    /// Encrypts the (compressed) payload with this passphrase.
+    pub passphrase: Option<String>,
+    /// Seals the payload to this receiver's X25519 public key.
+    pub recipient: Option<[u8; 32]>,
+    /// Ed25519 secret key to sign the stream digest with.
+    pub signing_key: Option<[u8; 32]>,
+    /// Tags data frames with their cell when shown several per frame.
+    pub grid: Option<Grid>,
+    /// Describes the file being sent, inside the payload.
+    pub file: Option<FileMetadata>,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self {
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            mode: StreamMode::Chunks,
+            repair_overhead: DEFAULT_REPAIR_OVERHEAD,
+            parity: None,
+            compression: None,
+            passphrase: None,
+            recipient: None,
+            signing_key: None,
+            grid: None,
+            file: None,
+        }
+    }
+}
+
+/// Header frame first, then the data frames in sequence order.
+pub fn encode_stream(
+    stream_id: &str,
+    data: &[u8],
+    options: &EncodeOptions,
+) -> Result<Vec<Frame>, String> {
+    let file = options.file.as_ref().filter(|file| !file.is_empty());
+    let payload = match file {
+        Some(file) => metadata::wrap(file, data),
+        None => data.to_vec(),
+    };
+    let payload = match options.compression {
+        Some(algorithm) => compression::compress(&payload, algorithm),
+        None => payload,
+    };
+    let (payload, encryption) = match &options.passphrase {
+        Some(passphrase) => {
+            let encryption = Encryption::generate();
+            (encryption.encrypt(passphrase, &payload), Some(encryption))
+        }
+        None => (payload, None),
+    };
+    let (payload, recipient) = match &options.recipient {
+        Some(receiver) => {
+            let (recipient, sealed) = Recipient::seal(receiver, &payload);
+            (sealed, Some(recipient))
+        }
+        None => (payload, None),
+    };
+    let announced = Announced {
+        digest: true,
+        encryption: encryption.is_some(),
+        recipient: recipient.is_some(),
+        file: file.is_some(),
+    };
+
+    let coded = code(&payload, options)?;
+    let total = coded.total;
+    let digest = checksum::sha256(&payload);
+    let mut frames = Vec::with_capacity(coded.packets.len() + 1);
+    frames.push(Frame::Header {
+        stream_id: stream_id.to_string(),
+        total,
+        header: StreamHeader {
+            digest: Some(digest),
+            length: Some(payload.len() as u64),
+            compression: options.compression,
+            encryption,
+            recipient,
+            signature: options
+                .signing_key
+                .map(|secret| StreamSignature::sign(&secret, &digest)),
+            ..coded.repeated.clone()
+        },
+    });
+    frames.extend(coded.packets.iter().enumerate().map(|(sequence, packet)| {
+        Frame::Data(Chunk {
+            stream_id: stream_id.to_string(),
+            sequence: sequence as u32,
+            total,
+            data: Payload::Raw(packet.clone()),
+            checksum: Some(checksum::crc32(packet)),
+            seed: None,
+            cell: options
+                .grid
+                .and_then(|grid| Cell::new(grid, grid.cell_of(sequence as u32))),
+            header: StreamHeader {
+                compression: options.compression,
+                ..coded.repeated.clone()
+            },
+            announced,
+        })
+    }));
+    Ok(frames)
+}
+
+/// The payloads of the data frames in sequence order, with the stream
+/// `total` and the header fields every data frame repeats so that it can
+/// be decoded without the header frame.
+struct Coded {
+    total: u32,
+    repeated: StreamHeader,
+    packets: Vec<Vec<u8>>,
+}
+
+fn code(payload: &[u8], options: &EncodeOptions) -> Result<Coded, String> {
+    // No frame could carry an empty payload, so the stream would never end.
+    if payload.is_empty() {
+        return Err("Nothing to send: the payload is empty".to_string());
+    }
+    if options.parity.is_some() && options.mode != StreamMode::Chunks {
+        return Err("Parity chunks only go with plain chunk streams".to_string());
+    }
+    let chunk_size = options.chunk_size.max(1);
+    let coded = match options.mode {
+        StreamMode::Chunks => {
+            let mut packets: Vec<Vec<u8>> =
+                payload.chunks(chunk_size).map(<[u8]>::to_vec).collect();
+            let total = packets.len() as u32;
+            let mut repeated = StreamHeader {
+                mode: Some(StreamMode::Chunks),
+                ..StreamHeader::default()
+            };
+            if let Some(options) = options.parity {
+                let layout = ParityLayout::new(total, options.group_size, options.parity)
+                    .ok_or("A parity group holds at most 256 data and parity chunks")?;
+                let shard_size = packets[0].len();
+                packets.extend(parity::encode(&layout, &packets, shard_size));
+                repeated = StreamHeader {
+                    group_size: Some(layout.group_size),
+                    parity: Some(layout.parity),
+                    length: Some(payload.len() as u64),
+                    ..repeated
+                };
+            }
+            Coded {
+                total,
+                repeated,
+                packets,
+            }
+        }
+        StreamMode::Lt => {
+            let encoder = LtEncoder::new(payload, chunk_size);
+            let droplets = droplet_count(&encoder, options.repair_overhead)?;
+            Coded {
+                total: encoder.k(),
+                repeated: StreamHeader {
+                    mode: Some(StreamMode::Lt),
+                    length: Some(encoder.length()),
+                    ..StreamHeader::default()
+                },
+                packets: (0..droplets).map(|seed| encoder.droplet(seed)).collect(),
+            }
+        }
+        StreamMode::RaptorQ => {
+            let packet_size = u16::try_from(chunk_size).unwrap_or(u16::MAX);
+            let encoder = RaptorEncoder::new(payload, packet_size)
+                .ok_or("RaptorQ needs a payload and room for a symbol per frame")?;
+            if encoder.source_symbols() > protocol::MAX_TOTAL {
+                return Err(too_many_frames(encoder.source_symbols()));
+            }
+            let per_block = encoder.source_symbols().div_ceil(encoder.source_blocks());
+            let repair = (per_block as u64 * options.repair_overhead as u64).div_ceil(100);
+            let repair =
+                u32::try_from(repair).map_err(|_| overhead_too_large(options.repair_overhead))?;
+            Coded {
+                total: encoder.source_symbols(),
+                repeated: StreamHeader {
+                    mode: Some(StreamMode::RaptorQ),
+                    oti: Some(encoder.oti()),
+                    ..StreamHeader::default()
+                },
+                packets: encoder.packets(repair),
+            }
+        }
+    };
+    if coded.total > protocol::MAX_TOTAL {
+        return Err(too_many_frames(coded.total));
+    }
+    Ok(coded)
+}
+
+fn too_many_frames(total: u32) -> String {
+    format!(
+        "The payload needs {} frames, more than the {} a stream may have",
+        total,
+        protocol::MAX_TOTAL
+    )
+}
+
+/// Droplets to send: as many as it takes until the droplets sent so far
+/// decode, so that a receiver catching every frame always finishes, plus
+/// `overhead` percent of the source blocks for frames it misses. Droplet
+/// `seed` is sent with the same sequence number.
+fn droplet_count(encoder: &LtEncoder, overhead: u32) -> Result<u32, String> {
+    let mut decoder = LtDecoder::new(encoder.k(), encoder.block_size(), encoder.length())
+        .ok_or_else(|| too_many_frames(encoder.k()))?;
+    let mut seed = 0;
+    while !decoder.is_complete() {
+        decoder.add_droplet(seed, encoder.droplet(seed));
+        seed += 1;
+    }
+    let repair = (encoder.k() as u64 * overhead as u64).div_ceil(100);
+    u32::try_from(seed as u64 + repair).map_err(|_| overhead_too_large(overhead))
+}
+
+fn overhead_too_large(overhead: u32) -> String {
+    format!("Repair overhead of {}% is too large", overhead)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame;
+    use crate::protocol::{Credentials, SignatureStatus, StreamStore, Verification};
+    use crate::recipient::ReceiverKey;
+
+    fn payload() -> Vec<u8> {
+        (0..3000u32).map(|i| (i * 31 % 251) as u8).collect()
+    }
+
+    /// Feeds the binary frames for which `keep` holds to a fresh store.
+    fn receive(frames: &[Frame], keep: impl Fn(&Frame) -> bool) -> StreamStore {
+        let mut store = StreamStore::new();
+        for frame in frames.iter().filter(|frame| keep(frame)) {
+            store
+                .process_payload(&frame::encode(frame).unwrap())
+                .unwrap();
+        }
+        store
+    }
+
+    fn sequence(frame: &Frame) -> Option<u32> {
+        match frame {
+            Frame::Data(chunk) => Some(chunk.sequence),
+            Frame::Header { .. } => None,
+        }
+    }
+
+    #[test]
+    fn encodes_a_chunk_stream() {
+        let data = payload();
+        let options = EncodeOptions {
+            chunk_size: 1000,
+            signing_key: Some([7; 32]),
+            ..EncodeOptions::default()
+        };
+        let frames = encode_stream("1", &data, &options).unwrap();
+        assert_eq!(frames.len(), 4);
+        assert!(matches!(&frames[0], Frame::Header { header, .. } if header.signature.is_some()));
+
+        let stream = receive(&frames, |_| true).reconstruct("1").unwrap();
+        assert_eq!(stream.data, data);
+        assert_eq!(stream.verification, Verification::Verified);
+        assert_eq!(stream.signature, SignatureStatus::SignedByUnknown);
+    }
+
+    #[test]
+    fn seals_the_file_metadata_with_the_payload() {
+        let receiver = ReceiverKey::generate();
+        let file = FileMetadata {
+            name: Some("merger-plans.pdf".to_string()),
+            mime: Some("application/pdf".to_string()),
+            size: Some(3000),
+            modified: Some(1_700_000_000),
+        };
+        let options = EncodeOptions {
+            recipient: Some(receiver.public_key()),
+            file: Some(file.clone()),
+            ..EncodeOptions::default()
+        };
+        let frames = encode_stream("9", &payload(), &options).unwrap();
+        for frame in &frames {
+            let bytes = frame::encode(frame).unwrap();
+            assert!(!bytes.windows(6).any(|window| window == b"merger"));
+        }
+
+        let store = receive(&frames, |_| true);
+        let credentials = Credentials {
+            receiver: Some(&receiver),
+            ..Credentials::default()
+        };
+        let stream = store.reconstruct_with("9", credentials).unwrap();
+        assert_eq!(stream.data, payload());
+        assert_eq!(stream.file, Some(file));
+        assert_eq!(stream.verification, Verification::Verified);
+    }
+
+    #[test]
+    fn lt_streams_decode_despite_lost_frames() {
+        let data = payload();
+        let options = EncodeOptions {
+            chunk_size: 100,
+            mode: StreamMode::Lt,
+            repair_overhead: 100,
+            ..EncodeOptions::default()
+        };
+        let frames = encode_stream("2", &data, &options).unwrap();
+        assert!(frames.len() > 2 * 30);
+
+        // Without the header and with every fifth droplet lost.
+        let store = receive(&frames, |frame| {
+            sequence(frame).is_some_and(|seq| seq % 5 != 0)
+        });
+        let stream = store.reconstruct("2").unwrap_err();
+        assert_eq!(
+            stream,
+            crate::protocol::ProtocolError::HeaderMissing { field: "digest" }
+        );
+        let store = receive(&frames, |frame| {
+            sequence(frame).is_none_or(|seq| seq % 5 != 0)
+        });
+        let stream = store.reconstruct("2").unwrap();
+        assert_eq!(stream.data, data);
+        assert_eq!(stream.verification, Verification::Verified);
+    }
+
+    #[test]
+    fn every_lt_droplet_counts_when_none_is_lost() {
+        let data = payload();
+        for chunk_size in [1, 7, 999, 3000] {
+            let options = EncodeOptions {
+                chunk_size,
+                mode: StreamMode::Lt,
+                repair_overhead: 0,
+                ..EncodeOptions::default()
+            };
+            let frames = encode_stream("3", &data, &options).unwrap();
+            let stream = receive(&frames, |_| true).reconstruct("3").unwrap();
+            assert_eq!(stream.data, data);
+        }
+    }
+
+    #[test]
+    fn raptorq_streams_decode_despite_lost_symbols() {
+        let data = payload();
+        let options = EncodeOptions {
+            chunk_size: 124,
+            mode: StreamMode::RaptorQ,
+            repair_overhead: 50,
+            ..EncodeOptions::default()
+        };
+        let frames = encode_stream("5", &data, &options).unwrap();
+        let Frame::Header { total, header, .. } = &frames[0] else {
+            panic!("header frame first");
+        };
+        // 120-byte symbols after the 4-byte payload id.
+        assert_eq!(*total, 25);
+        assert!(header.oti.is_some());
+        assert_eq!(frames.len(), 1 + 25 + 13);
+
+        // Without the header, and with every fourth symbol lost.
+        let mut store = receive(&frames, |frame| {
+            sequence(frame).is_some_and(|seq| seq % 4 != 1)
+        });
+        assert_eq!(
+            store.reconstruct("5").unwrap_err(),
+            crate::protocol::ProtocolError::HeaderMissing { field: "digest" }
+        );
+        store.insert(frames[0].clone()).unwrap();
+        let stream = store.reconstruct("5").unwrap();
+        assert_eq!(stream.data, data);
+        assert_eq!(stream.verification, Verification::Verified);
+    }
+
+    #[test]
+    fn parity_chunks_stand_in_for_lost_ones() {
+        let data = payload();
+        let options = EncodeOptions {
+            chunk_size: 128,
+            parity: Some(ParityOptions {
+                group_size: 8,
+                parity: 2,
+            }),
+            ..EncodeOptions::default()
+        };
+        let frames = encode_stream("6", &data, &options).unwrap();
+        // 24 data chunks in three groups, with two parity chunks each.
+        assert_eq!(frames.len(), 1 + 24 + 6);
+
+        // Without the header, and with two data chunks lost per group.
+        let mut store = receive(&frames, |frame| {
+            sequence(frame).is_some_and(|seq| seq >= 24 || seq % 8 > 1)
+        });
+        let progress = store.progress("6").unwrap();
+        assert!(progress.missing.is_empty());
+        store.insert(frames[0].clone()).unwrap();
+        let stream = store.reconstruct("6").unwrap();
+        assert_eq!(stream.data, data);
+        assert_eq!(stream.verification, Verification::Verified);
+    }
+
+    #[test]
+    fn rejects_invalid_parity_options() {
+        let options = |group_size, parity, mode| EncodeOptions {
+            mode,
+            parity: Some(ParityOptions { group_size, parity }),
+            ..EncodeOptions::default()
+        };
+        let data = payload();
+        assert!(encode_stream("7", &data, &options(0, 2, StreamMode::Chunks)).is_err());
+        assert!(encode_stream("7", &data, &options(250, 10, StreamMode::Chunks)).is_err());
+        assert!(encode_stream("7", &data, &options(8, 2, StreamMode::Lt)).is_err());
+        assert!(encode_stream("7", &[], &options(8, 2, StreamMode::Chunks)).is_err());
+    }
+
+    #[test]
+    fn refuses_an_empty_payload() {
+        for mode in [StreamMode::Chunks, StreamMode::Lt, StreamMode::RaptorQ] {
+            let options = EncodeOptions {
+                mode,
+                ..EncodeOptions::default()
+            };
+            assert!(encode_stream("3", &[], &options).is_err());
+        }
+        let file = EncodeOptions {
+            file: Some(FileMetadata {
+                name: Some("empty.txt".to_string()),
+                ..FileMetadata::default()
+            }),
+            ..EncodeOptions::default()
+        };
+        let frames = encode_stream("3", &[], &file).unwrap();
+        let stream = receive(&frames, |_| true).reconstruct("3").unwrap();
+        assert!(stream.data.is_empty());
+    }
+
+    #[test]
+    fn refuses_more_frames_than_a_stream_may_have() {
+        let data = vec![0; protocol::MAX_TOTAL as usize + 1];
+        for mode in [StreamMode::Chunks, StreamMode::Lt] {
+            let options = EncodeOptions {
+                chunk_size: 1,
+                mode,
+                ..EncodeOptions::default()
+            };
+            assert!(encode_stream("4", &data, &options).is_err());
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! Passphrase encryption of whole payloads.
+//!
+//! The key is derived with Argon2id from the passphrase and a random salt,
+//! and the payload is sealed with ChaCha20-Poly1305. Salt, nonce and the
+//! Argon2 cost parameters travel in the stream header; the ciphertext,
+//! including its 16-byte tag, is what gets chunked. A wrong passphrase and
+//! a modified ciphertext are indistinguishable, both fail authentication.
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+
+pub const SALT_LEN: usize = 16;
+pub const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+
+/// Argon2id defaults (OWASP: 19 MiB, 2 passes, 1 lane).
+pub const DEFAULT_MEMORY_KIB: u32 = 19 * 1024;
+pub const DEFAULT_ITERATIONS: u32 = 2;
+pub const DEFAULT_PARALLELISM: u32 = 1;
+
+/// Upper bounds accepted from a header, so a hostile stream cannot make
+/// the receiver allocate gigabytes or spin for minutes.
+const MAX_MEMORY_KIB: u32 = 256 * 1024;
+const MAX_ITERATIONS: u32 = 16;
+const MAX_PARALLELISM: u32 = 8;
+
+/// Key derivation and cipher parameters of an encrypted stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Encryption {
+    pub salt: [u8; SALT_LEN],
+    pub nonce: [u8; NONCE_LEN],
+    /// Argon2id memory cost in KiB.
+    pub memory: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecryptError;
+
+impl Encryption {
+    /// Fresh random salt and nonce with the default cost parameters.
+    pub fn generate() -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; NONCE_LEN];
+        getrandom::fill(&mut salt).expect("system random source unavailable");
+        getrandom::fill(&mut nonce).expect("system random source unavailable");
+        Self {
+            salt,
+            nonce,
+            memory: DEFAULT_MEMORY_KIB,
+            iterations: DEFAULT_ITERATIONS,
+            parallelism: DEFAULT_PARALLELISM,
+        }
+    }
+
+    /// Whether the cost parameters are ones Argon2 accepts and within the
+    /// receiver's limits.
+    pub fn is_valid(&self) -> bool {
+        self.iterations <= MAX_ITERATIONS
+            && self.parallelism <= MAX_PARALLELISM
+            && self.memory <= MAX_MEMORY_KIB
+            && self.params().is_some()
+    }
+
+    fn params(&self) -> Option<Params> {
+        Params::new(
+            self.memory,
+            self.iterations,
+            self.parallelism,
+            Some(KEY_LEN),
+        )
+        .ok()
+    }
+
+    fn cipher(&self, passphrase: &str) -> Option<ChaCha20Poly1305> {
+        let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params()?);
+        let mut key = [0u8; KEY_LEN];
+        argon2
+            .hash_password_into(passphrase.as_bytes(), &self.salt, &mut key)
+            .ok()?;
+        Some(ChaCha20Poly1305::new(&Key::from(key)))
+    }
+
+    pub fn encrypt(&self, passphrase: &str, plaintext: &[u8]) -> Vec<u8> {
+        self.cipher(passphrase)
+            .expect("encryption parameters are valid")
+            .encrypt(&Nonce::from(self.nonce), plaintext)
+            .expect("payload fits the cipher limits")
+    }
+
+    pub fn decrypt(&self, passphrase: &str, ciphertext: &[u8]) -> Result<Vec<u8>, DecryptError> {
+        self.cipher(passphrase)
+            .ok_or(DecryptError)?
+            .decrypt(&Nonce::from(self.nonce), ciphertext)
+            .map_err(|_| DecryptError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cheap() -> Encryption {
+        Encryption {
+            memory: 64,
+            iterations: 1,
+            ..Encryption::generate()
+        }
+    }
+
+    #[test]
+    fn round_trips_with_the_right_passphrase() {
+        let encryption = cheap();
+        let ciphertext = encryption.encrypt("correct horse", b"secret payload");
+        assert_eq!(ciphertext.len(), b"secret payload".len() + 16);
+        assert_eq!(
+            encryption.decrypt("correct horse", &ciphertext).unwrap(),
+            b"secret payload"
+        );
+    }
+
+    #[test]
+    fn rejects_a_wrong_passphrase_or_tampered_ciphertext() {
+        let encryption = cheap();
+        let mut ciphertext = encryption.encrypt("correct horse", b"secret payload");
+        assert_eq!(
+            encryption.decrypt("battery staple", &ciphertext),
+            Err(DecryptError)
+        );
+        ciphertext[0] ^= 1;
+        assert_eq!(
+            encryption.decrypt("correct horse", &ciphertext),
+            Err(DecryptError)
+        );
+    }
+
+    #[test]
+    fn bounds_the_cost_parameters() {
+        assert!(Encryption::generate().is_valid());
+        for encryption in [
+            Encryption {
+                memory: MAX_MEMORY_KIB + 1,
+                ..cheap()
+            },
+            Encryption {
+                iterations: 0,
+                ..cheap()
+            },
+            Encryption {
+                parallelism: MAX_PARALLELISM + 1,
+                ..cheap()
+            },
+        ] {
+            assert!(!encryption.is_valid());
+            assert_eq!(encryption.decrypt("pw", &[0; 32]), Err(DecryptError));
+        }
+    }
+}
@@ -0,0 +1,372 @@
+//! LT (Luby transform) fountain code.
+//!
+//! The payload is split into `k` equally sized source blocks (the last one
+//! zero-padded). Every droplet is the XOR of a random subset of source
+//! blocks; the subset is derived from the droplet's seed alone, using the
+//! robust soliton degree distribution, so sender and receiver only need to
+//! agree on `k` and the seed. The receiver peels droplets as they arrive and
+//! finishes after roughly `k * (1 + ε)` of them, whichever ones they are.
+
+use std::collections::HashSet;
+
+/// Most source blocks a decoder accepts, as many as a stream may have.
+pub const MAX_BLOCKS: u32 = crate::protocol::MAX_TOTAL;
+
+/// Robust soliton tuning constants (see Luby, "LT Codes", 2002).
+const SOLITON_C: f64 = 0.1;
+const SOLITON_DELTA: f64 = 0.05;
+
+/// SplitMix64, chosen because it is tiny and trivially portable to senders
+/// written in other languages.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform float in `[0, 1)`.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform integer in `[0, bound)`.
+    fn next_below(&mut self, bound: u32) -> u32 {
+        (((self.next_u64() >> 32) * bound as u64) >> 32) as u32
+    }
+}
+
+/// Cumulative robust soliton distribution over degrees `1..=k`.
+fn robust_soliton_cdf(k: u32) -> Vec<f64> {
+    let k_f = k as f64;
+    let r = SOLITON_C * (k_f / SOLITON_DELTA).ln() * k_f.sqrt();
+    let spike = if r > 0.0 { (k_f / r).floor() as u32 } else { 0 };
+
+    let weights: Vec<f64> = (1..=k)
+        .map(|d| {
+            let ideal = if d == 1 {
+                1.0 / k_f
+            } else {
+                1.0 / (d as f64 * (d as f64 - 1.0))
+            };
+            let robust = if spike == 0 || d > spike {
+                0.0
+            } else if d < spike {
+                r / (d as f64 * k_f)
+            } else {
+                r * (r / SOLITON_DELTA).ln() / k_f
+            };
+            ideal + robust.max(0.0)
+        })
+        .collect();
+
+    let sum: f64 = weights.iter().sum();
+    let mut acc = 0.0;
+    weights
+        .iter()
+        .map(|weight| {
+            acc += weight / sum;
+            acc
+        })
+        .collect()
+}
+
+/// Maps seeds to the set of source blocks their droplet combines.
+#[derive(Debug, Clone)]
+pub struct DegreeSampler {
+    k: u32,
+    cdf: Vec<f64>,
+}
+
+impl DegreeSampler {
+    pub fn new(k: u32) -> Self {
+        Self {
+            k,
+            cdf: robust_soliton_cdf(k),
+        }
+    }
+
+    /// Source block indices (sorted, distinct) covered by droplet `seed`.
+    pub fn indices(&self, seed: u32) -> Vec<u32> {
+        let mut rng = SplitMix64(seed as u64);
+        let u = rng.next_f64();
+        let degree = (self.cdf.partition_point(|p| *p < u) as u32 + 1).min(self.k);
+
+        let mut picked = HashSet::with_capacity(degree as usize);
+        while (picked.len() as u32) < degree {
+            picked.insert(rng.next_below(self.k));
+        }
+        let mut indices: Vec<u32> = picked.into_iter().collect();
+        indices.sort_unstable();
+        indices
+    }
+}
+
+fn xor_into(target: &mut [u8], source: &[u8]) {
+    for (t, s) in target.iter_mut().zip(source) {
+        *t ^= s;
+    }
+}
+
+/// Produces droplets for a payload.
+#[derive(Debug, Clone)]
+pub struct LtEncoder {
+    blocks: Vec<Vec<u8>>,
+    length: u64,
+    sampler: DegreeSampler,
+}
+
+impl LtEncoder {
+    /// Splits `data` into blocks of `block_size` bytes.
+    pub fn new(data: &[u8], block_size: usize) -> Self {
+        let block_size = block_size.max(1);
+        let mut blocks: Vec<Vec<u8>> = data.chunks(block_size).map(<[u8]>::to_vec).collect();
+        if blocks.is_empty() {
+            blocks.push(Vec::new());
+        }
+        for block in &mut blocks {
+            block.resize(block_size, 0);
+        }
+
+        Self {
+            sampler: DegreeSampler::new(blocks.len() as u32),
+            blocks,
+            length: data.len() as u64,
+        }
+    }
+
+    /// Number of source blocks.
+    pub fn k(&self) -> u32 {
+        self.blocks.len() as u32
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.blocks[0].len()
+    }
+
+    /// Payload length in bytes, before padding.
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    pub fn droplet(&self, seed: u32) -> Vec<u8> {
+        let indices = self.sampler.indices(seed);
+        let mut data = self.blocks[indices[0] as usize].clone();
+        for index in &indices[1..] {
+            xor_into(&mut data, &self.blocks[*index as usize]);
+        }
+        data
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PendingDroplet {
+    indices: Vec<u32>,
+    data: Vec<u8>,
+}
+
+/// Result of feeding a droplet to [`LtDecoder::add_droplet`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropletStatus {
+    /// A droplet with this seed was already received.
+    Duplicate,
+    /// The droplet was accepted; `decoded` source blocks are now known.
+    Accepted { decoded: u32 },
+}
+
+/// Peeling (belief propagation) decoder.
+#[derive(Debug, Clone)]
+pub struct LtDecoder {
+    length: u64,
+    block_size: usize,
+    sampler: DegreeSampler,
+    blocks: Vec<Option<Vec<u8>>>,
+    decoded: u32,
+    pending: Vec<PendingDroplet>,
+    seeds: HashSet<u32>,
+}
+
+impl LtDecoder {
+    /// Returns `None` for no blocks or more than [`MAX_BLOCKS`].
+    pub fn new(k: u32, block_size: usize, length: u64) -> Option<Self> {
+        if k == 0 || k > MAX_BLOCKS {
+            return None;
+        }
+        Some(Self {
+            length,
+            block_size,
+            sampler: DegreeSampler::new(k),
+            blocks: vec![None; k as usize],
+            decoded: 0,
+            pending: Vec::new(),
+            seeds: HashSet::new(),
+        })
+    }
+
+    pub fn k(&self) -> u32 {
+        self.blocks.len() as u32
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// Number of distinct droplets received so far.
+    pub fn droplets(&self) -> u32 {
+        self.seeds.len() as u32
+    }
+
+    /// Number of source blocks recovered so far.
+    pub fn decoded(&self) -> u32 {
+        self.decoded
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.decoded == self.k()
+    }
+
+    /// Source block indices that are still unknown.
+    pub fn missing(&self) -> Vec<u32> {
+        (0..self.k())
+            .filter(|index| self.blocks[*index as usize].is_none())
+            .collect()
+    }
+
+    /// Feeds one droplet. `data` must be exactly `block_size` bytes long.
+    pub fn add_droplet(&mut self, seed: u32, data: Vec<u8>) -> DropletStatus {
+        if !self.seeds.insert(seed) {
+            return DropletStatus::Duplicate;
+        }
+
+        let mut droplet = PendingDroplet {
+            indices: self.sampler.indices(seed),
+            data,
+        };
+        self.reduce(&mut droplet);
+        match droplet.indices.len() {
+            0 => {}
+            1 => self.resolve(droplet),
+            _ => self.pending.push(droplet),
+        }
+
+        DropletStatus::Accepted {
+            decoded: self.decoded,
+        }
+    }
+
+    /// XORs every already known block out of `droplet`.
+    fn reduce(&self, droplet: &mut PendingDroplet) {
+        let blocks = &self.blocks;
+        droplet.indices.retain(|index| match &blocks[*index as usize] {
+            Some(block) => {
+                xor_into(&mut droplet.data, block);
+                false
+            }
+            None => true,
+        });
+    }
+
+    /// Stores a degree-one droplet and peels everything it unlocks.
+    fn resolve(&mut self, droplet: PendingDroplet) {
+        let mut ready = vec![droplet];
+        while let Some(droplet) = ready.pop() {
+            let index = droplet.indices[0] as usize;
+            if self.blocks[index].is_some() {
+                continue;
+            }
+            self.blocks[index] = Some(droplet.data);
+            self.decoded += 1;
+
+            let mut remaining = Vec::with_capacity(self.pending.len());
+            for mut pending in std::mem::take(&mut self.pending) {
+                self.reduce(&mut pending);
+                match pending.indices.len() {
+                    0 => {}
+                    1 => ready.push(pending),
+                    _ => remaining.push(pending),
+                }
+            }
+            self.pending = remaining;
+        }
+    }
+
+    /// Concatenates the source blocks once every one of them is known.
+    pub fn finish(&self) -> Option<Vec<u8>> {
+        if !self.is_complete() {
+            return None;
+        }
+        let mut data: Vec<u8> = self.blocks.iter().flatten().flatten().copied().collect();
+        data.truncate(self.length as usize);
+        Some(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload() -> Vec<u8> {
+        (0..1000u32).map(|i| (i * 7 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn decodes_from_any_sufficient_droplets() {
+        let data = payload();
+        let encoder = LtEncoder::new(&data, 64);
+        assert_eq!((encoder.k(), encoder.block_size()), (16, 64));
+        let mut decoder = LtDecoder::new(encoder.k(), 64, encoder.length()).unwrap();
+        // Every third droplet is lost on the way.
+        for seed in (0..1000).filter(|seed| seed % 3 != 0) {
+            decoder.add_droplet(seed, encoder.droplet(seed));
+            if decoder.is_complete() {
+                break;
+            }
+        }
+        assert!(decoder.missing().is_empty());
+        assert_eq!(decoder.finish().unwrap(), data);
+    }
+
+    #[test]
+    fn ignores_duplicate_droplets() {
+        let encoder = LtEncoder::new(&payload(), 64);
+        let mut decoder = LtDecoder::new(encoder.k(), 64, encoder.length()).unwrap();
+        assert!(matches!(
+            decoder.add_droplet(5, encoder.droplet(5)),
+            DropletStatus::Accepted { .. }
+        ));
+        assert_eq!(
+            decoder.add_droplet(5, encoder.droplet(5)),
+            DropletStatus::Duplicate
+        );
+        assert_eq!(decoder.droplets(), 1);
+        assert_eq!(decoder.finish(), None);
+    }
+
+    #[test]
+    fn samples_the_same_blocks_for_a_seed() {
+        let (sampler, other) = (DegreeSampler::new(100), DegreeSampler::new(100));
+        for seed in 0..100 {
+            let indices = sampler.indices(seed);
+            assert_eq!(indices, other.indices(seed));
+            assert!(!indices.is_empty());
+            assert!(indices.windows(2).all(|pair| pair[0] < pair[1]));
+            assert!(indices.iter().all(|index| *index < 100));
+        }
+    }
+
+    #[test]
+    fn bounds_the_block_count() {
+        assert!(LtDecoder::new(0, 64, 0).is_none());
+        assert!(LtDecoder::new(MAX_BLOCKS + 1, 64, 0).is_none());
+        assert!(LtDecoder::new(u32::MAX, 64, 0).is_none());
+        assert_eq!(LtDecoder::new(MAX_BLOCKS, 1, 0).unwrap().k(), MAX_BLOCKS);
+    }
+}
@@ -0,0 +1,569 @@
+//! Compact binary frame layout, carried in QR byte mode.
+//!
+//! JSON frames spend a large part of each QR code on field names and base64
+//! expansion. A binary frame is laid out as:
+//!
+//! ```text
+//! magic    u8      0xA5
+//! version  u8      1
+//! flags    u8      see FLAG_*
+//! id       varint  stream id
+//! seq      varint  absent in header frames
+//! total    varint
+//! [ext_len varint, then ext_len bytes of TLV fields]  if FLAG_EXTENSIONS
+//! crc      u32 BE  CRC32 of the payload; absent in header frames
+//! payload  bytes   raw chunk data up to the end of the frame
+//! ```
+//!
+//! Varints are unsigned LEB128. Stream ids are numeric, so a binary stream
+//! shows up in the store under its decimal id. Extensions carry the
+//! [`StreamHeader`] fields, the droplet seed and the grid cell as
+//! `tag u8, len varint, value`; unknown tags are skipped so newer senders
+//! stay readable. A trailer is simply another header frame, say one carrying
+//! a signature. The payload compression is cheap enough to sit in the flags
+//! of every frame, and data frames flag the header fields they announce
+//! (see [`Announced`]) so that a lost header frame is noticed, as well as
+//! a payload led by file metadata.
+
+use crate::checksum;
+use crate::compression::Compression;
+use crate::encryption::Encryption;
+use crate::protocol::{
+    self, Announced, Cell, Chunk, Frame, Grid, Payload, ProtocolError, StreamHeader, StreamMode,
+};
+use crate::recipient::Recipient;
+use crate::signature::StreamSignature;
+
+pub const MAGIC: u8 = 0xA5;
+pub const VERSION: u8 = 1;
+
+/// The frame carries only stream-level fields (no seq, crc or payload).
+pub const FLAG_HEADER: u8 = 0b0000_0001;
+/// An extension block follows `total`.
+pub const FLAG_EXTENSIONS: u8 = 0b0000_0010;
+/// Data frames: the stream header carries a digest.
+pub const FLAG_DIGEST: u8 = 0b0000_0100;
+/// Data frames: the stream header carries passphrase encryption parameters.
+pub const FLAG_ENCRYPTED: u8 = 0b0000_1000;
+/// Payload compression: 0 none, 1 zstd, 2 deflate, 3 brotli.
+pub const FLAG_COMPRESSION: u8 = 0b0011_0000;
+const COMPRESSION_SHIFT: u32 = 4;
+/// Data frames: the stream header carries a recipient, the payload is sealed.
+pub const FLAG_SEALED: u8 = 0b0100_0000;
+/// Data frames: the payload starts with file metadata, see [`crate::metadata`].
+pub const FLAG_FILE: u8 = 0b1000_0000;
+
+const TAG_DIGEST: u8 = 0x01;
+const TAG_MODE: u8 = 0x02;
+const TAG_LENGTH: u8 = 0x03;
+const TAG_OTI: u8 = 0x04;
+const TAG_GROUP_SIZE: u8 = 0x05;
+const TAG_PARITY: u8 = 0x06;
+const TAG_SEED: u8 = 0x07;
+/// Salt, nonce, then Argon2 memory, iterations and parallelism as varints.
+const TAG_ENCRYPTION: u8 = 0x08;
+/// Ed25519 public key followed by the signature.
+const TAG_SIGNATURE: u8 = 0x09;
+/// Receiver public key, sender ephemeral public key, nonce.
+const TAG_RECIPIENT: u8 = 0x0a;
+/// Grid columns, rows and cell index as varints.
+const TAG_CELL: u8 = 0x0b;
+
+/// Whether `bytes` look like a binary frame rather than JSON text.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.first() == Some(&MAGIC)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_field(out: &mut Vec<u8>, tag: u8, value: &[u8]) {
+    out.push(tag);
+    write_varint(out, value.len() as u64);
+    out.extend_from_slice(value);
+}
+
+fn varint_bytes(value: u64) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_varint(&mut out, value);
+    out
+}
+
+fn mode_byte(mode: StreamMode) -> u8 {
+    match mode {
+        StreamMode::Chunks => 0,
+        StreamMode::Lt => 1,
+        StreamMode::RaptorQ => 2,
+    }
+}
+
+fn compression_bits(compression: Option<Compression>) -> u8 {
+    let value = match compression {
+        None => 0,
+        Some(Compression::Zstd) => 1,
+        Some(Compression::Deflate) => 2,
+        Some(Compression::Brotli) => 3,
+    };
+    value << COMPRESSION_SHIFT
+}
+
+fn compression_from_flags(flags: u8) -> Option<Compression> {
+    match (flags & FLAG_COMPRESSION) >> COMPRESSION_SHIFT {
+        1 => Some(Compression::Zstd),
+        2 => Some(Compression::Deflate),
+        3 => Some(Compression::Brotli),
+        _ => None,
+    }
+}
+
+fn announced_bits(announced: Announced) -> u8 {
+    [
+        (announced.digest, FLAG_DIGEST),
+        (announced.encryption, FLAG_ENCRYPTED),
+        (announced.recipient, FLAG_SEALED),
+        (announced.file, FLAG_FILE),
+    ]
+    .into_iter()
+    .filter(|(set, _)| *set)
+    .fold(0, |flags, (_, flag)| flags | flag)
+}
+
+fn announced_from_flags(flags: u8) -> Announced {
+    Announced {
+        digest: flags & FLAG_DIGEST != 0,
+        encryption: flags & FLAG_ENCRYPTED != 0,
+        recipient: flags & FLAG_SEALED != 0,
+        file: flags & FLAG_FILE != 0,
+    }
+}
+
+fn extensions(header: &StreamHeader, seed: Option<u32>, cell: Option<Cell>) -> Vec<u8> {
+    let mut out = Vec::new();
+    if let Some(digest) = &header.digest {
+        write_field(&mut out, TAG_DIGEST, digest);
+    }
+    if let Some(mode) = header.mode.filter(|mode| *mode != StreamMode::Chunks) {
+        write_field(&mut out, TAG_MODE, &[mode_byte(mode)]);
+    }
+    if let Some(length) = header.length {
+        write_field(&mut out, TAG_LENGTH, &varint_bytes(length));
+    }
+    if let Some(oti) = &header.oti {
+        write_field(&mut out, TAG_OTI, oti);
+    }
+    if let Some(group_size) = header.group_size {
+        write_field(&mut out, TAG_GROUP_SIZE, &varint_bytes(group_size as u64));
+    }
+    if let Some(parity) = header.parity {
+        write_field(&mut out, TAG_PARITY, &varint_bytes(parity as u64));
+    }
+    if let Some(encryption) = &header.encryption {
+        let mut value = Vec::new();
+        value.extend_from_slice(&encryption.salt);
+        value.extend_from_slice(&encryption.nonce);
+        write_varint(&mut value, encryption.memory as u64);
+        write_varint(&mut value, encryption.iterations as u64);
+        write_varint(&mut value, encryption.parallelism as u64);
+        write_field(&mut out, TAG_ENCRYPTION, &value);
+    }
+    if let Some(recipient) = &header.recipient {
+        let mut value = recipient.public_key.to_vec();
+        value.extend_from_slice(&recipient.ephemeral);
+        value.extend_from_slice(&recipient.nonce);
+        write_field(&mut out, TAG_RECIPIENT, &value);
+    }
+    if let Some(signature) = &header.signature {
+        let mut value = signature.public_key.to_vec();
+        value.extend_from_slice(&signature.signature);
+        write_field(&mut out, TAG_SIGNATURE, &value);
+    }
+    if let Some(seed) = seed {
+        write_field(&mut out, TAG_SEED, &varint_bytes(seed as u64));
+    }
+    if let Some(cell) = cell {
+        let mut value = varint_bytes(cell.grid.columns as u64);
+        write_varint(&mut value, cell.grid.rows as u64);
+        write_varint(&mut value, cell.index as u64);
+        write_field(&mut out, TAG_CELL, &value);
+    }
+    out
+}
+
+/// Serializes a frame. Binary frames need a numeric stream id.
+pub fn encode(frame: &Frame) -> Result<Vec<u8>, ProtocolError> {
+    let stream_id: u64 = frame
+        .stream_id()
+        .parse()
+        .map_err(|_| ProtocolError::InvalidFormat)?;
+    let (total, header, seed, cell) = match frame {
+        Frame::Data(chunk) => (chunk.total, &chunk.header, chunk.seed, chunk.cell),
+        Frame::Header { total, header, .. } => (*total, header, None, None),
+    };
+    let extensions = extensions(header, seed, cell);
+
+    let mut flags = compression_bits(header.compression);
+    if matches!(frame, Frame::Header { .. }) {
+        flags |= FLAG_HEADER;
+    }
+    if !extensions.is_empty() {
+        flags |= FLAG_EXTENSIONS;
+    }
+    if let Frame::Data(chunk) = frame {
+        flags |= announced_bits(chunk.announced);
+    }
+
+    let mut out = vec![MAGIC, VERSION, flags];
+    write_varint(&mut out, stream_id);
+    if let Frame::Data(chunk) = frame {
+        write_varint(&mut out, chunk.sequence as u64);
+    }
+    write_varint(&mut out, total as u64);
+    if !extensions.is_empty() {
+        write_varint(&mut out, extensions.len() as u64);
+        out.extend_from_slice(&extensions);
+    }
+
+    if let Frame::Data(chunk) = frame {
+        let payload = chunk
+            .data
+            .clone()
+            .decode()
+            .map_err(|e| ProtocolError::Decode {
+                sequence: chunk.sequence,
+                message: e.to_string(),
+            })?;
+        let crc = chunk.checksum.unwrap_or_else(|| checksum::crc32(&payload));
+        out.extend_from_slice(&crc.to_be_bytes());
+        out.extend_from_slice(&payload);
+    }
+    Ok(out)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, ProtocolError> {
+        let (first, rest) = self.bytes.split_first().ok_or_else(truncated)?;
+        self.bytes = rest;
+        Ok(*first)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ProtocolError> {
+        if len > self.bytes.len() {
+            return Err(truncated());
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn varint(&mut self) -> Result<u64, ProtocolError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(ProtocolError::InvalidFormat)
+    }
+
+    fn varint_u32(&mut self) -> Result<u32, ProtocolError> {
+        u32::try_from(self.varint()?).map_err(|_| ProtocolError::InvalidFormat)
+    }
+}
+
+fn truncated() -> ProtocolError {
+    ProtocolError::Parse {
+        message: "truncated binary frame".to_string(),
+    }
+}
+
+fn read_extensions(
+    bytes: &[u8],
+    header: &mut StreamHeader,
+    seed: &mut Option<u32>,
+    cell: &mut Option<Cell>,
+) -> Result<(), ProtocolError> {
+    let mut reader = Reader { bytes };
+    while !reader.bytes.is_empty() {
+        let tag = reader.byte()?;
+        let len = reader.varint()? as usize;
+        let value = reader.take(len)?;
+        let mut field = Reader { bytes: value };
+        match tag {
+            TAG_DIGEST => {
+                header.digest = Some(value.try_into().map_err(|_| ProtocolError::InvalidFormat)?)
+            }
+            TAG_MODE => {
+                header.mode = Some(match field.byte()? {
+                    0 => StreamMode::Chunks,
+                    1 => StreamMode::Lt,
+                    2 => StreamMode::RaptorQ,
+                    _ => return Err(ProtocolError::InvalidFormat),
+                })
+            }
+            TAG_LENGTH => header.length = Some(field.varint()?),
+            TAG_OTI => {
+                header.oti = Some(value.try_into().map_err(|_| ProtocolError::InvalidFormat)?)
+            }
+            TAG_GROUP_SIZE => header.group_size = Some(field.varint_u32()?),
+            TAG_PARITY => header.parity = Some(field.varint_u32()?),
+            TAG_SEED => *seed = Some(field.varint_u32()?),
+            TAG_CELL => {
+                let grid = Grid {
+                    columns: field.varint_u32()?,
+                    rows: field.varint_u32()?,
+                };
+                let index = field.varint_u32()?;
+                *cell = Some(Cell::new(grid, index).ok_or(ProtocolError::InvalidFormat)?);
+            }
+            TAG_ENCRYPTION => {
+                let encryption = Encryption {
+                    salt: field.take(16)?.try_into().unwrap(),
+                    nonce: field.take(12)?.try_into().unwrap(),
+                    memory: field.varint_u32()?,
+                    iterations: field.varint_u32()?,
+                    parallelism: field.varint_u32()?,
+                };
+                if !encryption.is_valid() {
+                    return Err(ProtocolError::InvalidFormat);
+                }
+                header.encryption = Some(encryption);
+            }
+            TAG_RECIPIENT => {
+                header.recipient = Some(Recipient {
+                    public_key: field.take(32)?.try_into().unwrap(),
+                    ephemeral: field.take(32)?.try_into().unwrap(),
+                    nonce: field.take(12)?.try_into().unwrap(),
+                })
+            }
+            TAG_SIGNATURE => {
+                header.signature = Some(StreamSignature {
+                    public_key: field.take(32)?.try_into().unwrap(),
+                    signature: field.take(64)?.try_into().unwrap(),
+                })
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Parses a binary frame.
+pub fn decode(bytes: &[u8]) -> Result<Frame, ProtocolError> {
+    let mut reader = Reader { bytes };
+    if reader.byte()? != MAGIC {
+        return Err(ProtocolError::InvalidFormat);
+    }
+    let version = reader.byte()?;
+    if version != VERSION {
+        return Err(ProtocolError::Parse {
+            message: format!("unsupported binary frame version {}", version),
+        });
+    }
+    let flags = reader.byte()?;
+
+    let stream_id = reader.varint()?.to_string();
+    let sequence = match flags & FLAG_HEADER {
+        0 => Some(reader.varint_u32()?),
+        _ => None,
+    };
+    let total = reader.varint_u32()?;
+    if total == 0 {
+        return Err(ProtocolError::InvalidFormat);
+    }
+
+    let mut header = StreamHeader {
+        compression: compression_from_flags(flags),
+        ..StreamHeader::default()
+    };
+    let mut seed = None;
+    let mut cell = None;
+    if flags & FLAG_EXTENSIONS != 0 {
+        let len = reader.varint()? as usize;
+        read_extensions(reader.take(len)?, &mut header, &mut seed, &mut cell)?;
+    }
+    protocol::check_limits(Some(total), &header)?;
+
+    let Some(sequence) = sequence else {
+        return Ok(Frame::Header {
+            stream_id,
+            total,
+            header,
+        });
+    };
+
+    header.mode.get_or_insert(StreamMode::Chunks);
+    let crc = u32::from_be_bytes(reader.take(4)?.try_into().unwrap());
+    if reader.bytes.is_empty() {
+        return Err(ProtocolError::InvalidFormat);
+    }
+    Ok(Frame::Data(Chunk {
+        stream_id,
+        sequence,
+        total,
+        data: Payload::Raw(reader.bytes.to_vec()),
+        checksum: Some(crc),
+        seed,
+        cell,
+        header,
+        announced: announced_from_flags(flags),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data_frame() -> Chunk {
+        Chunk {
+            stream_id: "42".to_string(),
+            sequence: 300,
+            total: 20,
+            data: Payload::Raw(b"droplet".to_vec()),
+            checksum: Some(checksum::crc32(b"droplet")),
+            seed: Some(7),
+            cell: Cell::new(
+                Grid {
+                    columns: 2,
+                    rows: 2,
+                },
+                3,
+            ),
+            header: StreamHeader {
+                mode: Some(StreamMode::Lt),
+                length: Some(1000),
+                compression: Some(Compression::Brotli),
+                ..StreamHeader::default()
+            },
+            announced: Announced {
+                digest: true,
+                encryption: true,
+                recipient: false,
+                file: true,
+            },
+        }
+    }
+
+    #[test]
+    fn round_trips_data_frames() {
+        let frame = Frame::Data(data_frame());
+        let bytes = encode(&frame).unwrap();
+        assert!(is_binary(&bytes));
+        assert_eq!(
+            bytes[2],
+            FLAG_EXTENSIONS | FLAG_DIGEST | FLAG_ENCRYPTED | (3 << 4) | FLAG_FILE
+        );
+        assert_eq!(decode(&bytes).unwrap(), frame);
+
+        let plain = Chunk {
+            seed: None,
+            cell: None,
+            header: StreamHeader {
+                mode: Some(StreamMode::Chunks),
+                ..StreamHeader::default()
+            },
+            announced: Announced::default(),
+            ..data_frame()
+        };
+        let bytes = encode(&Frame::Data(plain.clone())).unwrap();
+        // magic, version, flags, id, two-byte seq, total, crc, payload
+        assert_eq!(bytes.len(), 3 + 1 + 2 + 1 + 4 + 7);
+        assert_eq!(decode(&bytes).unwrap(), Frame::Data(plain));
+    }
+
+    #[test]
+    fn round_trips_header_and_trailer_frames() {
+        let header = StreamHeader {
+            digest: Some(checksum::sha256(b"payload")),
+            mode: Some(StreamMode::RaptorQ),
+            length: Some(123_456),
+            oti: Some([1; 12]),
+            group_size: Some(16),
+            parity: Some(4),
+            encryption: Some(Encryption::generate()),
+            recipient: Some(Recipient {
+                public_key: [2; 32],
+                ephemeral: [3; 32],
+                nonce: [4; 12],
+            }),
+            ..StreamHeader::default()
+        };
+        let frame = Frame::Header {
+            stream_id: "7".to_string(),
+            total: 5,
+            header,
+        };
+        assert_eq!(decode(&encode(&frame).unwrap()).unwrap(), frame);
+
+        let trailer = Frame::Header {
+            stream_id: "7".to_string(),
+            total: 5,
+            header: StreamHeader {
+                signature: Some(StreamSignature::sign(&[5; 32], &[6; 32])),
+                ..StreamHeader::default()
+            },
+        };
+        assert_eq!(decode(&encode(&trailer).unwrap()).unwrap(), trailer);
+    }
+
+    #[test]
+    fn skips_unknown_extension_tags() {
+        let mut bytes = vec![MAGIC, VERSION, FLAG_HEADER | FLAG_EXTENSIONS, 9, 2];
+        let mut extensions = Vec::new();
+        write_field(&mut extensions, 0x7f, b"from a newer sender");
+        write_field(&mut extensions, TAG_LENGTH, &varint_bytes(300));
+        write_varint(&mut bytes, extensions.len() as u64);
+        bytes.extend_from_slice(&extensions);
+
+        let Frame::Header { header, total, .. } = decode(&bytes).unwrap() else {
+            panic!("header frame");
+        };
+        assert_eq!((total, header.length), (2, Some(300)));
+    }
+
+    #[test]
+    fn rejects_malformed_frames() {
+        let bytes = encode(&Frame::Data(data_frame())).unwrap();
+        assert_eq!(decode(&bytes[1..]), Err(ProtocolError::InvalidFormat));
+        assert!(matches!(
+            decode(&bytes[..bytes.len() - 12]),
+            Err(ProtocolError::Parse { .. })
+        ));
+        let mut newer = bytes.clone();
+        newer[1] = VERSION + 1;
+        assert!(matches!(decode(&newer), Err(ProtocolError::Parse { .. })));
+
+        // A data frame without payload, and streams of no or too many chunks.
+        assert_eq!(
+            decode(&[MAGIC, VERSION, 0, 1, 0, 1, 0, 0, 0, 0]),
+            Err(ProtocolError::InvalidFormat)
+        );
+        assert_eq!(
+            decode(&[MAGIC, VERSION, FLAG_HEADER, 1, 0]),
+            Err(ProtocolError::InvalidFormat)
+        );
+        let mut huge = vec![MAGIC, VERSION, FLAG_HEADER, 1];
+        write_varint(&mut huge, protocol::MAX_TOTAL as u64 + 1);
+        assert!(matches!(decode(&huge), Err(ProtocolError::Parse { .. })));
+
+        let named = Frame::Data(Chunk {
+            stream_id: "not numeric".to_string(),
+            ..data_frame()
+        });
+        assert_eq!(encode(&named), Err(ProtocolError::InvalidFormat));
+    }
+}
@@ -0,0 +1,335 @@
+//! Append-only journal of received frames, so that partial streams survive
+//! the app being closed or killed.
+//!
+//! Every stream has one file in the journal folder, named after a hash of
+//! its id. It starts with a magic number and a record holding the stream
+//! id, followed by one record per accepted frame: the raw QR content as it
+//! was passed to [`StreamStore::process_payload`], which replays it
+//! exactly. Records are a little-endian `u32` length, the CRC-32 of the
+//! bytes and the bytes. A record cut short by the process dying mid-write
+//! fails its length or CRC; replaying the journal cuts the file back to
+//! the last good record, so that later frames are appended after it.
+//! Listing journals only reads them.
+//!
+//! A journal is deleted once its payload was written to disk, see
+//! [`StreamStore::settle`], and journals untouched for [`MAX_AGE`] are
+//! dropped instead of replayed at startup, as are those whose stream-id
+//! record was torn. Appending to such a journal starts it over.
+//!
+//! Writes are not synced: a killed process loses nothing the kernel has
+//! already accepted, and a power loss only costs frames that can be
+//! scanned again.
+//!
+//! [`StreamStore::process_payload`]: crate::protocol::StreamStore::process_payload
+//! [`StreamStore::settle`]: crate::protocol::StreamStore::settle
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::protocol::Progress;
+
+/// Folder under the app data directory.
+pub const DIR_NAME: &str = "streams";
+const EXTENSION: &str = "journal";
+const MAGIC: &[u8; 4] = b"SQJ1";
+const RECORD_HEADER: usize = 8;
+/// Journals not written to for this long are abandoned transfers.
+pub const MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// A stream kept in the journal, as listed to the frontend.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredStream {
+    pub id: String,
+    /// `None` when the journaled frames no longer form a valid stream.
+    pub progress: Option<Progress>,
+    /// Frames in the journal.
+    pub frames: usize,
+    /// Journal size in bytes.
+    pub size: u64,
+    /// Last write, in seconds since the Unix epoch.
+    pub updated: u64,
+}
+
+/// The contents of one journal file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournaledStream {
+    pub stream_id: String,
+    pub payloads: Vec<Vec<u8>>,
+    pub size: u64,
+    pub updated: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Journal {
+    dir: PathBuf,
+}
+
+impl Journal {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, stream_id: &str) -> PathBuf {
+        let hash = Sha256::digest(stream_id.as_bytes());
+        self.dir
+            .join(format!("{}.{}", hex::encode(&hash[..16]), EXTENSION))
+    }
+
+    /// Adds `payload` to the journal of `stream_id`, creating it if needed.
+    /// A journal whose stream-id record was torn is started over.
+    pub fn append(&self, stream_id: &str, payload: &[u8]) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(self.path(stream_id))?;
+        let mut header = MAGIC.to_vec();
+        push_record(&mut header, stream_id.as_bytes());
+        let len = file.metadata()?.len();
+        let mut start = vec![0; header.len().min(len as usize)];
+        file.read_exact(&mut start)?;
+        let mut bytes = Vec::with_capacity(header.len() + RECORD_HEADER + payload.len());
+        if start != header {
+            file.set_len(0)?;
+            bytes.extend_from_slice(&header);
+        }
+        push_record(&mut bytes, payload);
+        // One write per frame, so a crash cannot interleave two records.
+        file.write_all(&bytes)
+    }
+
+    /// The journal of `stream_id`, if there is one, repaired for replay.
+    pub fn load(&self, stream_id: &str) -> io::Result<Option<JournaledStream>> {
+        match read_file(&self.path(stream_id), true) {
+            Ok(stream) => Ok(Some(stream)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn files(&self) -> Vec<PathBuf> {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == EXTENSION))
+            .collect()
+    }
+
+    /// Every readable journal, oldest first, left as it is on disk.
+    pub fn load_all(&self) -> Vec<JournaledStream> {
+        let mut streams: Vec<JournaledStream> = self
+            .files()
+            .iter()
+            .filter_map(|path| read_file(path, false).ok())
+            .collect();
+        streams.sort_by_key(|stream| stream.updated);
+        streams
+    }
+
+    /// Every journal to replay at startup, oldest first and repaired.
+    /// Journals older than `max_age` or not readable as a journal are
+    /// deleted instead; the second list says which of them could not be.
+    pub fn restore(&self, max_age: Duration) -> (Vec<JournaledStream>, Vec<String>) {
+        let now = seconds(SystemTime::now());
+        let mut streams = Vec::new();
+        let mut errors = Vec::new();
+        for path in self.files() {
+            let Ok(updated) = modified(&path) else {
+                continue;
+            };
+            let stream = if now.saturating_sub(updated) > max_age.as_secs() {
+                None
+            } else {
+                match read_file(&path, true) {
+                    Ok(stream) => Some(stream),
+                    Err(e) if e.kind() == io::ErrorKind::InvalidData => None,
+                    Err(e) => {
+                        errors.push(format!("Failed to read journal {}: {}", path.display(), e));
+                        continue;
+                    }
+                }
+            };
+            match stream {
+                Some(stream) => streams.push(stream),
+                None => {
+                    if let Err(e) = fs::remove_file(&path) {
+                        errors.push(format!(
+                            "Failed to delete journal {}: {}",
+                            path.display(),
+                            e
+                        ));
+                    }
+                }
+            }
+        }
+        streams.sort_by_key(|stream| stream.updated);
+        (streams, errors)
+    }
+
+    /// Deletes the journal of `stream_id`; `false` when there was none.
+    pub fn remove(&self, stream_id: &str) -> io::Result<bool> {
+        match fs::remove_file(self.path(stream_id)) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn push_record(bytes: &mut Vec<u8>, data: &[u8]) {
+    bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&crc32fast::hash(data).to_le_bytes());
+    bytes.extend_from_slice(data);
+}
+
+fn seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |age| age.as_secs())
+}
+
+/// Last write of `path`, in seconds since the Unix epoch.
+fn modified(path: &Path) -> io::Result<u64> {
+    Ok(fs::metadata(path)?.modified().map_or(0, seconds))
+}
+
+/// Reads a journal. With `repair`, a torn last record is cut off the file.
+fn read_file(path: &Path, repair: bool) -> io::Result<JournaledStream> {
+    let bytes = fs::read(path)?;
+    let updated = modified(path)?;
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "not a stream journal");
+
+    let mut records = bytes.strip_prefix(MAGIC).ok_or_else(invalid)?;
+    let mut next = || {
+        let (header, rest) = records.split_at_checked(RECORD_HEADER)?;
+        let len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+        let crc = u32::from_le_bytes(header[4..].try_into().unwrap());
+        let (data, rest) = rest.split_at_checked(len)?;
+        if crc32fast::hash(data) != crc {
+            return None;
+        }
+        records = rest;
+        Some(data.to_vec())
+    };
+    let stream_id = next()
+        .and_then(|id| String::from_utf8(id).ok())
+        .ok_or_else(invalid)?;
+    let payloads = std::iter::from_fn(&mut next).collect();
+    let valid = bytes.len() - records.len();
+    if repair && valid < bytes.len() {
+        OpenOptions::new()
+            .write(true)
+            .open(path)?
+            .set_len(valid as u64)?;
+    }
+    Ok(JournaledStream {
+        stream_id,
+        payloads,
+        size: valid as u64,
+        updated,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> Journal {
+        let dir = std::env::temp_dir().join(format!(
+            "streaming-qr-journal-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        Journal::new(dir)
+    }
+
+    #[test]
+    fn appends_and_loads_frames() {
+        let journal = scratch("append");
+        assert_eq!(journal.load("a").unwrap(), None);
+        journal.append("a", b"first").unwrap();
+        journal.append("a", b"second").unwrap();
+        journal.append("b", b"other").unwrap();
+        let stored = journal.load("a").unwrap().unwrap();
+        assert_eq!(stored.stream_id, "a");
+        assert_eq!(stored.payloads, [b"first".to_vec(), b"second".to_vec()]);
+        assert_eq!(journal.load_all().len(), 2);
+        assert!(journal.remove("a").unwrap());
+        assert!(!journal.remove("a").unwrap());
+        assert_eq!(journal.load_all().len(), 1);
+        fs::remove_dir_all(&journal.dir).unwrap();
+    }
+
+    #[test]
+    fn only_replay_cuts_off_a_torn_record() {
+        let journal = scratch("torn");
+        journal.append("a", b"whole").unwrap();
+        let path = journal.path("a");
+        let whole = fs::metadata(&path).unwrap().len();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[9, 0, 0, 0, 1, 2]).unwrap();
+
+        let listed = journal.load_all();
+        assert_eq!(listed[0].payloads, [b"whole".to_vec()]);
+        assert_eq!(fs::metadata(&path).unwrap().len(), whole + 6);
+
+        let (restored, _) = journal.restore(MAX_AGE);
+        assert_eq!(restored[0].payloads, [b"whole".to_vec()]);
+        assert_eq!(fs::metadata(&path).unwrap().len(), whole);
+        journal.append("a", b"next").unwrap();
+        assert_eq!(journal.load("a").unwrap().unwrap().payloads.len(), 2);
+        fs::remove_dir_all(&journal.dir).unwrap();
+    }
+
+    #[test]
+    fn deletes_expired_journals_instead_of_restoring_them() {
+        let journal = scratch("expire");
+        journal.append("old", b"frame").unwrap();
+        journal.append("new", b"frame").unwrap();
+        let week_ago = SystemTime::now() - MAX_AGE - Duration::from_secs(60);
+        OpenOptions::new()
+            .write(true)
+            .open(journal.path("old"))
+            .unwrap()
+            .set_modified(week_ago)
+            .unwrap();
+
+        let (restored, errors) = journal.restore(MAX_AGE);
+        assert_eq!(restored.len(), 1);
+        assert_eq!(restored[0].stream_id, "new");
+        assert!(errors.is_empty());
+        assert_eq!(journal.load("old").unwrap(), None);
+        fs::remove_dir_all(&journal.dir).unwrap();
+    }
+
+    #[test]
+    fn recovers_from_a_torn_stream_id_record() {
+        let journal = scratch("header");
+        fs::create_dir_all(&journal.dir).unwrap();
+        let mut torn = MAGIC.to_vec();
+        push_record(&mut torn, b"a");
+        torn.pop();
+        fs::write(journal.path("a"), &torn).unwrap();
+        journal.append("a", b"frame").unwrap();
+        assert_eq!(
+            journal.load("a").unwrap().unwrap().payloads,
+            [b"frame".to_vec()]
+        );
+
+        fs::write(journal.path("b"), &torn).unwrap();
+        let (restored, errors) = journal.restore(MAX_AGE);
+        assert_eq!(restored.len(), 1);
+        assert!(errors.is_empty());
+        assert!(!journal.path("b").exists());
+        fs::remove_dir_all(&journal.dir).unwrap();
+    }
+}
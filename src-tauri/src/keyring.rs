@@ -0,0 +1,144 @@
+//! Trusted sender public keys, kept as a JSON file in the app data dir.
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::signature::{self, PUBLIC_KEY_LEN};
+
+pub const FILE_NAME: &str = "trusted_senders.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrustedSender {
+    pub name: String,
+    /// Ed25519 public key, hex encoded.
+    pub public_key: String,
+    /// Seconds since the Unix epoch.
+    pub added: u64,
+}
+
+#[derive(Debug)]
+pub struct Keyring {
+    path: PathBuf,
+    senders: Vec<TrustedSender>,
+}
+
+impl Keyring {
+    /// Loads the keyring at `path`, starting empty when the file is missing.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, String> {
+        let path = path.into();
+        let senders = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| format!("Failed to read keyring: {}", e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(format!("Failed to read keyring: {}", e)),
+        };
+        Ok(Self { path, senders })
+    }
+
+    fn save(&self) -> Result<(), String> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("Failed to save keyring: {}", e))?;
+        }
+        let json = serde_json::to_vec_pretty(&self.senders).expect("keyring serializes");
+        fs::write(&self.path, json).map_err(|e| format!("Failed to save keyring: {}", e))
+    }
+
+    pub fn list(&self) -> &[TrustedSender] {
+        &self.senders
+    }
+
+    pub fn find(&self, public_key: &[u8; PUBLIC_KEY_LEN]) -> Option<&TrustedSender> {
+        let public_key = hex::encode(public_key);
+        self.senders
+            .iter()
+            .find(|sender| sender.public_key == public_key)
+    }
+
+    /// Trusts `public_key` (hex or base64) under `name`, renaming the entry
+    /// if the key is already trusted.
+    pub fn add(&mut self, name: &str, public_key: &str) -> Result<TrustedSender, String> {
+        let key = signature::parse_public_key(public_key)
+            .ok_or_else(|| "Invalid Ed25519 public key".to_string())?;
+        let added = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let name = name.trim().to_string();
+        let public_key = hex::encode(key);
+
+        let index = match self
+            .senders
+            .iter()
+            .position(|existing| existing.public_key == public_key)
+        {
+            Some(index) => {
+                self.senders[index].name = name;
+                index
+            }
+            None => {
+                self.senders.push(TrustedSender {
+                    name,
+                    public_key,
+                    added,
+                });
+                self.senders.len() - 1
+            }
+        };
+        self.save()?;
+        Ok(self.senders[index].clone())
+    }
+
+    /// Returns whether the key was trusted.
+    pub fn remove(&mut self, public_key: &str) -> Result<bool, String> {
+        let key = signature::parse_public_key(public_key)
+            .ok_or_else(|| "Invalid Ed25519 public key".to_string())?;
+        let key = hex::encode(key);
+        let before = self.senders.len();
+        self.senders.retain(|sender| sender.public_key != key);
+        if self.senders.len() == before {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signature::public_key;
+
+    #[test]
+    fn keeps_trusted_senders_across_loads() {
+        let dir = std::env::temp_dir().join(format!("streaming-qr-keyring-{}", std::process::id()));
+        let path = dir.join(FILE_NAME);
+        let alice = public_key(&[1; 32]);
+        let bob = public_key(&[2; 32]);
+
+        let mut keyring = Keyring::load(&path).unwrap();
+        assert!(keyring.list().is_empty());
+        keyring.add(" Alice ", &hex::encode(alice)).unwrap();
+        keyring.add("Bob", &hex::encode(bob)).unwrap();
+        // The same key again only renames its entry.
+        let renamed = keyring.add("Alice B.", &hex::encode(alice)).unwrap();
+        assert_eq!(renamed.name, "Alice B.");
+        assert!(keyring.add("Eve", "not a key").is_err());
+
+        let mut loaded = Keyring::load(&path).unwrap();
+        assert_eq!(loaded.list(), keyring.list());
+        assert_eq!(loaded.find(&alice).unwrap().name, "Alice B.");
+        assert!(loaded.remove(&hex::encode(bob)).unwrap());
+        assert!(!loaded.remove(&hex::encode(bob)).unwrap());
+
+        let reloaded = Keyring::load(&path).unwrap();
+        assert!(reloaded.find(&bob).is_none());
+        assert_eq!(reloaded.list().len(), 1);
+        fs::write(&path, b"not json").unwrap();
+        assert!(Keyring::load(&path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,204 @@
+//! What the receiver learns about the file behind a stream.
+//!
+//! Senders that stream a file put its name, MIME type, size and
+//! modification time in front of the payload, so the receiver can save it
+//! under its own name instead of inventing one. Being part of the payload,
+//! the metadata is compressed, encrypted and sealed along with the file and
+//! covered by the digest and signature. All fields are optional.
+//!
+//! ```text
+//! len   u32 LE  length of the metadata
+//! json  len     the metadata as JSON
+//! data  bytes   the file
+//! ```
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::output;
+
+/// Longest file name accepted, in bytes.
+pub const MAX_NAME_LEN: usize = 255;
+/// Longest MIME type accepted, in bytes.
+pub const MAX_MIME_LEN: usize = 127;
+/// Longest metadata accepted in front of a payload, in bytes.
+const MAX_ENCODED_LEN: usize = 4096;
+
+/// MIME types of common extensions, for senders that only know the name.
+const MIME_TYPES: &[(&str, &str)] = &[
+    ("txt", "text/plain"),
+    ("md", "text/markdown"),
+    ("csv", "text/csv"),
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("css", "text/css"),
+    ("js", "text/javascript"),
+    ("json", "application/json"),
+    ("xml", "application/xml"),
+    ("yaml", "application/yaml"),
+    ("yml", "application/yaml"),
+    ("toml", "application/toml"),
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tar", "application/x-tar"),
+    ("zst", "application/zstd"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("svg", "image/svg+xml"),
+    ("mp3", "audio/mpeg"),
+    ("wav", "audio/wav"),
+    ("mp4", "video/mp4"),
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FileMetadata {
+    /// File name without any folder.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime: Option<String>,
+    /// Size of the original file in bytes, before compression.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Last modification, in seconds since the Unix epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+}
+
+impl FileMetadata {
+    /// Metadata of the file at `path`.
+    pub fn from_path(path: &Path) -> std::io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned());
+        Ok(Self {
+            mime: name.as_deref().and_then(guess_mime).map(str::to_string),
+            name,
+            size: Some(metadata.len()),
+            modified: metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|age| age.as_secs()),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Whether the fields stay within the limits a receiver accepts.
+    pub fn is_valid(&self) -> bool {
+        self.name
+            .as_ref()
+            .is_none_or(|name| name.len() <= MAX_NAME_LEN)
+            && self
+                .mime
+                .as_ref()
+                .is_none_or(|mime| mime.len() <= MAX_MIME_LEN)
+    }
+
+    /// The name to save under: the last component of `name`, cleaned like
+    /// any output file name.
+    pub fn file_name(&self) -> Option<String> {
+        let name = self.name.as_deref()?.rsplit(['/', '\\']).next()?;
+        match name {
+            "" | "." | ".." => None,
+            name => Some(output::sanitize_file_name(name)),
+        }
+    }
+
+    pub fn modified_time(&self) -> Option<SystemTime> {
+        UNIX_EPOCH.checked_add(Duration::from_secs(self.modified?))
+    }
+}
+
+/// Puts `file` in front of `data`.
+pub fn wrap(file: &FileMetadata, data: &[u8]) -> Vec<u8> {
+    let json = serde_json::to_vec(file).expect("metadata serializes");
+    let mut payload = Vec::with_capacity(4 + json.len() + data.len());
+    payload.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    payload.extend_from_slice(&json);
+    payload.extend_from_slice(data);
+    payload
+}
+
+/// Splits a [`wrap`]ped payload into the metadata and the length of the
+/// prefix it took. `None` when the prefix is malformed or out of limits.
+pub fn split(payload: &[u8]) -> Option<(FileMetadata, usize)> {
+    let (len, rest) = payload.split_first_chunk::<4>()?;
+    let len = u32::from_le_bytes(*len) as usize;
+    if len > MAX_ENCODED_LEN {
+        return None;
+    }
+    let file: FileMetadata = serde_json::from_slice(rest.get(..len)?).ok()?;
+    file.is_valid().then_some((file, 4 + len))
+}
+
+/// MIME type of a file name by its extension.
+pub fn guess_mime(name: &str) -> Option<&'static str> {
+    let (_, extension) = name.rsplit_once('.')?;
+    MIME_TYPES
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(extension))
+        .map(|(_, mime)| *mime)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_and_splits_the_metadata() {
+        let file = FileMetadata {
+            name: Some("notes.txt".to_string()),
+            modified: Some(1_700_000_000),
+            ..FileMetadata::default()
+        };
+        let payload = wrap(&file, b"contents");
+        let (split_file, prefix) = split(&payload).unwrap();
+        assert_eq!(split_file, file);
+        assert_eq!(&payload[prefix..], b"contents");
+    }
+
+    #[test]
+    fn rejects_malformed_metadata() {
+        let payload = wrap(&FileMetadata::default(), b"");
+        assert_eq!(split(&payload[..payload.len() - 1]), None);
+        assert_eq!(split(&[2, 0, 0]), None);
+        assert_eq!(split(b"\x03\x00\x00\x00\"x\""), None);
+        assert_eq!(split(&u32::MAX.to_le_bytes()), None);
+        let long = FileMetadata {
+            name: Some("x".repeat(MAX_NAME_LEN + 1)),
+            ..FileMetadata::default()
+        };
+        assert_eq!(split(&wrap(&long, b"")), None);
+    }
+
+    #[test]
+    fn cleans_the_name_to_save_under() {
+        let file = |name: &str| FileMetadata {
+            name: Some(name.to_string()),
+            ..FileMetadata::default()
+        };
+        assert_eq!(
+            file("../../etc/passwd").file_name(),
+            Some("passwd".to_string())
+        );
+        assert_eq!(
+            file("C:\\a\\b?.txt").file_name(),
+            Some("b_.txt".to_string())
+        );
+        assert_eq!(file("dir/..").file_name(), None);
+        assert_eq!(guess_mime("Report.PDF"), Some("application/pdf"));
+    }
+}
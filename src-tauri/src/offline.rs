@@ -0,0 +1,316 @@
+//! Decoding streams from image and animation files, without a camera.
+//!
+//! Accepts still images (PNG, JPEG, GIF), animated GIF and APNG files, and
+//! folders of them, read in file name order without recursing. Every frame
+//! is scanned with [`color::scan`], which also separates layered screens,
+//! and every symbol goes through [`StreamStore::process_payload`], the same
+//! reassembly the camera uses.
+
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::{AnimationDecoder, ImageFormat};
+use serde::Serialize;
+
+use crate::color;
+use crate::protocol::{
+    ChunkStatus, Credentials, Progress, ProtocolError, ReconstructedStream, StreamStore,
+};
+
+/// File extensions picked up when a folder is given.
+pub const EXTENSIONS: &[&str] = &["png", "apng", "jpg", "jpeg", "gif"];
+
+/// What happened to one input file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileReport {
+    pub path: String,
+    /// Frames read; 1 for a still image.
+    pub frames: usize,
+    /// QR symbols found over all frames.
+    pub symbols: usize,
+    /// Symbols that added a header or chunk to a stream.
+    pub accepted: usize,
+    pub duplicates: usize,
+    /// Symbols that could not be read or that the protocol rejected.
+    pub rejected: usize,
+    pub stream_ids: Vec<String>,
+    /// Why symbols were rejected, or why the file could not be read.
+    pub errors: Vec<String>,
+}
+
+/// Outcome of one stream seen in the input files.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DecodedStream {
+    pub stream_id: String,
+    pub progress: Option<Progress>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<ReconstructedStream>,
+    /// Base64 of the reconstructed payload.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ProtocolError>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OfflineReport {
+    pub files: Vec<FileReport>,
+    pub streams: Vec<DecodedStream>,
+}
+
+/// Expands folders into their image files, sorted by name.
+pub fn collect_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            files.push(path.clone());
+            continue;
+        }
+        let entries =
+            fs::read_dir(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let mut images: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && has_image_extension(path))
+            .collect();
+        images.sort();
+        files.extend(images);
+    }
+    Ok(files)
+}
+
+fn has_image_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// Calls `each` with the width, height and RGBA pixels of every frame of
+/// an image or animation, and returns the number of frames.
+pub fn read_frames(
+    bytes: &[u8],
+    mut each: impl FnMut(usize, usize, &[u8]),
+) -> Result<usize, String> {
+    let format = image::guess_format(bytes).map_err(|e| e.to_string())?;
+    let frames = match format {
+        ImageFormat::Gif => GifDecoder::new(Cursor::new(bytes))
+            .map_err(|e| e.to_string())?
+            .into_frames(),
+        ImageFormat::Png => {
+            let decoder = PngDecoder::new(Cursor::new(bytes)).map_err(|e| e.to_string())?;
+            if !decoder.is_apng().map_err(|e| e.to_string())? {
+                return read_still(bytes, each);
+            }
+            decoder.apng().map_err(|e| e.to_string())?.into_frames()
+        }
+        _ => return read_still(bytes, each),
+    };
+
+    let mut count = 0;
+    for frame in frames {
+        let image = frame
+            .map_err(|e| format!("frame {}: {}", count + 1, e))?
+            .into_buffer();
+        each(image.width() as usize, image.height() as usize, &image);
+        count += 1;
+    }
+    Ok(count)
+}
+
+fn read_still(bytes: &[u8], mut each: impl FnMut(usize, usize, &[u8])) -> Result<usize, String> {
+    let image = image::load_from_memory(bytes)
+        .map_err(|e| e.to_string())?
+        .to_rgba8();
+    each(image.width() as usize, image.height() as usize, &image);
+    Ok(1)
+}
+
+/// Scans every frame of the file at `path` into `store`. The store is only
+/// locked while the symbols of a frame are inserted.
+pub fn decode_file(store: &Mutex<StreamStore>, path: &Path) -> FileReport {
+    let mut report = FileReport {
+        path: path.display().to_string(),
+        ..FileReport::default()
+    };
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            report.errors.push(format!("Failed to read file: {}", e));
+            return report;
+        }
+    };
+
+    let mut frame = 0;
+    let result = read_frames(&bytes, |width, height, rgba| {
+        frame += 1;
+        let symbols = color::scan(width, height, rgba, 4);
+        report.symbols += symbols.len();
+        let mut store = store.lock().unwrap();
+        for symbol in symbols {
+            let outcome = symbol
+                .content
+                .and_then(|content| store.process_payload(&content).map_err(|e| e.to_string()));
+            match outcome {
+                Ok(outcome) => {
+                    if outcome.status == ChunkStatus::Duplicate {
+                        report.duplicates += 1;
+                    } else {
+                        report.accepted += 1;
+                    }
+                    if !report.stream_ids.contains(&outcome.stream_id) {
+                        report.stream_ids.push(outcome.stream_id);
+                    }
+                }
+                Err(e) => {
+                    report.rejected += 1;
+                    report.errors.push(format!("frame {}: {}", frame, e));
+                }
+            }
+        }
+    });
+    match result {
+        Ok(frames) => report.frames = frames,
+        Err(e) => {
+            report.frames = frame;
+            report.errors.push(format!("Failed to decode image: {}", e));
+        }
+    }
+    report
+}
+
+/// Decodes all files (folders expanded) and reconstructs every stream they
+/// contributed to.
+pub fn decode_files(
+    store: &Mutex<StreamStore>,
+    paths: &[PathBuf],
+    credentials: Credentials<'_>,
+) -> Result<OfflineReport, String> {
+    let files: Vec<FileReport> = collect_files(paths)?
+        .iter()
+        .map(|path| decode_file(store, path))
+        .collect();
+
+    let mut stream_ids: Vec<&String> = Vec::new();
+    for id in files.iter().flat_map(|file| &file.stream_ids) {
+        if !stream_ids.contains(&id) {
+            stream_ids.push(id);
+        }
+    }
+
+    let store = store.lock().unwrap();
+    let streams = stream_ids
+        .into_iter()
+        .map(|stream_id| {
+            let (stream, error) = match store.reconstruct_with(stream_id, credentials) {
+                Ok(stream) => (Some(stream), None),
+                Err(e) => (None, Some(e)),
+            };
+            DecodedStream {
+                stream_id: stream_id.clone(),
+                progress: store.progress(stream_id),
+                data: stream.as_ref().map(|stream| BASE64.encode(&stream.data)),
+                stream,
+                error,
+            }
+        })
+        .collect();
+    Ok(OfflineReport { files, streams })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sender::{self, SendOptions};
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "streaming-qr-offline-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Writes every screen of a new stream of `data` as a PNG into `dir`.
+    fn write_screens(dir: &Path, data: &[u8]) -> (String, Vec<PathBuf>) {
+        let send = SendOptions {
+            version: Some(5),
+            ..SendOptions::default()
+        };
+        let (stream_id, screens) = sender::stream_screens(data, &send).unwrap();
+        let paths = screens
+            .iter()
+            .enumerate()
+            .map(|(index, screen)| {
+                let path = dir.join(format!("frame{:02}.png", index));
+                fs::write(&path, screen.picture().to_png(3)).unwrap();
+                path
+            })
+            .collect();
+        (stream_id.to_string(), paths)
+    }
+
+    #[test]
+    fn collects_images_from_folders_and_keeps_given_files() {
+        let dir = scratch("collect");
+        let folder = dir.join("frames");
+        fs::create_dir_all(folder.join("nested")).unwrap();
+        for name in ["b.PNG", "a.gif", "notes.txt", "nested/c.png"] {
+            fs::write(folder.join(name), b"").unwrap();
+        }
+        let single = dir.join("single.jpeg");
+        fs::write(&single, b"").unwrap();
+
+        let files = collect_files(&[single.clone(), folder.clone()]).unwrap();
+        assert_eq!(files, [single, folder.join("a.gif"), folder.join("b.PNG")]);
+        // Missing files are reported when they are read.
+        assert_eq!(collect_files(&[dir.join("missing")]).unwrap().len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn decodes_a_folder_of_png_frames() {
+        let dir = scratch("decode");
+        let data = b"offline frames ".repeat(60);
+        let (stream_id, _) = write_screens(&dir, &data);
+        fs::write(dir.join("readme.md"), b"not a frame").unwrap();
+
+        let store = Mutex::new(StreamStore::new());
+        let report =
+            decode_files(&store, std::slice::from_ref(&dir), Credentials::default()).unwrap();
+        assert!(report.files.iter().all(|file| file.accepted == 1));
+        assert_eq!(report.streams.len(), 1);
+        let stream = &report.streams[0];
+        assert_eq!(stream.stream_id, stream_id);
+        assert_eq!(stream.stream.as_ref().unwrap().data, data);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reports_a_missing_frame_as_incomplete() {
+        let dir = scratch("missing");
+        let (_, paths) = write_screens(&dir, &b"offline frames ".repeat(60));
+        fs::remove_file(&paths[2]).unwrap();
+
+        let store = Mutex::new(StreamStore::new());
+        let report =
+            decode_files(&store, std::slice::from_ref(&dir), Credentials::default()).unwrap();
+        let stream = &report.streams[0];
+        assert!(stream.stream.is_none());
+        assert!(matches!(
+            stream.error,
+            Some(ProtocolError::Incomplete { .. })
+        ));
+        assert_eq!(stream.progress.as_ref().unwrap().missing, [1]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,445 @@
+//! Where decoded files are saved.
+//!
+//! Every save resolves a caller-supplied name under one output folder,
+//! which defaults to the downloads folder and can be changed from the
+//! frontend. Names may hold subfolders, but never climb out of the output
+//! folder: absolute paths and `..` are rejected, and every component is
+//! cleaned of characters that some file system would refuse.
+//!
+//! Files are written to a temporary file next to their destination, synced
+//! and only then moved into place, so a crash never leaves a truncated
+//! file behind. What happens when the name is taken is up to the caller's
+//! [`Overwrite`] policy.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// File in the app data dir holding the chosen output folder.
+pub const FILE_NAME: &str = "output_dir.txt";
+/// Output folder under the app data dir when there is no downloads folder.
+pub const DEFAULT_DIR_NAME: &str = "downloads";
+/// Longest file name component, in bytes.
+const MAX_COMPONENT: usize = 255;
+/// Bytes `.{name}.{token}.tmp` adds to the name of a temporary file.
+const TEMP_EXTRA: usize = 1 + 1 + 12 + 4;
+/// Most `name (n)` variants tried before giving up.
+const MAX_SUFFIX: usize = 9999;
+/// Names Windows reserves for devices, whatever the extension.
+const RESERVED: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// What to do when a file with the chosen name already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Overwrite {
+    /// Leave the existing file alone and fail.
+    Fail,
+    /// Replace the existing file.
+    Replace,
+    /// Save as `name (1).ext`, `name (2).ext` and so on.
+    #[default]
+    Suffix,
+}
+
+/// A file written to the output folder.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedFile {
+    /// Canonical path.
+    pub path: String,
+    pub size: u64,
+    /// Hex SHA-256 of the contents.
+    pub sha256: String,
+}
+
+#[derive(Debug)]
+pub struct OutputDir {
+    /// Where the chosen folder is remembered, if anywhere.
+    path: Option<PathBuf>,
+    default: PathBuf,
+    dir: PathBuf,
+}
+
+impl OutputDir {
+    /// Loads the folder remembered at `path`, or uses `default` when none
+    /// was chosen.
+    pub fn load(path: impl Into<PathBuf>, default: impl Into<PathBuf>) -> Result<Self, String> {
+        let path = path.into();
+        let default = default.into();
+        let dir = match fs::read_to_string(&path) {
+            Ok(text) if !text.trim().is_empty() => PathBuf::from(text.trim()),
+            Ok(_) => default.clone(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => default.clone(),
+            Err(e) => return Err(format!("Failed to read output folder: {}", e)),
+        };
+        Ok(Self {
+            path: Some(path),
+            default,
+            dir,
+        })
+    }
+
+    /// Saves to `dir` without remembering any choice, as the CLI does.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let dir = dir.into();
+        Self {
+            path: None,
+            default: dir.clone(),
+            dir,
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Makes `dir` the output folder, or goes back to the default with
+    /// `None`. The folder is created if needed and remembered canonically.
+    pub fn set(&mut self, dir: Option<&str>) -> Result<&Path, String> {
+        let chosen = match dir {
+            Some(dir) if !Path::new(dir).is_absolute() => {
+                return Err("Output folder must be an absolute path".to_string());
+            }
+            Some(dir) => PathBuf::from(dir),
+            None => self.default.clone(),
+        };
+        fs::create_dir_all(&chosen)
+            .map_err(|e| format!("Failed to create output folder: {}", e))?;
+        let dir = chosen
+            .canonicalize()
+            .map_err(|e| format!("Failed to open output folder: {}", e))?;
+        let saved = match &self.path {
+            None => Ok(()),
+            Some(path) if chosen == self.default => {
+                fs::remove_file(path).or_else(|e| match e.kind() {
+                    std::io::ErrorKind::NotFound => Ok(()),
+                    _ => Err(e),
+                })
+            }
+            Some(path) => {
+                let text = dir.to_string_lossy().into_owned();
+                path.parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|()| fs::write(path, text))
+            }
+        };
+        saved.map_err(|e| format!("Failed to save output folder: {}", e))?;
+        self.dir = dir;
+        Ok(&self.dir)
+    }
+
+    /// The path `name` saves to: inside the output folder, with its parent
+    /// folders created. Fails on names that would leave the folder,
+    /// including through a symlink.
+    pub fn resolve(&self, name: &str) -> Result<PathBuf, String> {
+        let relative = sanitize_path(name)?;
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("Failed to create output folder: {}", e))?;
+        let root = self
+            .dir
+            .canonicalize()
+            .map_err(|e| format!("Failed to open output folder: {}", e))?;
+        let path = root.join(&relative);
+        let parent = path.parent().unwrap_or(&root);
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create folder: {}", e))?;
+        let parent = parent
+            .canonicalize()
+            .map_err(|e| format!("Failed to open folder: {}", e))?;
+        if !parent.starts_with(&root) {
+            return Err(format!("{} is outside the output folder", name));
+        }
+        let file_name = path.file_name().expect("sanitized paths end in a name");
+        let path = parent.join(file_name);
+        if path.is_symlink() {
+            return Err(format!("{} is a symbolic link", name));
+        }
+        Ok(path)
+    }
+
+    /// Saves `data` as `name` atomically, following `overwrite` when the
+    /// name is taken, and dates it `modified` if given.
+    pub fn write(
+        &self,
+        name: &str,
+        data: &[u8],
+        overwrite: Overwrite,
+        modified: Option<SystemTime>,
+    ) -> Result<SavedFile, String> {
+        let path = self.resolve(name)?;
+        let save_error = |e: io::Error| format!("Failed to save {}: {}", name, e);
+        let temp = write_temp(&path, data, modified).map_err(save_error)?;
+        let placed = place(&temp, &path, overwrite);
+        if placed.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        let path = placed.map_err(|e| match e.kind() {
+            io::ErrorKind::AlreadyExists => format!("{} already exists", name),
+            _ => save_error(e),
+        })?;
+        sync_dir(&path);
+        let path = path.canonicalize().map_err(save_error)?;
+        Ok(SavedFile {
+            path: path.to_string_lossy().into_owned(),
+            size: data.len() as u64,
+            sha256: hex::encode(Sha256::digest(data)),
+        })
+    }
+}
+
+/// Writes and syncs `data` to a new hidden file beside `path`, named after
+/// it but shortened so that the temporary name is legal too.
+fn write_temp(path: &Path, data: &[u8], modified: Option<SystemTime>) -> io::Result<PathBuf> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let name = truncate(name.into_owned(), MAX_COMPONENT - TEMP_EXTRA);
+    loop {
+        let mut token = [0u8; 6];
+        getrandom::fill(&mut token).expect("system random source unavailable");
+        let temp = dir.join(format!(".{}.{}.tmp", name, hex::encode(token)));
+        let mut file = match OpenOptions::new().write(true).create_new(true).open(&temp) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        };
+        let written = file.write_all(data).and_then(|()| {
+            if let Some(time) = modified {
+                // Not every file system keeps times; the data matters more.
+                let _ = file.set_modified(time);
+            }
+            file.sync_all()
+        });
+        if let Err(e) = written {
+            let _ = fs::remove_file(&temp);
+            return Err(e);
+        }
+        return Ok(temp);
+    }
+}
+
+/// Moves `temp` to `path`, or to the first free suffixed name, and returns
+/// where it went.
+fn place(temp: &Path, path: &Path, overwrite: Overwrite) -> io::Result<PathBuf> {
+    match overwrite {
+        Overwrite::Replace => fs::rename(temp, path).map(|()| path.to_path_buf()),
+        Overwrite::Fail => rename_new(temp, path).map(|()| path.to_path_buf()),
+        Overwrite::Suffix => {
+            for n in 0..=MAX_SUFFIX {
+                let candidate = match n {
+                    0 => path.to_path_buf(),
+                    n => with_suffix(path, n),
+                };
+                match rename_new(temp, &candidate) {
+                    Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                    placed => return placed.map(|()| candidate),
+                }
+            }
+            Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "every numbered name is taken",
+            ))
+        }
+    }
+}
+
+/// Renames `temp` to `path` unless `path` exists. A hard link makes the
+/// check and the move one step; file systems without hard links, like the
+/// FAT of some SD cards, fall back to checking first.
+fn rename_new(temp: &Path, path: &Path) -> io::Result<()> {
+    match fs::hard_link(temp, path) {
+        Ok(()) => fs::remove_file(temp),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Err(e),
+        Err(_) if path.symlink_metadata().is_ok() => {
+            Err(io::Error::from(io::ErrorKind::AlreadyExists))
+        }
+        Err(_) => fs::rename(temp, path),
+    }
+}
+
+/// `name (n).ext` beside `path`.
+fn with_suffix(path: &Path, n: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{} ({}).{}", stem, n, extension.to_string_lossy()),
+        None => format!("{} ({})", stem, n),
+    };
+    path.with_file_name(name)
+}
+
+/// Makes a rename into `path`'s folder survive a power loss. Only Unix can
+/// sync a folder, and a failure here leaves a written file regardless.
+fn sync_dir(path: &Path) {
+    if cfg!(unix) {
+        if let Some(dir) = path.parent() {
+            let _ = File::open(dir).and_then(|dir| dir.sync_all());
+        }
+    }
+}
+
+/// Turns `name` into a relative path of cleaned components. Both `/` and
+/// `\` separate folders, whatever the platform.
+pub fn sanitize_path(name: &str) -> Result<PathBuf, String> {
+    let portable = name.replace('\\', "/");
+    let has_drive = matches!(portable.as_bytes(), [drive, b':', ..] if drive.is_ascii_alphabetic());
+    let is_absolute = has_drive || Path::new(&portable).has_root();
+    if is_absolute {
+        return Err(format!("{} is an absolute path", name));
+    }
+    let mut path = PathBuf::new();
+    for component in Path::new(&portable).components() {
+        match component {
+            Component::Normal(part) => path.push(sanitize_file_name(&part.to_string_lossy())),
+            Component::CurDir => {}
+            Component::ParentDir => return Err(format!("{} leaves the output folder", name)),
+            Component::RootDir | Component::Prefix(_) => {
+                return Err(format!("{} is an absolute path", name));
+            }
+        }
+    }
+    if path.as_os_str().is_empty() {
+        return Err("File name is empty".to_string());
+    }
+    Ok(path)
+}
+
+/// Replaces characters that are not allowed in file names on common file
+/// systems, trims the dots and spaces Windows drops and steers clear of
+/// its device names.
+pub fn sanitize_file_name(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let mut cleaned = cleaned
+        .trim_end_matches(['.', ' '])
+        .trim_start()
+        .to_string();
+    if cleaned.is_empty() {
+        cleaned.push('_');
+    }
+    let stem = cleaned.split('.').next().unwrap_or_default();
+    if RESERVED
+        .iter()
+        .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+    {
+        cleaned.insert(0, '_');
+    }
+    truncate(cleaned, MAX_COMPONENT)
+}
+
+/// Shortens `name` to at most `max` bytes, keeping its extension.
+fn truncate(name: String, max: usize) -> String {
+    if name.len() <= max {
+        return name;
+    }
+    let extension = name
+        .rfind('.')
+        .filter(|&dot| dot > 0 && name.len() - dot <= 16)
+        .map_or("", |dot| &name[dot..]);
+    let mut end = max - extension.len();
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{}", &name[..end], extension)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> OutputDir {
+        let dir = std::env::temp_dir().join(format!(
+            "streaming-qr-output-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        OutputDir::new(dir)
+    }
+
+    #[test]
+    fn keeps_names_inside_the_output_folder() {
+        assert_eq!(
+            sanitize_path("docs\\2024/./report.pdf").unwrap(),
+            Path::new("docs/2024/report.pdf")
+        );
+        for name in [
+            "/etc/passwd",
+            "\\\\server\\share",
+            "C:\\x",
+            "c:x",
+            "a/../../b",
+            "",
+            ".",
+        ] {
+            assert!(sanitize_path(name).is_err(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn cleans_file_names() {
+        assert_eq!(sanitize_file_name("a<b>:c?.txt"), "a_b__c_.txt");
+        assert_eq!(sanitize_file_name(" notes. . "), "notes");
+        assert_eq!(sanitize_file_name("con.txt"), "_con.txt");
+        assert_eq!(sanitize_file_name("..."), "_");
+        let long = format!("{}.pdf", "é".repeat(200));
+        let cleaned = sanitize_file_name(&long);
+        assert!(cleaned.len() <= MAX_COMPONENT && cleaned.ends_with("é.pdf"));
+    }
+
+    #[test]
+    fn follows_the_overwrite_policy() {
+        let output = scratch("overwrite");
+        let first = output
+            .write("a.txt", b"one", Overwrite::Fail, None)
+            .unwrap();
+        assert_eq!(
+            output.write("a.txt", b"two", Overwrite::Fail, None),
+            Err("a.txt already exists".to_string())
+        );
+        let suffixed = output
+            .write("a.txt", b"two", Overwrite::Suffix, None)
+            .unwrap();
+        assert!(suffixed.path.ends_with("a (1).txt"));
+        let replaced = output
+            .write("a.txt", b"three", Overwrite::Replace, None)
+            .unwrap();
+        assert_eq!(replaced.path, first.path);
+        assert_eq!(fs::read(&first.path).unwrap(), b"three");
+        assert_eq!(fs::read(&suffixed.path).unwrap(), b"two");
+        // Only the two files are left, no temporary ones.
+        assert_eq!(fs::read_dir(output.dir()).unwrap().count(), 2);
+        fs::remove_dir_all(output.dir()).unwrap();
+    }
+
+    #[test]
+    fn saves_names_of_the_longest_legal_length() {
+        let output = scratch("long");
+        let name = format!("{}.bin", "x".repeat(MAX_COMPONENT - 4));
+        let saved = output.write(&name, b"data", Overwrite::Fail, None).unwrap();
+        assert!(saved.path.ends_with(&name));
+        fs::remove_dir_all(output.dir()).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_symlinks_out_of_the_folder() {
+        let output = scratch("symlink");
+        fs::create_dir_all(output.dir()).unwrap();
+        std::os::unix::fs::symlink(std::env::temp_dir(), output.dir().join("out")).unwrap();
+        assert!(output.resolve("out/escape.txt").is_err());
+        std::os::unix::fs::symlink("/nonexistent", output.dir().join("link")).unwrap();
+        assert!(output.resolve("link").is_err());
+        fs::remove_dir_all(output.dir()).unwrap();
+    }
+}
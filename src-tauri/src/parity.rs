@@ -0,0 +1,238 @@
+//! Systematic Reed–Solomon parity for plain chunk streams.
+//!
+//! Data chunks are grouped `group_size` at a time (the last group may be
+//! shorter) and every group gets `parity` extra chunks. Parity chunks are
+//! numbered after the data chunks: group `g` owns the sequence numbers
+//! `total + g * parity .. total + (g + 1) * parity`. Any `parity` chunks of
+//! a group may go missing and still be rebuilt from the rest.
+//!
+//! All shards of a group are as long as a full chunk; the short last data
+//! chunk is zero-padded for encoding and trimmed again using the stream
+//! `length`. The missing tail of a short last group counts as zero shards.
+
+use reed_solomon_erasure::galois_8::ReedSolomon;
+
+/// Reed–Solomon over GF(2^8) allows at most 256 shards per group.
+const MAX_SHARDS: u32 = 256;
+
+/// Where data and parity chunks of a stream live in its sequence space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParityLayout {
+    pub total: u32,
+    pub group_size: u32,
+    pub parity: u32,
+}
+
+impl ParityLayout {
+    /// Returns `None` for parameters Reed–Solomon cannot encode.
+    pub fn new(total: u32, group_size: u32, parity: u32) -> Option<Self> {
+        let valid = total > 0
+            && group_size > 0
+            && parity > 0
+            && group_size.checked_add(parity)? <= MAX_SHARDS;
+        valid.then_some(Self {
+            total,
+            group_size,
+            parity,
+        })
+    }
+
+    pub fn groups(&self) -> u32 {
+        self.total.div_ceil(self.group_size)
+    }
+
+    /// Total number of sequence numbers, data and parity together.
+    pub fn sequence_count(&self) -> u32 {
+        self.total + self.groups() * self.parity
+    }
+
+    pub fn is_parity(&self, sequence: u32) -> bool {
+        sequence >= self.total && sequence < self.sequence_count()
+    }
+
+    /// Group that a data or parity sequence number belongs to.
+    pub fn group_of(&self, sequence: u32) -> u32 {
+        if sequence < self.total {
+            sequence / self.group_size
+        } else {
+            (sequence - self.total) / self.parity
+        }
+    }
+
+    /// Data sequence numbers of `group`.
+    pub fn data_range(&self, group: u32) -> std::ops::Range<u32> {
+        let start = group * self.group_size;
+        start..(start + self.group_size).min(self.total)
+    }
+
+    /// Parity sequence numbers of `group`.
+    pub fn parity_range(&self, group: u32) -> std::ops::Range<u32> {
+        let start = self.total + group * self.parity;
+        start..start + self.parity
+    }
+
+    fn codec(&self) -> ReedSolomon {
+        ReedSolomon::new(self.group_size as usize, self.parity as usize)
+            .expect("layout parameters were validated")
+    }
+}
+
+fn padded(chunk: &[u8], shard_size: usize) -> Vec<u8> {
+    let mut shard = chunk.to_vec();
+    shard.resize(shard_size, 0);
+    shard
+}
+
+/// Computes every parity chunk of a stream, in sequence order.
+///
+/// `chunks` are the data chunks; all but the last must be `shard_size` long.
+pub fn encode(layout: &ParityLayout, chunks: &[Vec<u8>], shard_size: usize) -> Vec<Vec<u8>> {
+    let codec = layout.codec();
+    let mut parity = Vec::with_capacity((layout.groups() * layout.parity) as usize);
+
+    for group in 0..layout.groups() {
+        let mut shards: Vec<Vec<u8>> = (0..layout.group_size)
+            .map(|offset| {
+                let sequence = group * layout.group_size + offset;
+                match chunks.get(sequence as usize) {
+                    Some(chunk) if sequence < layout.total => padded(chunk, shard_size),
+                    _ => vec![0; shard_size],
+                }
+            })
+            .collect();
+        shards.extend((0..layout.parity).map(|_| vec![0; shard_size]));
+
+        codec
+            .encode(&mut shards)
+            .expect("shards are equally sized");
+        parity.extend(shards.drain(layout.group_size as usize..));
+    }
+    parity
+}
+
+/// Rebuilds the missing data chunks of one group.
+///
+/// `data` and `parity` hold the received chunks of the group in order
+/// (`None` where missing). Returns the rebuilt data chunks, padded to
+/// `shard_size`, or `None` while too few chunks are present.
+pub fn recover(
+    layout: &ParityLayout,
+    group: u32,
+    data: &[Option<&[u8]>],
+    parity: &[Option<&[u8]>],
+    shard_size: usize,
+) -> Option<Vec<Vec<u8>>> {
+    let real = layout.data_range(group).len();
+    let mut shards: Vec<Option<Vec<u8>>> = (0..layout.group_size as usize)
+        .map(|offset| match data.get(offset) {
+            _ if offset >= real => Some(vec![0; shard_size]),
+            Some(Some(chunk)) => Some(padded(chunk, shard_size)),
+            _ => None,
+        })
+        .chain(
+            (0..layout.parity as usize)
+                .map(|index| parity.get(index).copied().flatten().map(<[u8]>::to_vec)),
+        )
+        .collect();
+
+    let present = shards.iter().filter(|shard| shard.is_some()).count();
+    if present < layout.group_size as usize {
+        return None;
+    }
+
+    layout.codec().reconstruct_data(&mut shards).ok()?;
+    Some(
+        shards
+            .into_iter()
+            .take(real)
+            .map(|shard| shard.unwrap_or_default())
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ten chunks of eight bytes, the last one short.
+    fn chunks() -> Vec<Vec<u8>> {
+        (0..10u8)
+            .map(|index| {
+                let len = if index == 9 { 3 } else { 8 };
+                (0..len).map(|offset| index * 16 + offset).collect()
+            })
+            .collect()
+    }
+
+    fn slices<'a>(chunks: &'a [Vec<u8>], lost: &[usize], offset: usize) -> Vec<Option<&'a [u8]>> {
+        chunks
+            .iter()
+            .enumerate()
+            .map(|(index, chunk)| (!lost.contains(&(index + offset))).then_some(chunk.as_slice()))
+            .collect()
+    }
+
+    #[test]
+    fn lays_out_groups_after_the_data() {
+        let layout = ParityLayout::new(10, 4, 2).unwrap();
+        assert_eq!(layout.groups(), 3);
+        assert_eq!(layout.sequence_count(), 16);
+        assert_eq!(layout.data_range(2), 8..10);
+        assert_eq!(layout.parity_range(1), 12..14);
+        assert_eq!(layout.group_of(9), 2);
+        assert_eq!(layout.group_of(13), 1);
+        assert!(layout.is_parity(10) && !layout.is_parity(9) && !layout.is_parity(16));
+
+        assert!(ParityLayout::new(0, 4, 2).is_none());
+        assert!(ParityLayout::new(10, 0, 2).is_none());
+        assert!(ParityLayout::new(10, 4, 0).is_none());
+        assert!(ParityLayout::new(10, 250, 7).is_none());
+    }
+
+    #[test]
+    fn recovers_up_to_parity_lost_chunks_per_group() {
+        let chunks = chunks();
+        let layout = ParityLayout::new(10, 4, 2).unwrap();
+        let parity = encode(&layout, &chunks, 8);
+        assert_eq!(parity.len(), 6);
+
+        // Group 0 loses two data chunks, the short last group one data and
+        // one parity chunk.
+        let rebuilt = recover(
+            &layout,
+            0,
+            &slices(&chunks[0..4], &[1, 3], 0),
+            &slices(&parity[0..2], &[], 0),
+            8,
+        )
+        .unwrap();
+        assert_eq!(rebuilt, chunks[0..4]);
+
+        let rebuilt = recover(
+            &layout,
+            2,
+            &slices(&chunks[8..10], &[9], 8),
+            &slices(&parity[4..6], &[4], 4),
+            8,
+        )
+        .unwrap();
+        assert_eq!(rebuilt[0], chunks[8]);
+        assert_eq!(&rebuilt[1][..3], chunks[9].as_slice());
+        assert_eq!(&rebuilt[1][3..], &[0; 5]);
+    }
+
+    #[test]
+    fn gives_up_when_too_many_chunks_are_lost() {
+        let chunks = chunks();
+        let layout = ParityLayout::new(10, 4, 2).unwrap();
+        let parity = encode(&layout, &chunks, 8);
+        let rebuilt = recover(
+            &layout,
+            1,
+            &slices(&chunks[4..8], &[4, 5], 4),
+            &slices(&parity[2..4], &[2], 2),
+            8,
+        );
+        assert_eq!(rebuilt, None);
+    }
+}
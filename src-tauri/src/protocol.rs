@@ -0,0 +1,2123 @@
+//! QR stream protocol
+//!
+//! Each QR code carries a JSON object with:
+//! - id: unique stream identifier
+//! - seq: sequence number (0-based)
+//! - total: total number of chunks, at most [`MAX_TOTAL`]
+//! - data: base64 encoded chunk data
+//! - checksum: CRC32 checksum of the decoded chunk data
+//! - digest: optional SHA-256 of the whole payload (hex)
+//!
+//! A header chunk (`"type": "header"`) carries only the stream-level fields
+//! (`id`, `total`, `digest`); the same fields may instead be repeated on
+//! every data chunk. When a digest is known the reconstructed payload is
+//! checked against it and only marked verified when it matches. Data chunks
+//! of a stream whose header has a digest say so (`"hasDigest": true`), and
+//! such a stream is not complete before the digest arrived, so that losing
+//! the header frame cannot pass it off as unverified.
+//!
+//! Fountain-coded streams (`"mode": "lt"`) reuse the same fields, where
+//! `total` is the number of source blocks, `length` the payload size in
+//! bytes and `seed` (defaulting to `seq`) selects which blocks a droplet
+//! combines; see [`crate::fountain`]. Such a stream completes once enough
+//! droplets were caught, whichever ones they are.
+//!
+//! Plain chunk streams may add Reed–Solomon parity chunks (`groupSize`
+//! and `parity` in the header, plus the payload `length`); parity chunks
+//! are numbered after the data chunks and let the receiver rebuild up to
+//! `parity` missing chunks per group. See [`crate::parity`].
+//!
+//! RaptorQ streams (`"mode": "raptorq"`) carry one serialized RFC 6330
+//! encoding packet per chunk plus the base64 `oti` (Object Transmission
+//! Information); `total` is the number of source symbols. See
+//! [`crate::raptor`].
+//!
+//! Chunks whose checksum does not match their data are rejected with
+//! [`ProtocolError::ChecksumMismatch`] and counted as corrupted scans of
+//! their stream instead of being stored.
+//!
+//! A header `compression` of `zstd`, `deflate` or `brotli` means the
+//! payload was compressed as a whole before chunking. Chunk checksums and
+//! the digest cover the compressed bytes; the stream is decompressed only
+//! after it verified, see [`crate::compression`].
+//!
+//! Passphrase-encrypted streams carry an `encryption` object in the header
+//! (`salt`, `nonce`, Argon2id cost parameters); the payload is compressed
+//! first, then encrypted, then chunked. The receiver decrypts only once all
+//! chunks are in and the digest matched. Their data chunks are flagged
+//! `"encrypted": true`, so that a missed header cannot turn the ciphertext
+//! into a plain payload. See [`crate::encryption`].
+//!
+//! Streams sealed to one receiver carry a `recipient` object (the
+//! receiver's `publicKey`, the sender's `ephemeral` key and the `nonce`).
+//! Sealing is the outermost layer, so it is opened first with the
+//! receiver's own key. Data chunks of a sealed stream are flagged
+//! `"sealed": true` and wait for that header. See [`crate::recipient`].
+//!
+//! Data chunks flagged `"file": true` carry a payload led by the metadata
+//! of the file behind it: its `name`, `mime` type, original `size` and
+//! `modified` time in Unix seconds. It is split off after decompression,
+//! so it is as private as the payload and covered by its digest. See
+//! [`crate::metadata`].
+//!
+//! The header may carry an Ed25519 `signature` over the digest and the
+//! signer's `publicKey`, both base64, so that a stream waiting for its
+//! digest also waits for the signature. A later trailer chunk
+//! (`"type": "trailer"`) is accepted as well. Reconstruction checks it and
+//! reports whether the signer is in the receiver's keyring. See
+//! [`crate::signature`].
+//!
+//! A sender showing several symbols per frame (grid mode) tags every data
+//! chunk with its `cell`: the grid `columns` and `rows` plus the `index`
+//! of the cell, row by row. Chunks fill the grid in sequence order, so
+//! chunk `seq` always lands in cell `seq % (columns * rows)` and progress
+//! can show which cells keep failing to scan.
+//!
+//! The same frames can also be sent in the compact binary layout of
+//! [`crate::frame`], carried in QR byte mode; [`parse_payload`] accepts
+//! either.
+//!
+//! [`StreamStore`] keeps the per-stream chunk maps and is held in Tauri
+//! managed state, so the webview only forwards scanned strings. With a
+//! [`Journal`] it also writes every accepted frame to disk and restores
+//! partial streams on the next start.
+
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::time::Instant;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::checksum;
+use crate::compression::{self, Compression, DecompressError};
+use crate::encryption::{self, Encryption};
+use crate::fountain::{DropletStatus, LtDecoder};
+use crate::frame;
+use crate::journal::{self, Journal, StoredStream};
+use crate::keyring::Keyring;
+use crate::metadata::{self, FileMetadata};
+use crate::parity::{self, ParityLayout};
+use crate::raptor::{self, PacketStatus, RaptorDecoder};
+use crate::recipient::{OpenError, ReceiverKey, Recipient};
+use crate::signature::{self, StreamSignature};
+
+/// How the chunks of a stream combine into the payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamMode {
+    /// Every `seq` from 0 to `total - 1` carries one slice of the payload.
+    Chunks,
+    /// LT fountain droplets over `total` source blocks.
+    Lt,
+    /// RFC 6330 source and repair symbols over `total` source symbols.
+    #[serde(rename = "raptorq")]
+    RaptorQ,
+}
+
+impl StreamMode {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "chunks" => Some(StreamMode::Chunks),
+            "lt" | "fountain" => Some(StreamMode::Lt),
+            "raptorq" => Some(StreamMode::RaptorQ),
+            _ => None,
+        }
+    }
+}
+
+/// Stream-level fields, sent in a header chunk or repeated on data chunks.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StreamHeader {
+    pub digest: Option<[u8; 32]>,
+    pub mode: Option<StreamMode>,
+    /// Payload length in bytes, required by fountain modes.
+    pub length: Option<u64>,
+    /// Serialized RaptorQ Object Transmission Information.
+    pub oti: Option<[u8; 12]>,
+    /// Data chunks per Reed–Solomon group.
+    pub group_size: Option<u32>,
+    /// Parity chunks per Reed–Solomon group.
+    pub parity: Option<u32>,
+    /// Algorithm the whole payload was compressed with before chunking.
+    pub compression: Option<Compression>,
+    /// Passphrase encryption parameters.
+    pub encryption: Option<Encryption>,
+    /// Receiver the payload was sealed to.
+    pub recipient: Option<Recipient>,
+    /// Sender signature over `digest`, sent with it in the header chunk.
+    pub signature: Option<StreamSignature>,
+}
+
+impl StreamHeader {
+    /// Folds `other` into `self`, failing when both set a field differently.
+    fn merge(&mut self, other: &StreamHeader) -> Result<(), ProtocolError> {
+        merge_field(&mut self.digest, &other.digest, "digest")?;
+        merge_field(&mut self.mode, &other.mode, "mode")?;
+        merge_field(&mut self.length, &other.length, "length")?;
+        merge_field(&mut self.oti, &other.oti, "oti")?;
+        merge_field(&mut self.group_size, &other.group_size, "groupSize")?;
+        merge_field(&mut self.parity, &other.parity, "parity")?;
+        merge_field(&mut self.compression, &other.compression, "compression")?;
+        merge_field(&mut self.encryption, &other.encryption, "encryption")?;
+        merge_field(&mut self.recipient, &other.recipient, "recipient")?;
+        merge_field(&mut self.signature, &other.signature, "signature")
+    }
+}
+
+/// Header fields that data chunks say the stream has, see [`Chunk::announced`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Announced {
+    pub digest: bool,
+    pub encryption: bool,
+    pub recipient: bool,
+    /// Not a header field: the payload starts with the file metadata.
+    pub file: bool,
+}
+
+impl Announced {
+    fn merge(&mut self, other: Announced) {
+        self.digest |= other.digest;
+        self.encryption |= other.encryption;
+        self.recipient |= other.recipient;
+        self.file |= other.file;
+    }
+
+    /// The first announced field `header` lacks.
+    fn missing_from(&self, header: &StreamHeader) -> Option<&'static str> {
+        [
+            (self.recipient && header.recipient.is_none(), "recipient"),
+            (self.encryption && header.encryption.is_none(), "encryption"),
+            (self.digest && header.digest.is_none(), "digest"),
+        ]
+        .into_iter()
+        .find_map(|(missing, field)| missing.then_some(field))
+    }
+}
+
+fn merge_field<T: Clone + PartialEq>(
+    current: &mut Option<T>,
+    incoming: &Option<T>,
+    name: &'static str,
+) -> Result<(), ProtocolError> {
+    match (current.as_ref(), incoming) {
+        (Some(current), Some(incoming)) if current != incoming => {
+            Err(ProtocolError::HeaderConflict { field: name })
+        }
+        (None, Some(incoming)) => {
+            *current = Some(incoming.clone());
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Most chunks, LT source blocks or RaptorQ source symbols a stream may
+/// have. `total` sizes the receiver's bookkeeping, so it is bounded before
+/// anything is allocated from it.
+pub const MAX_TOTAL: u32 = 1 << 16;
+/// Largest payload length a stream may announce, in bytes.
+pub const MAX_LENGTH: u64 = compression::MAX_DECOMPRESSED_SIZE as u64;
+
+/// Rejects a frame whose `total` or `length` exceed [`MAX_TOTAL`] and
+/// [`MAX_LENGTH`].
+pub fn check_limits(total: Option<u32>, header: &StreamHeader) -> Result<(), ProtocolError> {
+    if let Some(total) = total.filter(|total| *total > MAX_TOTAL) {
+        return Err(ProtocolError::Parse {
+            message: format!("total of {} exceeds the limit of {}", total, MAX_TOTAL),
+        });
+    }
+    if let Some(length) = header.length.filter(|length| *length > MAX_LENGTH) {
+        return Err(ProtocolError::Parse {
+            message: format!("length of {} exceeds the limit of {}", length, MAX_LENGTH),
+        });
+    }
+    Ok(())
+}
+
+/// Largest number of grid columns or rows a stream may use.
+pub const MAX_GRID_SIDE: u32 = 4;
+
+/// Symbols shown side by side in one sender frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Grid {
+    pub columns: u32,
+    pub rows: u32,
+}
+
+impl Grid {
+    pub fn is_valid(&self) -> bool {
+        (1..=MAX_GRID_SIDE).contains(&self.columns) && (1..=MAX_GRID_SIDE).contains(&self.rows)
+    }
+
+    pub fn cells(&self) -> u32 {
+        self.columns * self.rows
+    }
+
+    /// Cell showing chunk `sequence`.
+    pub fn cell_of(&self, sequence: u32) -> u32 {
+        sequence % self.cells()
+    }
+}
+
+/// Position hint of a chunk sent in grid mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Cell {
+    #[serde(flatten)]
+    pub grid: Grid,
+    /// Cell index, row by row.
+    pub index: u32,
+}
+
+impl Cell {
+    pub fn new(grid: Grid, index: u32) -> Option<Self> {
+        (grid.is_valid() && index < grid.cells()).then_some(Self { grid, index })
+    }
+}
+
+/// Chunk data as it was carried in the QR code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Payload {
+    /// Base64 text from a JSON frame.
+    Base64(String),
+    /// Raw bytes from a binary frame.
+    Raw(Vec<u8>),
+}
+
+impl Payload {
+    pub fn decode(self) -> Result<Vec<u8>, base64::DecodeError> {
+        match self {
+            Payload::Base64(text) => BASE64.decode(text),
+            Payload::Raw(bytes) => Ok(bytes),
+        }
+    }
+}
+
+/// A single parsed QR chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    pub stream_id: String,
+    pub sequence: u32,
+    pub total: u32,
+    pub data: Payload,
+    pub checksum: Option<u32>,
+    /// Droplet seed for fountain modes; defaults to `sequence`.
+    pub seed: Option<u32>,
+    /// Grid position, when the sender shows several symbols per frame.
+    pub cell: Option<Cell>,
+    pub header: StreamHeader,
+    /// Fields the header frame carries, so the stream waits for them even
+    /// when that frame is lost.
+    pub announced: Announced,
+}
+
+/// Anything a single QR code can carry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+    Data(Chunk),
+    Header {
+        stream_id: String,
+        total: u32,
+        header: StreamHeader,
+    },
+}
+
+impl Frame {
+    pub fn stream_id(&self) -> &str {
+        match self {
+            Frame::Data(chunk) => &chunk.stream_id,
+            Frame::Header { stream_id, .. } => stream_id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProtocolError {
+    InvalidFormat,
+    Parse { message: String },
+    TotalMismatch { expected: u32, got: u32 },
+    StreamNotFound { stream_id: String },
+    Incomplete { received: u32, total: u32 },
+    Decode { sequence: u32, message: String },
+    ChecksumMismatch { sequence: u32, expected: u32, actual: u32 },
+    BlockSizeMismatch { expected: usize, got: usize },
+    HeaderConflict { field: &'static str },
+    HeaderMissing { field: &'static str },
+    DigestMismatch { expected: [u8; 32], actual: [u8; 32] },
+    Decompress { message: String },
+    DecompressedTooLarge { limit: usize },
+    PassphraseRequired,
+    DecryptFailed,
+    SignatureInvalid,
+    WrongReceiver,
+    Tampered,
+    MetadataInvalid,
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::InvalidFormat => write!(f, "Invalid chunk format"),
+            ProtocolError::Parse { message } => write!(f, "Failed to parse QR code: {}", message),
+            ProtocolError::TotalMismatch { expected, got } => {
+                write!(f, "Total mismatch: expected {}, got {}", expected, got)
+            }
+            ProtocolError::StreamNotFound { stream_id } => {
+                write!(f, "Stream not found: {}", stream_id)
+            }
+            ProtocolError::Incomplete { received, total } => write!(
+                f,
+                "Incomplete stream: {}/{} chunks received",
+                received, total
+            ),
+            ProtocolError::Decode { sequence, message } => {
+                write!(f, "Failed to decode chunk {}: {}", sequence, message)
+            }
+            ProtocolError::ChecksumMismatch {
+                sequence,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Checksum mismatch in chunk {}: expected {}, got {}",
+                sequence,
+                checksum::format_crc32(*expected),
+                checksum::format_crc32(*actual)
+            ),
+            ProtocolError::BlockSizeMismatch { expected, got } => write!(
+                f,
+                "Block size mismatch: expected {} bytes, got {}",
+                expected, got
+            ),
+            ProtocolError::HeaderConflict { field } => {
+                write!(f, "Conflicting {} for the same stream id", field)
+            }
+            ProtocolError::HeaderMissing { field } => write!(
+                f,
+                "All chunks received, but not the stream header with its {}",
+                field
+            ),
+            ProtocolError::DigestMismatch { expected, actual } => write!(
+                f,
+                "Digest mismatch: expected {}, got {}",
+                hex::encode(expected),
+                hex::encode(actual)
+            ),
+            ProtocolError::Decompress { message } => {
+                write!(f, "Failed to decompress stream: {}", message)
+            }
+            ProtocolError::DecompressedTooLarge { limit } => write!(
+                f,
+                "Decompressed stream exceeds the limit of {} bytes",
+                limit
+            ),
+            ProtocolError::PassphraseRequired => {
+                write!(f, "Stream is encrypted, a passphrase is required")
+            }
+            ProtocolError::DecryptFailed => write!(f, "Wrong passphrase or tampered stream"),
+            ProtocolError::SignatureInvalid => {
+                write!(f, "Stream signature does not match its digest")
+            }
+            ProtocolError::WrongReceiver => {
+                write!(f, "Stream is encrypted for a different receiver")
+            }
+            ProtocolError::Tampered => write!(f, "Stream failed authentication, it was tampered with"),
+            ProtocolError::MetadataInvalid => write!(f, "Stream carries malformed file metadata"),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+impl ProtocolError {
+    /// Stable identifier the frontend can branch on.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ProtocolError::InvalidFormat => "invalid_format",
+            ProtocolError::Parse { .. } => "parse",
+            ProtocolError::TotalMismatch { .. } => "total_mismatch",
+            ProtocolError::StreamNotFound { .. } => "stream_not_found",
+            ProtocolError::Incomplete { .. } => "incomplete",
+            ProtocolError::Decode { .. } => "decode",
+            ProtocolError::ChecksumMismatch { .. } => "checksum_mismatch",
+            ProtocolError::BlockSizeMismatch { .. } => "block_size_mismatch",
+            ProtocolError::HeaderConflict { .. } => "header_conflict",
+            ProtocolError::HeaderMissing { .. } => "header_missing",
+            ProtocolError::DigestMismatch { .. } => "digest_mismatch",
+            ProtocolError::Decompress { .. } => "decompress",
+            ProtocolError::DecompressedTooLarge { .. } => "decompressed_too_large",
+            ProtocolError::PassphraseRequired => "passphrase_required",
+            ProtocolError::DecryptFailed => "decrypt_failed",
+            ProtocolError::SignatureInvalid => "signature_invalid",
+            ProtocolError::WrongReceiver => "wrong_receiver",
+            ProtocolError::Tampered => "tampered",
+            ProtocolError::MetadataInvalid => "metadata_invalid",
+        }
+    }
+}
+
+impl Serialize for ProtocolError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("ProtocolError", 2)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChunkStatus {
+    Header,
+    Progress,
+    Duplicate,
+    Complete,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Progress {
+    pub mode: StreamMode,
+    /// Distinct chunks (or droplets) received.
+    pub received: u32,
+    /// Source blocks recovered; equals `received` for plain chunk streams.
+    pub decoded: u32,
+    pub total: u32,
+    pub percentage: u32,
+    /// Sequence numbers, or source block indices, still unknown.
+    pub missing: Vec<u32>,
+    /// The part of `missing` that parity can still rebuild: catching any
+    /// further chunks of their group is enough. The rest needs a rescan.
+    pub recoverable: Vec<u32>,
+    /// Chunks rebuilt from parity instead of being scanned.
+    pub recovered: u32,
+    pub corrupted: u32,
+    /// The chunks announced header fields that have not arrived yet; the
+    /// stream completes once its header frame is scanned.
+    pub awaiting_header: bool,
+    /// Every chunk and header field needed to reconstruct has arrived. For
+    /// fountain streams `missing` says nothing about this.
+    pub is_complete: bool,
+    /// Per-cell counts of streams sent in grid mode.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cells: Option<CellProgress>,
+}
+
+/// Scan results per grid cell, so the UI can point out cells that fail.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CellProgress {
+    #[serde(flatten)]
+    pub grid: Grid,
+    /// Distinct chunks received, per cell.
+    pub received: Vec<u32>,
+    /// Chunks rejected for a bad checksum, per cell.
+    pub corrupted: Vec<u32>,
+    /// Chunk streams only: chunks still missing, per cell.
+    pub missing: Vec<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkOutcome {
+    pub stream_id: String,
+    pub sequence: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cell: Option<Cell>,
+    pub status: ChunkStatus,
+    pub progress: Progress,
+    pub is_complete: bool,
+    /// Why the frame could not be journaled; it is still in memory, only a
+    /// restart would lose it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub journal_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verification {
+    /// The stream carried no digest, so only per-chunk checks were made.
+    Unverified,
+    /// The reconstructed payload matches the stream digest.
+    Verified,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureStatus {
+    Unsigned,
+    /// Validly signed by a key in the receiver's keyring.
+    SignedByKnown,
+    /// Validly signed, but by a key the receiver does not trust.
+    SignedByUnknown,
+}
+
+/// Secrets and trust anchors the receiver brings to reconstruction.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Credentials<'a> {
+    pub passphrase: Option<&'a str>,
+    pub keyring: Option<&'a Keyring>,
+    /// This device's key, for streams sealed to a receiver.
+    pub receiver: Option<&'a ReceiverKey>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReconstructedStream {
+    pub stream_id: String,
+    /// Raw payload bytes; sent to the webview separately as a binary response.
+    #[serde(skip)]
+    pub data: Vec<u8>,
+    pub size: usize,
+    pub chunks: u32,
+    pub duration: u64,
+    pub verification: Verification,
+    pub signature: SignatureStatus,
+    /// Hex public key of the signer.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signer: Option<String>,
+    /// Keyring name of the signer, when known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signer_name: Option<String>,
+    /// Set when the payload was decompressed; `size` is the decompressed size.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+    /// The payload was decrypted with a passphrase.
+    pub encrypted: bool,
+    /// The payload was sealed to this receiver and opened with its key.
+    pub sealed: bool,
+    /// Bytes actually transferred, when that differs from `size`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transfer_size: Option<usize>,
+    /// RaptorQ only: symbols beyond `total` that were needed to recover.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extra_symbols: Option<u32>,
+    /// The file the sender streamed, when it said.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<FileMetadata>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamSummary {
+    pub id: String,
+    pub progress: Progress,
+}
+
+/// Returns the first present, non-null field out of `names`.
+fn field<'a>(object: &'a serde_json::Map<String, Value>, names: &[&str]) -> Option<&'a Value> {
+    names
+        .iter()
+        .find_map(|name| object.get(*name).filter(|value| !value.is_null()))
+}
+
+/// Reads an integer that may also be sent as a float or a numeric string.
+fn as_integer(value: &Value) -> Option<i64> {
+    match value {
+        Value::Number(number) => number.as_i64().or_else(|| {
+            number
+                .as_f64()
+                .filter(|float| float.fract() == 0.0 && float.abs() < i64::MAX as f64)
+                .map(|float| float as i64)
+        }),
+        Value::String(text) => text.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn parse_header(object: &serde_json::Map<String, Value>) -> Result<StreamHeader, ProtocolError> {
+    let digest = match field(object, &["digest", "sha256"]) {
+        None => None,
+        Some(value) => Some(
+            value
+                .as_str()
+                .and_then(checksum::parse_sha256)
+                .ok_or(ProtocolError::InvalidFormat)?,
+        ),
+    };
+    let mode = match field(object, &["mode"]) {
+        None => None,
+        Some(value) => Some(
+            value
+                .as_str()
+                .and_then(StreamMode::parse)
+                .ok_or(ProtocolError::InvalidFormat)?,
+        ),
+    };
+    let length = match field(object, &["length", "size"]) {
+        None => None,
+        Some(value) => Some(
+            as_integer(value)
+                .and_then(|length| u64::try_from(length).ok())
+                .ok_or(ProtocolError::InvalidFormat)?,
+        ),
+    };
+
+    let oti = match field(object, &["oti"]) {
+        None => None,
+        Some(value) => Some(
+            value
+                .as_str()
+                .and_then(|text| BASE64.decode(text).ok())
+                .and_then(|bytes| <[u8; 12]>::try_from(bytes).ok())
+                .ok_or(ProtocolError::InvalidFormat)?,
+        ),
+    };
+
+    let count = |names: &[&str]| match field(object, names) {
+        None => Ok(None),
+        Some(value) => as_integer(value)
+            .and_then(|count| u32::try_from(count).ok())
+            .map(Some)
+            .ok_or(ProtocolError::InvalidFormat),
+    };
+    let group_size = count(&["groupSize", "group_size"])?;
+    let parity = count(&["parity", "parityChunks", "parity_chunks"])?;
+    let compression = match field(object, &["compression", "encoding"]) {
+        None => None,
+        Some(Value::String(name)) if name == "none" => None,
+        Some(value) => Some(
+            value
+                .as_str()
+                .and_then(Compression::parse)
+                .ok_or(ProtocolError::InvalidFormat)?,
+        ),
+    };
+    let encryption = match field(object, &["encryption"]) {
+        None => None,
+        Some(value) => Some(parse_encryption(value).ok_or(ProtocolError::InvalidFormat)?),
+    };
+    let recipient = match field(object, &["recipient"]) {
+        None => None,
+        Some(value) => Some(parse_recipient(value).ok_or(ProtocolError::InvalidFormat)?),
+    };
+    let signature = match (
+        field(object, &["signature"]),
+        field(object, &["publicKey", "public_key", "signer"]),
+    ) {
+        (None, None) => None,
+        (Some(signature), Some(public_key)) => Some(StreamSignature {
+            public_key: public_key
+                .as_str()
+                .and_then(signature::parse_public_key)
+                .ok_or(ProtocolError::InvalidFormat)?,
+            signature: signature
+                .as_str()
+                .and_then(|text| BASE64.decode(text).ok())
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or(ProtocolError::InvalidFormat)?,
+        }),
+        _ => return Err(ProtocolError::InvalidFormat),
+    };
+    Ok(StreamHeader {
+        digest,
+        mode,
+        length,
+        oti,
+        group_size,
+        parity,
+        compression,
+        encryption,
+        recipient,
+        signature,
+    })
+}
+
+fn parse_recipient(value: &Value) -> Option<Recipient> {
+    let object = value.as_object()?;
+    let bytes = |name: &str| {
+        field(object, &[name])?
+            .as_str()
+            .and_then(|text| BASE64.decode(text).ok())
+    };
+    Some(Recipient {
+        public_key: bytes("publicKey")?.try_into().ok()?,
+        ephemeral: bytes("ephemeral")?.try_into().ok()?,
+        nonce: bytes("nonce")?.try_into().ok()?,
+    })
+}
+
+fn parse_cell(value: &Value) -> Option<Cell> {
+    let object = value.as_object()?;
+    let number = |name: &str| u32::try_from(as_integer(field(object, &[name])?)?).ok();
+    let grid = Grid {
+        columns: number("columns")?,
+        rows: number("rows")?,
+    };
+    Cell::new(grid, number("index")?)
+}
+
+fn parse_encryption(value: &Value) -> Option<Encryption> {
+    let object = value.as_object()?;
+    let cipher = field(object, &["cipher"]).map(Value::as_str);
+    let kdf = field(object, &["kdf"]).map(Value::as_str);
+    if !matches!(cipher, None | Some(Some("chacha20-poly1305")))
+        || !matches!(kdf, None | Some(Some("argon2id")))
+    {
+        return None;
+    }
+
+    let bytes = |name: &str| {
+        field(object, &[name])?
+            .as_str()
+            .and_then(|text| BASE64.decode(text).ok())
+    };
+    let cost = |name: &str, default: u32| match field(object, &[name]) {
+        None => Some(default),
+        Some(value) => as_integer(value).and_then(|cost| u32::try_from(cost).ok()),
+    };
+    let encryption = Encryption {
+        salt: bytes("salt")?.try_into().ok()?,
+        nonce: bytes("nonce")?.try_into().ok()?,
+        memory: cost("memory", encryption::DEFAULT_MEMORY_KIB)?,
+        iterations: cost("iterations", encryption::DEFAULT_ITERATIONS)?,
+        parallelism: cost("parallelism", encryption::DEFAULT_PARALLELISM)?,
+    };
+    encryption.is_valid().then_some(encryption)
+}
+
+/// Parse raw QR byte-mode content, either a binary frame or JSON text.
+pub fn parse_payload(bytes: &[u8]) -> Result<Frame, ProtocolError> {
+    if frame::is_binary(bytes) {
+        return frame::decode(bytes);
+    }
+    let text = std::str::from_utf8(bytes).map_err(|e| ProtocolError::Parse {
+        message: e.to_string(),
+    })?;
+    parse_frame(text)
+}
+
+/// Parse a QR code string into a data or header frame.
+///
+/// Alternative field names and numeric strings are accepted so mixed
+/// protocol sources keep working.
+pub fn parse_frame(qr_string: &str) -> Result<Frame, ProtocolError> {
+    let value: Value = serde_json::from_str(qr_string).map_err(|e| ProtocolError::Parse {
+        message: e.to_string(),
+    })?;
+    let object = value.as_object().ok_or(ProtocolError::InvalidFormat)?;
+
+    let stream_id = match field(object, &["id", "streamId", "stream_id", "streamID"]) {
+        Some(Value::String(text)) => text.trim().to_string(),
+        Some(Value::Number(number)) => number.to_string(),
+        _ => String::new(),
+    };
+    let sequence = field(object, &["seq", "sequence", "index", "chunkIndex", "chunk"])
+        .and_then(as_integer)
+        .and_then(|seq| u32::try_from(seq).ok());
+    let total = field(object, &["total", "totalChunks", "total_chunks", "chunkCount"])
+        .and_then(as_integer)
+        .and_then(|total| u32::try_from(total).ok())
+        .filter(|total| *total > 0);
+    let mut header = parse_header(object)?;
+    check_limits(total, &header)?;
+
+    let is_header = matches!(
+        field(object, &["type", "kind"]).and_then(Value::as_str),
+        Some("header" | "trailer")
+    );
+    if is_header {
+        return match (stream_id.is_empty(), total) {
+            (false, Some(total)) => Ok(Frame::Header {
+                stream_id,
+                total,
+                header,
+            }),
+            _ => Err(ProtocolError::InvalidFormat),
+        };
+    }
+
+    header.mode.get_or_insert(StreamMode::Chunks);
+    let seed = match field(object, &["seed"]) {
+        None => None,
+        Some(value) => Some(
+            as_integer(value)
+                .and_then(|seed| u32::try_from(seed).ok())
+                .ok_or(ProtocolError::InvalidFormat)?,
+        ),
+    };
+    let cell = match field(object, &["cell"]) {
+        None => None,
+        Some(value) => Some(parse_cell(value).ok_or(ProtocolError::InvalidFormat)?),
+    };
+    let data = field(object, &["data", "payload", "body", "content"])
+        .and_then(Value::as_str)
+        .filter(|data| !data.is_empty());
+    let announced = Announced {
+        digest: field(object, &["hasDigest", "has_digest"]) == Some(&Value::Bool(true)),
+        encryption: field(object, &["encrypted"]) == Some(&Value::Bool(true)),
+        recipient: field(object, &["sealed"]) == Some(&Value::Bool(true)),
+        file: field(object, &["file"]) == Some(&Value::Bool(true)),
+    };
+    let checksum = match field(object, &["checksum", "crc"]) {
+        None => None,
+        Some(Value::String(text)) if text.is_empty() => None,
+        Some(Value::String(text)) => {
+            Some(checksum::parse_crc32(text).ok_or(ProtocolError::InvalidFormat)?)
+        }
+        Some(value) => Some(
+            as_integer(value)
+                .and_then(|crc| u32::try_from(crc).ok())
+                .ok_or(ProtocolError::InvalidFormat)?,
+        ),
+    };
+
+    match (stream_id.is_empty(), sequence, total, data) {
+        (false, Some(sequence), Some(total), Some(data)) => Ok(Frame::Data(Chunk {
+            stream_id,
+            sequence,
+            total,
+            data: Payload::Base64(data.to_string()),
+            checksum,
+            seed,
+            cell,
+            header,
+            announced,
+        })),
+        _ => Err(ProtocolError::InvalidFormat),
+    }
+}
+
+/// Data and parity chunks of a plain chunk stream.
+#[derive(Debug, Default)]
+struct ChunkSet {
+    data: BTreeMap<u32, Vec<u8>>,
+    parity: BTreeMap<u32, Vec<u8>>,
+    /// Length of a full chunk, once known; parity streams need it fixed.
+    shard_size: Option<usize>,
+    recovered: u32,
+}
+
+impl ChunkSet {
+    /// Stores a chunk, returning whether it was new. Parity streams also
+    /// pass the payload `length`.
+    fn insert(
+        &mut self,
+        sequence: u32,
+        bytes: Vec<u8>,
+        layout: Option<&ParityLayout>,
+        length: u64,
+    ) -> Result<bool, ProtocolError> {
+        let Some(layout) = layout else {
+            return Ok(match self.data.entry(sequence) {
+                Entry::Occupied(_) => false,
+                Entry::Vacant(slot) => {
+                    slot.insert(bytes);
+                    true
+                }
+            });
+        };
+
+        let last = layout.total - 1;
+        if sequence == last && self.shard_size.is_none() {
+            // Every other data chunk holds at least a byte, which bounds
+            // the last one until a full chunk gives the shard size.
+            let most = length.saturating_sub(u64::from(last));
+            if bytes.len() as u64 > most {
+                return Err(ProtocolError::BlockSizeMismatch {
+                    expected: most as usize,
+                    got: bytes.len(),
+                });
+            }
+        } else {
+            let expected = *self.shard_size.get_or_insert(bytes.len());
+            let fits = if sequence == last {
+                bytes.len() <= expected
+            } else {
+                bytes.len() == expected
+            };
+            if !fits {
+                return Err(ProtocolError::BlockSizeMismatch {
+                    expected,
+                    got: bytes.len(),
+                });
+            }
+            // A last chunk stored before the shard size was known is only
+            // kept if it fits; otherwise it has to be scanned again.
+            if self
+                .data
+                .get(&last)
+                .is_some_and(|chunk| chunk.len() > expected)
+            {
+                self.data.remove(&last);
+            }
+        }
+
+        let target = if layout.is_parity(sequence) {
+            &mut self.parity
+        } else {
+            &mut self.data
+        };
+        Ok(match target.entry(sequence) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(slot) => {
+                slot.insert(bytes);
+                true
+            }
+        })
+    }
+
+    /// Rebuilds the missing data chunks of `group` if enough are present.
+    fn recover(&mut self, layout: &ParityLayout, group: u32, length: u64) {
+        let data_range = layout.data_range(group);
+        let missing: Vec<u32> = data_range
+            .clone()
+            .filter(|seq| !self.data.contains_key(seq))
+            .collect();
+        let Some(shard_size) = self.shard_size else {
+            return;
+        };
+        if missing.is_empty() {
+            return;
+        }
+
+        let data: Vec<Option<&[u8]>> = data_range
+            .clone()
+            .map(|seq| self.data.get(&seq).map(Vec::as_slice))
+            .collect();
+        let parity: Vec<Option<&[u8]>> = layout
+            .parity_range(group)
+            .map(|seq| self.parity.get(&seq).map(Vec::as_slice))
+            .collect();
+        let Some(rebuilt) = parity::recover(layout, group, &data, &parity, shard_size) else {
+            return;
+        };
+
+        for sequence in missing {
+            let mut chunk = rebuilt[(sequence - data_range.start) as usize].clone();
+            if sequence == layout.total - 1 {
+                let offset = (layout.total as u64 - 1) * shard_size as u64;
+                chunk.truncate(length.saturating_sub(offset) as usize);
+            }
+            self.data.insert(sequence, chunk);
+            self.recovered += 1;
+        }
+    }
+}
+
+/// Received data of a stream, shaped by its mode.
+#[derive(Debug)]
+enum Assembly {
+    Chunks(ChunkSet),
+    Lt(LtDecoder),
+    RaptorQ(RaptorDecoder),
+}
+
+/// Per-cell counters of a grid stream.
+#[derive(Debug)]
+struct CellCounts {
+    grid: Grid,
+    received: Vec<u32>,
+    corrupted: Vec<u32>,
+}
+
+impl CellCounts {
+    fn new(grid: Grid) -> Self {
+        Self {
+            grid,
+            received: vec![0; grid.cells() as usize],
+            corrupted: vec![0; grid.cells() as usize],
+        }
+    }
+}
+
+#[derive(Debug)]
+struct StreamState {
+    total: u32,
+    header: StreamHeader,
+    announced: Announced,
+    /// Created by the first data chunk, since fountain decoders need its size.
+    assembly: Option<Assembly>,
+    corrupted: u32,
+    /// Created by the first chunk with a cell hint.
+    cells: Option<CellCounts>,
+    started: Instant,
+    /// Rebuilt and its journal deleted; later frames are not journaled.
+    settled: bool,
+}
+
+impl StreamState {
+    fn mark_corrupted(&mut self, cell: Option<Cell>) {
+        self.corrupted += 1;
+        if let (Some(cell), Some(counts)) = (cell, &mut self.cells) {
+            counts.corrupted[cell.index as usize] += 1;
+        }
+    }
+
+    fn mode(&self) -> StreamMode {
+        self.header.mode.unwrap_or(StreamMode::Chunks)
+    }
+
+    fn parity_layout(&self) -> Option<ParityLayout> {
+        ParityLayout::new(self.total, self.header.group_size?, self.header.parity?)
+    }
+
+    fn progress(&self) -> Progress {
+        let (received, decoded, missing) = match &self.assembly {
+            None => (0, 0, (0..self.total).collect()),
+            Some(Assembly::Chunks(set)) => {
+                let received = set.data.len() as u32;
+                let missing = (0..self.total)
+                    .filter(|seq| !set.data.contains_key(seq))
+                    .collect();
+                (received, received, missing)
+            }
+            Some(Assembly::Lt(decoder)) => {
+                (decoder.droplets(), decoder.decoded(), decoder.missing())
+            }
+            // RaptorQ recovers everything at once, so there is no partial
+            // decode to report and no particular symbol is missing.
+            Some(Assembly::RaptorQ(decoder)) => {
+                let decoded = if decoder.is_complete() { self.total } else { 0 };
+                (decoder.symbols(), decoded, Vec::new())
+            }
+        };
+        let towards = match self.mode() {
+            StreamMode::RaptorQ => received.min(self.total).max(decoded),
+            _ => decoded,
+        };
+
+        let (recoverable, recovered) = match (&self.assembly, self.parity_layout()) {
+            (Some(Assembly::Chunks(set)), Some(layout)) => {
+                let recoverable = missing
+                    .iter()
+                    .copied()
+                    .filter(|seq| {
+                        let group = layout.data_range(layout.group_of(*seq));
+                        let gaps = group.filter(|seq| !set.data.contains_key(seq)).count();
+                        gaps as u32 <= layout.parity
+                    })
+                    .collect();
+                (recoverable, set.recovered)
+            }
+            _ => (Vec::new(), 0),
+        };
+
+        let cells = self.cells.as_ref().map(|counts| {
+            let mut per_cell = Vec::new();
+            if self.mode() == StreamMode::Chunks {
+                per_cell.resize(counts.received.len(), 0);
+                for sequence in &missing {
+                    per_cell[counts.grid.cell_of(*sequence) as usize] += 1;
+                }
+            }
+            CellProgress {
+                grid: counts.grid,
+                received: counts.received.clone(),
+                corrupted: counts.corrupted.clone(),
+                missing: per_cell,
+            }
+        });
+
+        Progress {
+            mode: self.mode(),
+            received,
+            decoded,
+            total: self.total,
+            percentage: ((towards as f64 / self.total as f64) * 100.0).round() as u32,
+            missing,
+            recoverable,
+            recovered,
+            corrupted: self.corrupted,
+            awaiting_header: self.awaiting_header().is_some(),
+            is_complete: self.is_complete(),
+            cells,
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        self.has_all_data() && self.awaiting_header().is_none()
+    }
+
+    /// An announced header field that has not arrived yet.
+    fn awaiting_header(&self) -> Option<&'static str> {
+        self.announced.missing_from(&self.header)
+    }
+
+    fn has_all_data(&self) -> bool {
+        match &self.assembly {
+            None => false,
+            Some(Assembly::Chunks(set)) => set.data.len() as u32 == self.total,
+            Some(Assembly::Lt(decoder)) => decoder.is_complete(),
+            Some(Assembly::RaptorQ(decoder)) => decoder.is_complete(),
+        }
+    }
+}
+
+/// In-memory chunk bookkeeping for every stream seen so far.
+#[derive(Debug, Default)]
+pub struct StreamStore {
+    streams: HashMap<String, StreamState>,
+    /// Where accepted frames are persisted, if anywhere.
+    journal: Option<Journal>,
+}
+
+impl StreamStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A store that journals every accepted frame, starting out with the
+    /// streams already in the journal. Journals older than
+    /// [`journal::MAX_AGE`] or no longer readable are deleted instead of
+    /// replayed; also returns the ones that could not be.
+    pub fn with_journal(journal: Journal) -> (Self, Vec<String>) {
+        let mut store = Self::new();
+        let (streams, errors) = journal.restore(journal::MAX_AGE);
+        for stream in streams {
+            store.replay(&stream.payloads);
+        }
+        store.journal = Some(journal);
+        (store, errors)
+    }
+
+    /// Process a chunk and add it to its stream.
+    pub fn process_chunk(&mut self, qr_string: &str) -> Result<ChunkOutcome, ProtocolError> {
+        let frame = parse_frame(qr_string)?;
+        self.insert_journaled(frame, qr_string.as_bytes())
+    }
+
+    /// Process raw QR byte-mode content, binary or JSON.
+    pub fn process_payload(&mut self, bytes: &[u8]) -> Result<ChunkOutcome, ProtocolError> {
+        let frame = parse_payload(bytes)?;
+        self.insert_journaled(frame, bytes)
+    }
+
+    /// Inserts `frame` and journals its raw content `bytes` when it added
+    /// something: duplicates and repeated headers are left out.
+    fn insert_journaled(
+        &mut self,
+        frame: Frame,
+        bytes: &[u8],
+    ) -> Result<ChunkOutcome, ProtocolError> {
+        let settled = self
+            .streams
+            .get(frame.stream_id())
+            .is_some_and(|stream| stream.settled);
+        if self.journal.is_none() || settled {
+            return self.insert(frame);
+        }
+        let header = |store: &Self, stream_id: &str| {
+            store
+                .streams
+                .get(stream_id)
+                .map(|stream| stream.header.clone())
+        };
+        let before = header(self, frame.stream_id());
+        let mut outcome = self.insert(frame)?;
+        let added = match outcome.status {
+            ChunkStatus::Duplicate => false,
+            ChunkStatus::Header => before != header(self, &outcome.stream_id),
+            ChunkStatus::Progress | ChunkStatus::Complete => true,
+        };
+        if let (true, Some(journal)) = (added, &self.journal) {
+            if let Err(e) = journal.append(&outcome.stream_id, bytes) {
+                outcome.journal_error = Some(format!("Failed to journal frame: {}", e));
+            }
+        }
+        Ok(outcome)
+    }
+
+    /// Feeds journaled frames back in without journaling them again.
+    fn replay(&mut self, payloads: &[Vec<u8>]) {
+        for payload in payloads {
+            if let Ok(frame) = parse_payload(payload) {
+                let _ = self.insert(frame);
+            }
+        }
+    }
+
+    pub fn insert(&mut self, frame: Frame) -> Result<ChunkOutcome, ProtocolError> {
+        let (total, header) = match &frame {
+            Frame::Data(chunk) => (chunk.total, &chunk.header),
+            Frame::Header { total, header, .. } => (*total, header),
+        };
+        check_limits(Some(total), header)?;
+
+        let stream = self
+            .streams
+            .entry(frame.stream_id().to_string())
+            .or_insert_with(|| StreamState {
+                total,
+                header: StreamHeader::default(),
+                announced: Announced::default(),
+                assembly: None,
+                corrupted: 0,
+                cells: None,
+                started: Instant::now(),
+                settled: false,
+            });
+
+        if stream.total != total {
+            return Err(ProtocolError::TotalMismatch {
+                expected: stream.total,
+                got: total,
+            });
+        }
+        stream.header.merge(header)?;
+
+        let chunk = match frame {
+            Frame::Data(chunk) => chunk,
+            Frame::Header { stream_id, .. } => {
+                let progress = stream.progress();
+                return Ok(ChunkOutcome {
+                    stream_id,
+                    sequence: None,
+                    cell: None,
+                    status: ChunkStatus::Header,
+                    is_complete: stream.is_complete(),
+                    progress,
+                    journal_error: None,
+                });
+            }
+        };
+
+        let mode = stream.mode();
+        let layout = stream.parity_layout();
+        if mode == StreamMode::Chunks {
+            let in_range = chunk.sequence < stream.total
+                || layout.is_some_and(|layout| layout.is_parity(chunk.sequence));
+            // Rebuilding the short last chunk needs the payload length.
+            if !in_range || (layout.is_some() && stream.header.length.is_none()) {
+                return Err(ProtocolError::InvalidFormat);
+            }
+        }
+        if mode == StreamMode::Lt && stream.header.length.is_none() {
+            return Err(ProtocolError::InvalidFormat);
+        }
+        let oti = match mode {
+            StreamMode::RaptorQ => Some(
+                stream
+                    .header
+                    .oti
+                    .as_ref()
+                    .and_then(|oti| raptor::parse_oti(oti))
+                    .filter(|oti| raptor::source_symbols(oti) == stream.total)
+                    .ok_or(ProtocolError::InvalidFormat)?,
+            ),
+            _ => None,
+        };
+        if let Some(cell) = chunk.cell {
+            match &stream.cells {
+                Some(counts) if counts.grid != cell.grid => {
+                    return Err(ProtocolError::HeaderConflict { field: "cell" });
+                }
+                Some(_) => {}
+                None => stream.cells = Some(CellCounts::new(cell.grid)),
+            }
+        }
+
+        let bytes = chunk
+            .data
+            .decode()
+            .map_err(|e| ProtocolError::Decode {
+                sequence: chunk.sequence,
+                message: e.to_string(),
+            });
+        let bytes = match (bytes, chunk.checksum) {
+            (Ok(bytes), Some(expected)) if checksum::crc32(&bytes) != expected => {
+                stream.mark_corrupted(chunk.cell);
+                return Err(ProtocolError::ChecksumMismatch {
+                    sequence: chunk.sequence,
+                    expected,
+                    actual: checksum::crc32(&bytes),
+                });
+            }
+            (Ok(bytes), _) => bytes,
+            (Err(error), _) => {
+                stream.mark_corrupted(chunk.cell);
+                return Err(error);
+            }
+        };
+
+        stream.announced.merge(chunk.announced);
+
+        let length = stream.header.length.unwrap_or_default();
+        if stream.assembly.is_none() {
+            stream.assembly = Some(match mode {
+                StreamMode::Chunks => Assembly::Chunks(ChunkSet::default()),
+                StreamMode::Lt => Assembly::Lt(
+                    LtDecoder::new(stream.total, bytes.len(), length)
+                        .ok_or(ProtocolError::InvalidFormat)?,
+                ),
+                StreamMode::RaptorQ => Assembly::RaptorQ(RaptorDecoder::new(
+                    oti.expect("checked for raptorq streams"),
+                )),
+            });
+        }
+        let assembly = stream.assembly.as_mut().expect("created above");
+        let is_new = match assembly {
+            Assembly::Chunks(set) => {
+                let is_new = set.insert(chunk.sequence, bytes, layout.as_ref(), length)?;
+                if let (true, Some(layout)) = (is_new, &layout) {
+                    set.recover(layout, layout.group_of(chunk.sequence), length);
+                }
+                is_new
+            }
+            Assembly::Lt(decoder) => {
+                if bytes.len() != decoder.block_size() {
+                    return Err(ProtocolError::BlockSizeMismatch {
+                        expected: decoder.block_size(),
+                        got: bytes.len(),
+                    });
+                }
+                let seed = chunk.seed.unwrap_or(chunk.sequence);
+                decoder.add_droplet(seed, bytes) != DropletStatus::Duplicate
+            }
+            Assembly::RaptorQ(decoder) => match decoder.add_packet(&bytes) {
+                PacketStatus::Invalid => return Err(ProtocolError::InvalidFormat),
+                PacketStatus::Duplicate => false,
+                PacketStatus::Accepted => true,
+            },
+        };
+
+        if let (true, Some(cell), Some(counts)) = (is_new, chunk.cell, &mut stream.cells) {
+            counts.received[cell.index as usize] += 1;
+        }
+        let is_complete = stream.is_complete();
+        let status = match (is_new, is_complete) {
+            (false, _) => ChunkStatus::Duplicate,
+            (true, true) => ChunkStatus::Complete,
+            (true, false) => ChunkStatus::Progress,
+        };
+
+        Ok(ChunkOutcome {
+            stream_id: chunk.stream_id,
+            sequence: Some(chunk.sequence),
+            cell: chunk.cell,
+            status,
+            is_complete,
+            progress: stream.progress(),
+            journal_error: None,
+        })
+    }
+
+    pub fn progress(&self, stream_id: &str) -> Option<Progress> {
+        self.streams.get(stream_id).map(StreamState::progress)
+    }
+
+    pub fn missing_chunks(&self, stream_id: &str) -> Vec<u32> {
+        self.progress(stream_id)
+            .map(|progress| progress.missing)
+            .unwrap_or_default()
+    }
+
+    /// Reconstruct the complete data by concatenating the chunks (or the
+    /// peeled source blocks of a fountain stream), which were already
+    /// decoded individually when they were received, and check it
+    /// against the stream digest when one was sent.
+    pub fn reconstruct(&self, stream_id: &str) -> Result<ReconstructedStream, ProtocolError> {
+        self.reconstruct_with(stream_id, Credentials::default())
+    }
+
+    /// Like [`StreamStore::reconstruct`], decrypting encrypted streams with
+    /// the given passphrase and looking signers up in the given keyring.
+    pub fn reconstruct_with(
+        &self,
+        stream_id: &str,
+        credentials: Credentials<'_>,
+    ) -> Result<ReconstructedStream, ProtocolError> {
+        let stream = self
+            .streams
+            .get(stream_id)
+            .ok_or_else(|| ProtocolError::StreamNotFound {
+                stream_id: stream_id.to_string(),
+            })?;
+
+        if let (true, Some(field)) = (stream.has_all_data(), stream.awaiting_header()) {
+            return Err(ProtocolError::HeaderMissing { field });
+        }
+        let bytes = match &stream.assembly {
+            Some(Assembly::Chunks(set)) if stream.is_complete() => {
+                set.data.values().flatten().copied().collect()
+            }
+            Some(Assembly::Lt(decoder)) if stream.is_complete() => {
+                decoder.finish().unwrap_or_default()
+            }
+            Some(Assembly::RaptorQ(decoder)) if stream.is_complete() => {
+                decoder.finish().unwrap_or_default()
+            }
+            _ => {
+                let progress = stream.progress();
+                return Err(ProtocolError::Incomplete {
+                    received: progress.decoded,
+                    total: progress.total,
+                });
+            }
+        };
+
+        let verification = match stream.header.digest {
+            Some(expected) => {
+                let actual = checksum::sha256(&bytes);
+                if actual != expected {
+                    return Err(ProtocolError::DigestMismatch { expected, actual });
+                }
+                Verification::Verified
+            }
+            None => Verification::Unverified,
+        };
+
+        let signer = match (&stream.header.signature, &stream.header.digest) {
+            (None, _) => None,
+            (Some(signature), Some(digest)) if signature.verify(digest) => {
+                Some(signature.public_key)
+            }
+            (Some(_), _) => return Err(ProtocolError::SignatureInvalid),
+        };
+        let trusted = signer
+            .as_ref()
+            .and_then(|key| credentials.keyring?.find(key));
+        let signature = match (signer, trusted) {
+            (None, _) => SignatureStatus::Unsigned,
+            (Some(_), Some(_)) => SignatureStatus::SignedByKnown,
+            (Some(_), None) => SignatureStatus::SignedByUnknown,
+        };
+
+        let transfer_size = bytes.len();
+        let bytes = match &stream.header.recipient {
+            Some(recipient) => credentials
+                .receiver
+                .ok_or(ProtocolError::WrongReceiver)?
+                .open(recipient, &bytes)
+                .map_err(|e| match e {
+                    OpenError::WrongReceiver => ProtocolError::WrongReceiver,
+                    OpenError::Tampered => ProtocolError::Tampered,
+                })?,
+            None => bytes,
+        };
+        let bytes = match stream.header.encryption {
+            Some(encryption) => {
+                let passphrase = credentials
+                    .passphrase
+                    .ok_or(ProtocolError::PassphraseRequired)?;
+                encryption
+                    .decrypt(passphrase, &bytes)
+                    .map_err(|_| ProtocolError::DecryptFailed)?
+            }
+            None => bytes,
+        };
+        let mut bytes = match stream.header.compression {
+            Some(algorithm) => {
+                compression::decompress(&bytes, algorithm, compression::MAX_DECOMPRESSED_SIZE)
+                    .map_err(|e| match e {
+                        DecompressError::TooLarge { limit } => {
+                            ProtocolError::DecompressedTooLarge { limit }
+                        }
+                        DecompressError::Corrupt(message) => ProtocolError::Decompress { message },
+                    })?
+            }
+            None => bytes,
+        };
+        let file = if stream.announced.file {
+            let (file, prefix) = metadata::split(&bytes).ok_or(ProtocolError::MetadataInvalid)?;
+            bytes.drain(..prefix);
+            Some(file).filter(|file| !file.is_empty())
+        } else {
+            None
+        };
+
+        let size = bytes.len();
+        Ok(ReconstructedStream {
+            stream_id: stream_id.to_string(),
+            size,
+            data: bytes,
+            chunks: stream.total,
+            duration: stream.started.elapsed().as_millis() as u64,
+            verification,
+            signature,
+            signer: signer.map(hex::encode),
+            signer_name: trusted.map(|sender| sender.name.clone()),
+            compression: stream.header.compression,
+            encrypted: stream.header.encryption.is_some(),
+            sealed: stream.header.recipient.is_some(),
+            transfer_size: (transfer_size != size).then_some(transfer_size),
+            extra_symbols: match &stream.assembly {
+                Some(Assembly::RaptorQ(decoder)) => decoder.extra_symbols(),
+                _ => None,
+            },
+            file,
+        })
+    }
+
+    /// Forgets a stream in memory; its journal stays, see
+    /// [`StreamStore::discard_stored`].
+    pub fn clear_stream(&mut self, stream_id: &str) {
+        self.streams.remove(stream_id);
+    }
+
+    pub fn clear_all(&mut self) {
+        self.streams.clear();
+    }
+
+    /// Every journaled stream, most recently updated first.
+    pub fn stored_streams(&self) -> Vec<StoredStream> {
+        let Some(journal) = &self.journal else {
+            return Vec::new();
+        };
+        let mut streams: Vec<StoredStream> = journal
+            .load_all()
+            .into_iter()
+            .map(|stored| {
+                let progress = self.progress(&stored.stream_id).or_else(|| {
+                    let mut scratch = Self::new();
+                    scratch.replay(&stored.payloads);
+                    scratch.progress(&stored.stream_id)
+                });
+                StoredStream {
+                    id: stored.stream_id,
+                    progress,
+                    frames: stored.payloads.len(),
+                    size: stored.size,
+                    updated: stored.updated,
+                }
+            })
+            .collect();
+        streams.reverse();
+        streams
+    }
+
+    /// Rebuilds a stream from its journal, replacing what memory holds of
+    /// it. `None` when the stream has no journal.
+    pub fn resume_stored(&mut self, stream_id: &str) -> Option<Progress> {
+        let stored = self.journal.as_ref()?.load(stream_id).ok()??;
+        self.streams.remove(stream_id);
+        self.replay(&stored.payloads);
+        self.progress(stream_id)
+    }
+
+    /// Deletes the journal of a stream whose payload was saved, keeping
+    /// it in memory. Frames that still arrive for it are not journaled.
+    pub fn settle(&mut self, stream_id: &str) -> Result<(), String> {
+        if let Some(stream) = self.streams.get_mut(stream_id) {
+            stream.settled = true;
+        }
+        match &self.journal {
+            Some(journal) => journal
+                .remove(stream_id)
+                .map(|_| ())
+                .map_err(|e| format!("Failed to delete journal: {}", e)),
+            None => Ok(()),
+        }
+    }
+
+    /// Deletes the journal of a stream and forgets it in memory. Returns
+    /// whether there was a journal.
+    pub fn discard_stored(&mut self, stream_id: &str) -> Result<bool, String> {
+        self.streams.remove(stream_id);
+        match &self.journal {
+            Some(journal) => journal
+                .remove(stream_id)
+                .map_err(|e| format!("Failed to discard stream {}: {}", stream_id, e)),
+            None => Ok(false),
+        }
+    }
+
+    pub fn active_streams(&self) -> Vec<StreamSummary> {
+        let mut streams: Vec<_> = self
+            .streams
+            .iter()
+            .map(|(id, stream)| StreamSummary {
+                id: id.clone(),
+                progress: stream.progress(),
+            })
+            .collect();
+        streams.sort_by(|a, b| a.id.cmp(&b.id));
+        streams
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &str, seq: u32, total: u32, data: &[u8]) -> String {
+        serde_json::json!({
+            "id": id,
+            "seq": seq,
+            "total": total,
+            "data": BASE64.encode(data),
+            "checksum": checksum::crc32(data),
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn parses_alternative_field_names() {
+        let frame = parse_frame(
+            r#"{"streamId": "s", "chunkIndex": "2", "totalChunks": 3.0, "payload": "YWJj"}"#,
+        )
+        .unwrap();
+        let Frame::Data(chunk) = frame else {
+            panic!("expected a data frame");
+        };
+        assert_eq!(
+            (chunk.stream_id.as_str(), chunk.sequence, chunk.total),
+            ("s", 2, 3)
+        );
+        assert_eq!(chunk.data.decode().unwrap(), b"abc");
+        assert_eq!(chunk.header.mode, Some(StreamMode::Chunks));
+    }
+
+    #[test]
+    fn rejects_malformed_frames() {
+        assert!(matches!(
+            parse_frame("not json"),
+            Err(ProtocolError::Parse { .. })
+        ));
+        assert_eq!(parse_frame("[1, 2]"), Err(ProtocolError::InvalidFormat));
+        assert_eq!(
+            parse_frame(r#"{"id": "s", "seq": 0, "total": 0, "data": "YQ=="}"#),
+            Err(ProtocolError::InvalidFormat)
+        );
+        assert_eq!(
+            parse_frame(r#"{"id": "s", "seq": 0, "total": 1}"#),
+            Err(ProtocolError::InvalidFormat)
+        );
+    }
+
+    #[test]
+    fn rejects_totals_and_lengths_above_the_limits() {
+        let total = format!(
+            r#"{{"id": "s", "seq": 0, "total": {}, "data": "YQ=="}}"#,
+            MAX_TOTAL + 1
+        );
+        assert!(matches!(
+            parse_frame(&total),
+            Err(ProtocolError::Parse { .. })
+        ));
+        let header = format!(
+            r#"{{"type": "header", "id": "s", "total": 1, "length": {}}}"#,
+            MAX_LENGTH + 1
+        );
+        assert!(matches!(
+            parse_frame(&header),
+            Err(ProtocolError::Parse { .. })
+        ));
+        let huge = format!(
+            r#"{{"id": "s", "seq": 0, "total": {}, "data": "YQ=="}}"#,
+            MAX_TOTAL
+        );
+        assert!(parse_frame(&huge).is_ok());
+    }
+
+    #[test]
+    fn tracks_duplicates_and_missing_chunks() {
+        let mut store = StreamStore::new();
+        let first = store.process_chunk(&chunk("s", 0, 3, b"ab")).unwrap();
+        assert_eq!(first.status, ChunkStatus::Progress);
+        assert_eq!(first.progress.missing, vec![1, 2]);
+
+        let again = store.process_chunk(&chunk("s", 0, 3, b"ab")).unwrap();
+        assert_eq!(again.status, ChunkStatus::Duplicate);
+        assert_eq!(again.progress.received, 1);
+
+        store.process_chunk(&chunk("s", 2, 3, b"ef")).unwrap();
+        assert_eq!(store.missing_chunks("s"), vec![1]);
+        assert_eq!(store.progress("s").unwrap().percentage, 67);
+        assert!(store.missing_chunks("other").is_empty());
+    }
+
+    #[test]
+    fn reconstructs_chunks_in_sequence_order() {
+        let mut store = StreamStore::new();
+        store.process_chunk(&chunk("s", 2, 3, b"ef")).unwrap();
+        store.process_chunk(&chunk("s", 0, 3, b"ab")).unwrap();
+        assert_eq!(
+            store.reconstruct("s"),
+            Err(ProtocolError::Incomplete {
+                received: 2,
+                total: 3
+            })
+        );
+
+        let last = store.process_chunk(&chunk("s", 1, 3, b"cd")).unwrap();
+        assert_eq!(last.status, ChunkStatus::Complete);
+        assert!(last.is_complete);
+        let stream = store.reconstruct("s").unwrap();
+        assert_eq!(stream.data, b"abcdef");
+        assert_eq!(stream.chunks, 3);
+        assert_eq!(stream.verification, Verification::Unverified);
+    }
+
+    #[test]
+    fn rejects_corrupted_and_inconsistent_chunks() {
+        let mut store = StreamStore::new();
+        store.process_chunk(&chunk("s", 0, 2, b"ab")).unwrap();
+        let corrupted = r#"{"id": "s", "seq": 1, "total": 2, "data": "Y2Q=", "checksum": 1}"#;
+        assert!(matches!(
+            store.process_chunk(corrupted),
+            Err(ProtocolError::ChecksumMismatch { sequence: 1, .. })
+        ));
+        assert_eq!(store.progress("s").unwrap().corrupted, 1);
+        assert_eq!(
+            store.process_chunk(&chunk("s", 1, 3, b"cd")),
+            Err(ProtocolError::TotalMismatch {
+                expected: 2,
+                got: 3
+            })
+        );
+        assert_eq!(
+            store.process_chunk(&chunk("s", 5, 2, b"cd")),
+            Err(ProtocolError::InvalidFormat)
+        );
+    }
+
+    fn header(id: &str, total: u32, data: &[u8]) -> String {
+        serde_json::json!({
+            "type": "header",
+            "id": id,
+            "total": total,
+            "digest": hex::encode(checksum::sha256(data)),
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn verifies_the_stream_digest() {
+        let mut store = StreamStore::new();
+        store.process_chunk(&header("s", 2, b"abcd")).unwrap();
+        store.process_chunk(&chunk("s", 0, 2, b"ab")).unwrap();
+        store.process_chunk(&chunk("s", 1, 2, b"cd")).unwrap();
+        let stream = store.reconstruct("s").unwrap();
+        assert_eq!(stream.verification, Verification::Verified);
+
+        store.process_chunk(&header("t", 1, b"other")).unwrap();
+        store.process_chunk(&chunk("t", 0, 1, b"ab")).unwrap();
+        assert!(matches!(
+            store.reconstruct("t"),
+            Err(ProtocolError::DigestMismatch { .. })
+        ));
+        assert_eq!(
+            store.process_chunk(&header("s", 2, b"other")).unwrap_err(),
+            ProtocolError::HeaderConflict { field: "digest" }
+        );
+    }
+
+    #[test]
+    fn checks_the_signature_and_its_signer() {
+        let secret = [4; 32];
+        let digest = checksum::sha256(b"abcd");
+        let signed = |id: &str, signature: StreamSignature| Frame::Header {
+            stream_id: id.to_string(),
+            total: 1,
+            header: StreamHeader {
+                digest: Some(digest),
+                signature: Some(signature),
+                ..StreamHeader::default()
+            },
+        };
+        let mut store = StreamStore::new();
+        store
+            .insert(signed("s", StreamSignature::sign(&secret, &digest)))
+            .unwrap();
+        store.process_chunk(&chunk("s", 0, 1, b"abcd")).unwrap();
+        store
+            .insert(signed("t", StreamSignature::sign(&secret, &[0; 32])))
+            .unwrap();
+        store.process_chunk(&chunk("t", 0, 1, b"abcd")).unwrap();
+        assert_eq!(
+            store.reconstruct("t").unwrap_err(),
+            ProtocolError::SignatureInvalid
+        );
+
+        let stream = store.reconstruct("s").unwrap();
+        assert_eq!(stream.signature, SignatureStatus::SignedByUnknown);
+        let path =
+            std::env::temp_dir().join(format!("streaming-qr-signers-{}.json", std::process::id()));
+        let mut keyring = Keyring::load(&path).unwrap();
+        keyring
+            .add("sender", &hex::encode(signature::public_key(&secret)))
+            .unwrap();
+        let credentials = Credentials {
+            keyring: Some(&keyring),
+            ..Credentials::default()
+        };
+        let stream = store.reconstruct_with("s", credentials).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(stream.signature, SignatureStatus::SignedByKnown);
+        assert_eq!(stream.signer_name.as_deref(), Some("sender"));
+    }
+
+    #[test]
+    fn waits_for_an_announced_digest() {
+        let announced = |seq: u32, data: &[u8]| {
+            let mut value: Value = serde_json::from_str(&chunk("s", seq, 2, data)).unwrap();
+            value["hasDigest"] = Value::Bool(true);
+            value.to_string()
+        };
+        let mut store = StreamStore::new();
+        store.process_chunk(&announced(0, b"ab")).unwrap();
+        let last = store.process_chunk(&announced(1, b"cd")).unwrap();
+        assert!(!last.is_complete);
+        assert!(last.progress.awaiting_header);
+        assert!(last.progress.missing.is_empty());
+        assert_eq!(
+            store.reconstruct("s").unwrap_err(),
+            ProtocolError::HeaderMissing { field: "digest" }
+        );
+
+        let header = store.process_chunk(&header("s", 2, b"abcd")).unwrap();
+        assert_eq!(header.status, ChunkStatus::Header);
+        assert!(header.is_complete);
+        assert!(!header.progress.awaiting_header);
+        let stream = store.reconstruct("s").unwrap();
+        assert_eq!(stream.verification, Verification::Verified);
+    }
+
+    #[test]
+    fn encrypted_streams_wait_for_their_header() {
+        let frames = crate::encoder::encode_stream(
+            "7",
+            b"attack at dawn",
+            &crate::encoder::EncodeOptions {
+                passphrase: Some("pw".into()),
+                chunk_size: 8,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut store = StreamStore::new();
+        for frame in &frames[1..] {
+            let outcome = store
+                .process_payload(&crate::frame::encode(frame).unwrap())
+                .unwrap();
+            assert!(!outcome.is_complete);
+        }
+        assert_eq!(
+            store.reconstruct("7").unwrap_err(),
+            ProtocolError::HeaderMissing {
+                field: "encryption"
+            }
+        );
+
+        store.insert(frames[0].clone()).unwrap();
+        assert_eq!(
+            store.reconstruct("7").unwrap_err(),
+            ProtocolError::PassphraseRequired
+        );
+        let stream = store
+            .reconstruct_with(
+                "7",
+                Credentials {
+                    passphrase: Some("pw"),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert!(stream.encrypted);
+        assert_eq!(stream.data, b"attack at dawn");
+    }
+
+    #[test]
+    fn sealed_streams_wait_for_their_header() {
+        let receiver = ReceiverKey::generate();
+        let frames = crate::encoder::encode_stream(
+            "8",
+            b"for your eyes only",
+            &crate::encoder::EncodeOptions {
+                recipient: Some(receiver.public_key()),
+                chunk_size: 8,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut store = StreamStore::new();
+        for frame in &frames[1..] {
+            let outcome = store
+                .process_payload(&crate::frame::encode(frame).unwrap())
+                .unwrap();
+            assert!(!outcome.is_complete);
+        }
+        assert_eq!(
+            store.reconstruct("8").unwrap_err(),
+            ProtocolError::HeaderMissing { field: "recipient" }
+        );
+
+        store.insert(frames[0].clone()).unwrap();
+        assert_eq!(
+            store.reconstruct("8").unwrap_err(),
+            ProtocolError::WrongReceiver
+        );
+        let stream = store
+            .reconstruct_with(
+                "8",
+                Credentials {
+                    receiver: Some(&receiver),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert!(stream.sealed);
+        assert_eq!(stream.data, b"for your eyes only");
+    }
+
+    /// A chunk of a six-chunk stream shown on a grid `columns` wide and two
+    /// rows high.
+    fn cell_chunk(seq: u32, columns: u32, data: &[u8], checksum: u32) -> String {
+        serde_json::json!({
+            "id": "g",
+            "seq": seq,
+            "total": 6,
+            "data": BASE64.encode(data),
+            "checksum": checksum,
+            "cell": {"columns": columns, "rows": 2, "index": seq % (columns * 2)},
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn counts_chunks_per_grid_cell() {
+        let mut store = StreamStore::new();
+        for seq in [0, 1, 2, 4, 0] {
+            store
+                .process_chunk(&cell_chunk(seq, 2, b"ab", checksum::crc32(b"ab")))
+                .unwrap();
+        }
+        assert!(matches!(
+            store.process_chunk(&cell_chunk(3, 2, b"ab", 0)),
+            Err(ProtocolError::ChecksumMismatch { sequence: 3, .. })
+        ));
+
+        let cells = store.progress("g").unwrap().cells.unwrap();
+        assert_eq!(
+            cells.grid,
+            Grid {
+                columns: 2,
+                rows: 2
+            }
+        );
+        assert_eq!(cells.received, [2, 1, 1, 0]);
+        assert_eq!(cells.corrupted, [0, 0, 0, 1]);
+        // Chunks 3 and 5 are missing, in cells 3 and 1.
+        assert_eq!(cells.missing, [0, 1, 0, 1]);
+
+        let other_grid = cell_chunk(5, 3, b"ab", checksum::crc32(b"ab"));
+        assert_eq!(
+            store.process_chunk(&other_grid),
+            Err(ProtocolError::HeaderConflict { field: "cell" })
+        );
+    }
+
+    #[test]
+    fn grid_screens_decode_into_every_symbol() {
+        use crate::scanner;
+        use crate::sender::{self, FrameKind, SendOptions};
+
+        let data: Vec<u8> = (0..1200u32).map(|i| (i % 233) as u8).collect();
+        let options = SendOptions {
+            version: Some(4),
+            grid: Some(Grid {
+                columns: 2,
+                rows: 2,
+            }),
+            ..SendOptions::default()
+        };
+        let (stream_id, screens) = sender::stream_screens(&data, &options).unwrap();
+        let mut store = StreamStore::new();
+        for screen in &screens {
+            let mosaic = &screen.layers()[0];
+            let (width, height) = mosaic.image_size(3);
+            let symbols = scanner::scan(width, height, &mosaic.to_luma(3));
+            assert_eq!(symbols.len(), screen.frames.len());
+            for symbol in symbols {
+                store.process_payload(&symbol.content.unwrap()).unwrap();
+            }
+        }
+        assert!(screens.iter().any(|screen| screen.frames.len() == 4));
+        let data_frames = screens
+            .iter()
+            .filter(|screen| screen.kind == FrameKind::Data)
+            .map(|screen| screen.frames.len() as u32)
+            .sum::<u32>();
+        let stream_id = stream_id.to_string();
+        let cells = store.progress(&stream_id).unwrap().cells.unwrap();
+        assert_eq!(cells.received.iter().sum::<u32>(), data_frames);
+        assert_eq!(cells.missing, [0; 4]);
+        assert_eq!(store.reconstruct(&stream_id).unwrap().data, data);
+    }
+
+    #[test]
+    fn bounds_a_parity_stream_last_chunk_that_comes_first() {
+        let parity_chunk = |seq: u32, data: &[u8]| {
+            serde_json::json!({
+                "id": "p",
+                "seq": seq,
+                "total": 3,
+                "data": BASE64.encode(data),
+                "checksum": checksum::crc32(data),
+                "groupSize": 3,
+                "parity": 1,
+                "length": 10,
+            })
+            .to_string()
+        };
+        let mut store = StreamStore::new();
+        assert_eq!(
+            store.process_chunk(&parity_chunk(2, b"ijklmnopq")),
+            Err(ProtocolError::BlockSizeMismatch {
+                expected: 8,
+                got: 9
+            })
+        );
+        // Within the length, but longer than the chunks turn out to be.
+        store.process_chunk(&parity_chunk(2, b"ijklmn")).unwrap();
+        store.process_chunk(&parity_chunk(0, b"abcd")).unwrap();
+        assert_eq!(store.progress("p").unwrap().missing, [1, 2]);
+
+        store.process_chunk(&parity_chunk(2, b"ij")).unwrap();
+        store.process_chunk(&parity_chunk(1, b"efgh")).unwrap();
+        assert_eq!(store.reconstruct("p").unwrap().data, b"abcdefghij");
+    }
+
+    #[test]
+    fn clears_streams() {
+        let mut store = StreamStore::new();
+        store.process_chunk(&chunk("a", 0, 2, b"ab")).unwrap();
+        store.process_chunk(&chunk("b", 0, 2, b"ab")).unwrap();
+        let ids: Vec<String> = store.active_streams().into_iter().map(|s| s.id).collect();
+        assert_eq!(ids, ["a", "b"]);
+
+        store.clear_stream("a");
+        assert!(matches!(
+            store.reconstruct("a"),
+            Err(ProtocolError::StreamNotFound { .. })
+        ));
+        store.clear_all();
+        assert!(store.active_streams().is_empty());
+    }
+
+    #[test]
+    fn replays_journals_until_the_stream_is_settled() {
+        let dir = std::env::temp_dir().join(format!("streaming-qr-store-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let (mut store, _) = StreamStore::with_journal(Journal::new(&dir));
+        store.process_chunk(&chunk("a", 0, 2, b"ab")).unwrap();
+        store.process_chunk(&chunk("a", 0, 2, b"ab")).unwrap();
+
+        let (mut store, errors) = StreamStore::with_journal(Journal::new(&dir));
+        assert!(errors.is_empty());
+        let stored = store.stored_streams();
+        assert_eq!((stored.len(), stored[0].frames), (1, 1));
+        let progress = store.progress("a").unwrap();
+        assert_eq!((progress.received, progress.is_complete), (1, false));
+        store.process_chunk(&chunk("a", 1, 2, b"cd")).unwrap();
+        assert_eq!(store.reconstruct("a").unwrap().data, b"abcd");
+        // Rebuilding alone keeps the journal until the payload is saved.
+        let (mut store, _) = StreamStore::with_journal(Journal::new(&dir));
+        assert!(store.progress("a").unwrap().is_complete);
+
+        store.settle("a").unwrap();
+        assert!(store.stored_streams().is_empty());
+        assert_eq!(store.reconstruct("a").unwrap().data, b"abcd");
+        let (store, _) = StreamStore::with_journal(Journal::new(&dir));
+        assert!(store.active_streams().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
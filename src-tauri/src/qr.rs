@@ -0,0 +1,322 @@
+//! QR symbol generation and rendering for the sender.
+//!
+//! Frames are always encoded as a single byte-mode segment. Letting the
+//! encoder pick numeric or alphanumeric segments for parts of a binary
+//! frame would save a few modules, but readers such as ZXing only report
+//! byte segments as raw bytes, so the frame would not survive the trip.
+
+use std::fmt::Write as _;
+
+use qrcode::bits::Bits;
+use qrcode::{Color, EcLevel, QrCode, Version};
+use serde::{Deserialize, Serialize};
+
+pub const MIN_VERSION: u8 = 1;
+pub const MAX_VERSION: u8 = 40;
+/// Light modules around the symbol required by ISO/IEC 18004.
+pub const QUIET_ZONE: usize = 4;
+
+/// Error correction level, from 7% (L) to 30% (H) recoverable codewords.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EccLevel {
+    L,
+    #[default]
+    M,
+    Q,
+    H,
+}
+
+impl EccLevel {
+    fn to_qrcode(self) -> EcLevel {
+        match self {
+            EccLevel::L => EcLevel::L,
+            EccLevel::M => EcLevel::M,
+            EccLevel::Q => EcLevel::Q,
+            EccLevel::H => EcLevel::H,
+        }
+    }
+}
+
+/// Bytes a single byte-mode segment can hold in `version` at `ecc`.
+pub fn byte_capacity(version: u8, ecc: EccLevel) -> usize {
+    let bits = Bits::new(Version::Normal(version as i16))
+        .max_len(ecc.to_qrcode())
+        .unwrap_or(0);
+    let header = 4 + if version < 10 { 8 } else { 16 };
+    bits.saturating_sub(header) / 8
+}
+
+/// Smallest version from `min_version` up that fits `len` bytes.
+pub fn smallest_version(len: usize, ecc: EccLevel, min_version: u8) -> Option<u8> {
+    (min_version.max(MIN_VERSION)..=MAX_VERSION).find(|version| byte_capacity(*version, ecc) >= len)
+}
+
+/// An encoded QR symbol, without quiet zone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QrSymbol {
+    pub version: u8,
+    pub ecc: EccLevel,
+    /// Modules per side.
+    pub width: usize,
+    /// Row-major, `true` for dark modules.
+    pub modules: Vec<bool>,
+}
+
+impl QrSymbol {
+    /// Encodes `data` in exactly `version`.
+    pub fn encode(data: &[u8], version: u8, ecc: EccLevel) -> Result<Self, String> {
+        if !(MIN_VERSION..=MAX_VERSION).contains(&version) {
+            return Err(format!("Invalid QR version {}", version));
+        }
+        let too_long = || {
+            format!(
+                "{} bytes do not fit a version {} QR code at ECC level {:?}",
+                data.len(),
+                version,
+                ecc
+            )
+        };
+        let mut bits = Bits::new(Version::Normal(version as i16));
+        bits.push_byte_data(data).map_err(|_| too_long())?;
+        bits.push_terminator(ecc.to_qrcode())
+            .map_err(|_| too_long())?;
+        let code = QrCode::with_bits(bits, ecc.to_qrcode()).map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            version,
+            ecc,
+            width: code.width(),
+            modules: code
+                .to_colors()
+                .into_iter()
+                .map(|color| color == Color::Dark)
+                .collect(),
+        })
+    }
+
+    /// Encodes `data` in the smallest version not below `min_version`.
+    pub fn encode_fitting(data: &[u8], min_version: u8, ecc: EccLevel) -> Result<Self, String> {
+        let version = smallest_version(data.len(), ecc, min_version)
+            .ok_or_else(|| format!("{} bytes do not fit any QR code", data.len()))?;
+        Self::encode(data, version, ecc)
+    }
+
+    pub fn is_dark(&self, x: usize, y: usize) -> bool {
+        self.modules[y * self.width + x]
+    }
+
+    pub fn rows(&self) -> Vec<Vec<bool>> {
+        self.modules
+            .chunks(self.width)
+            .map(<[bool]>::to_vec)
+            .collect()
+    }
+
+    /// Side length in pixels when rendered at `scale` with the quiet zone.
+    pub fn image_size(&self, scale: usize) -> usize {
+        (self.width + 2 * QUIET_ZONE) * scale
+    }
+
+    /// 8-bit grayscale pixels (0 dark, 255 light), quiet zone included.
+    pub fn to_luma(&self, scale: usize) -> Vec<u8> {
+        Mosaic::from(self).to_luma(scale)
+    }
+
+    pub fn to_png(&self, scale: usize) -> Vec<u8> {
+        Mosaic::from(self).to_png(scale)
+    }
+
+    /// SVG with one unit per module, to be scaled freely by the viewer.
+    pub fn to_svg(&self) -> String {
+        Mosaic::from(self).to_svg()
+    }
+}
+
+/// Symbols tiled on a grid to be shown together, each pair of neighbours
+/// separated by a quiet zone. Every cell is as large as the largest
+/// symbol; smaller symbols sit in the top left corner of their cell.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mosaic {
+    /// Modules per row, without the outer quiet zone.
+    pub width: usize,
+    /// Modules per column, without the outer quiet zone.
+    pub height: usize,
+    /// Row-major, `true` for dark modules.
+    pub modules: Vec<bool>,
+}
+
+impl From<&QrSymbol> for Mosaic {
+    fn from(symbol: &QrSymbol) -> Self {
+        Self {
+            width: symbol.width,
+            height: symbol.width,
+            modules: symbol.modules.clone(),
+        }
+    }
+}
+
+impl Mosaic {
+    /// Lays `symbols` out row by row on a `columns` × `rows` grid. Cells
+    /// beyond the last symbol stay blank, so the layout does not shift.
+    pub fn new(symbols: &[&QrSymbol], columns: usize, rows: usize) -> Self {
+        let columns = columns.max(1);
+        let rows = rows.max(symbols.len().div_ceil(columns)).max(1);
+        let cell = symbols.iter().map(|symbol| symbol.width).max().unwrap_or(0);
+        let pitch = cell + QUIET_ZONE;
+        let width = columns * pitch - QUIET_ZONE;
+        let height = rows * pitch - QUIET_ZONE;
+
+        let mut modules = vec![false; width * height];
+        for (index, symbol) in symbols.iter().enumerate() {
+            let (left, top) = ((index % columns) * pitch, (index / columns) * pitch);
+            for (y, row) in symbol.modules.chunks(symbol.width).enumerate() {
+                let start = (top + y) * width + left;
+                modules[start..start + symbol.width].copy_from_slice(row);
+            }
+        }
+        Self {
+            width,
+            height,
+            modules,
+        }
+    }
+
+    pub fn is_dark(&self, x: usize, y: usize) -> bool {
+        self.modules[y * self.width + x]
+    }
+
+    pub fn rows(&self) -> Vec<Vec<bool>> {
+        self.modules
+            .chunks(self.width)
+            .map(<[bool]>::to_vec)
+            .collect()
+    }
+
+    /// Width and height in pixels when rendered at `scale` with the quiet
+    /// zone.
+    pub fn image_size(&self, scale: usize) -> (usize, usize) {
+        (
+            (self.width + 2 * QUIET_ZONE) * scale,
+            (self.height + 2 * QUIET_ZONE) * scale,
+        )
+    }
+
+    /// 8-bit grayscale pixels (0 dark, 255 light), quiet zone included.
+    pub fn to_luma(&self, scale: usize) -> Vec<u8> {
+        let (image_width, image_height) = self.image_size(scale);
+        let mut pixels = vec![255u8; image_width * image_height];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if !self.is_dark(x, y) {
+                    continue;
+                }
+                for row in 0..scale {
+                    let start =
+                        ((y + QUIET_ZONE) * scale + row) * image_width + (x + QUIET_ZONE) * scale;
+                    pixels[start..start + scale].fill(0);
+                }
+            }
+        }
+        pixels
+    }
+
+    pub fn to_png(&self, scale: usize) -> Vec<u8> {
+        let (width, height) = self.image_size(scale);
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, width as u32, height as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.to_luma(scale)))
+            .expect("writing a PNG into memory cannot fail");
+        png
+    }
+
+    /// SVG with one unit per module, to be scaled freely by the viewer.
+    pub fn to_svg(&self) -> String {
+        let (width, height) = self.image_size(1);
+        let mut path = String::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if self.is_dark(x, y) {
+                    let _ = write!(path, "M{} {}h1v1h-1z", x + QUIET_ZONE, y + QUIET_ZONE);
+                }
+            }
+        }
+        format!(
+            concat!(
+                r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {width} {height}" "#,
+                r##"shape-rendering="crispEdges"><rect width="{width}" height="{height}" fill="#fff"/>"##,
+                r##"<path fill="#000" d="{path}"/></svg>"##
+            ),
+            width = width,
+            height = height,
+            path = path
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner;
+
+    fn scan(mosaic: &Mosaic) -> Vec<Vec<u8>> {
+        let (width, height) = mosaic.image_size(4);
+        scanner::scan(width, height, &mosaic.to_luma(4))
+            .into_iter()
+            .map(|symbol| symbol.content.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn rendered_symbols_decode_to_the_same_bytes() {
+        let data: Vec<u8> = (0..=255).collect();
+        for ecc in [EccLevel::L, EccLevel::M, EccLevel::Q, EccLevel::H] {
+            let symbol = QrSymbol::encode_fitting(&data, MIN_VERSION, ecc).unwrap();
+            assert_eq!(symbol.width, 17 + 4 * symbol.version as usize);
+            assert_eq!(scan(&Mosaic::from(&symbol)), vec![data.clone()]);
+        }
+    }
+
+    #[test]
+    fn byte_capacity_is_exactly_what_fits() {
+        for (version, ecc) in [
+            (1, EccLevel::L),
+            (9, EccLevel::M),
+            (10, EccLevel::Q),
+            (25, EccLevel::H),
+        ] {
+            let capacity = byte_capacity(version, ecc);
+            let full = vec![0xa5; capacity];
+            let symbol = QrSymbol::encode(&full, version, ecc).unwrap();
+            assert_eq!(scan(&Mosaic::from(&symbol)), [full]);
+            assert!(QrSymbol::encode(&vec![0; capacity + 1], version, ecc).is_err());
+            assert_eq!(
+                smallest_version(capacity + 1, ecc, version),
+                Some(version + 1)
+            );
+        }
+        assert_eq!(byte_capacity(1, EccLevel::M), 14);
+        assert_eq!(smallest_version(10_000, EccLevel::L, MIN_VERSION), None);
+    }
+
+    #[test]
+    fn mosaics_keep_every_symbol_readable() {
+        let payloads: Vec<Vec<u8>> = (0..3).map(|i| vec![i; 40]).collect();
+        let symbols: Vec<QrSymbol> = payloads
+            .iter()
+            .map(|data| QrSymbol::encode(data, 4, EccLevel::M).unwrap())
+            .collect();
+        let refs: Vec<&QrSymbol> = symbols.iter().collect();
+        let mosaic = Mosaic::new(&refs, 2, 2);
+        assert_eq!(
+            (mosaic.width, mosaic.height),
+            (2 * 33 + QUIET_ZONE, 2 * 33 + QUIET_ZONE)
+        );
+        let mut decoded = scan(&mosaic);
+        decoded.sort();
+        assert_eq!(decoded, payloads);
+    }
+}
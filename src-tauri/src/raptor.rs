@@ -0,0 +1,236 @@
+//! RaptorQ (RFC 6330) erasure-coded streams.
+//!
+//! The sender emits the source symbols of every source block followed by
+//! repair symbols. Each frame carries one serialized encoding packet (the
+//! 4-byte payload id followed by the symbol) and the 12-byte Object
+//! Transmission Information (OTI) from section 3.3 of the RFC. The object is
+//! recovered once enough symbols of each source block arrived, with only a
+//! couple of symbols of overhead in practice.
+
+use std::collections::HashSet;
+
+use raptorq::{Decoder, Encoder, EncodingPacket, ObjectTransmissionInformation, PayloadId};
+
+/// Largest number of source symbols per block allowed by the RFC (K'_max).
+const MAX_SOURCE_SYMBOLS_PER_BLOCK: u64 = 56403;
+
+/// Produces RaptorQ packets for a payload.
+pub struct RaptorEncoder {
+    encoder: Encoder,
+}
+
+/// Serialized payload id in front of every symbol.
+const PAYLOAD_ID_LEN: u16 = 4;
+
+impl RaptorEncoder {
+    /// Splits `data` so every serialized packet, payload id included, is at
+    /// most `packet_size` bytes. Returns `None` for empty data or packets
+    /// too small to hold a symbol.
+    pub fn new(data: &[u8], packet_size: u16) -> Option<Self> {
+        let symbol_size = packet_size
+            .checked_sub(PAYLOAD_ID_LEN)
+            .filter(|size| *size > 0)?;
+        (!data.is_empty()).then(|| Self {
+            encoder: Encoder::with_defaults(data, symbol_size),
+        })
+    }
+
+    /// Serialized OTI to put in the frame header.
+    pub fn oti(&self) -> [u8; 12] {
+        self.encoder.get_config().serialize()
+    }
+
+    /// Source symbols across all blocks, i.e. the minimum a receiver needs.
+    pub fn source_symbols(&self) -> u32 {
+        source_symbols(&self.encoder.get_config())
+    }
+
+    pub fn source_blocks(&self) -> u32 {
+        self.encoder.get_config().source_blocks() as u32
+    }
+
+    /// Every source packet followed by `repair_per_block` repair packets for
+    /// each source block, serialized.
+    pub fn packets(&self, repair_per_block: u32) -> Vec<Vec<u8>> {
+        self.encoder
+            .get_encoded_packets(repair_per_block)
+            .iter()
+            .map(EncodingPacket::serialize)
+            .collect()
+    }
+}
+
+/// Number of source symbols the OTI describes, across all source blocks.
+pub fn source_symbols(config: &ObjectTransmissionInformation) -> u32 {
+    config
+        .transfer_length()
+        .div_ceil(config.symbol_size() as u64) as u32
+}
+
+/// Parses an OTI and rejects parameters the decoder would choke on.
+pub fn parse_oti(bytes: &[u8]) -> Option<ObjectTransmissionInformation> {
+    let bytes: &[u8; 12] = bytes.try_into().ok()?;
+    let config = ObjectTransmissionInformation::deserialize(bytes);
+
+    let symbol_size = config.symbol_size() as u64;
+    let blocks = config.source_blocks() as u64;
+    let valid = symbol_size > 0
+        && blocks > 0
+        && config.sub_blocks() > 0
+        && config.symbol_alignment() > 0
+        && symbol_size.is_multiple_of(config.symbol_alignment() as u64)
+        && config
+            .transfer_length()
+            .div_ceil(symbol_size)
+            .div_ceil(blocks)
+            <= MAX_SOURCE_SYMBOLS_PER_BLOCK;
+    valid.then_some(config)
+}
+
+/// Result of feeding a packet to [`RaptorDecoder::add_packet`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketStatus {
+    Duplicate,
+    Accepted,
+    Invalid,
+}
+
+/// Collects packets until the object can be recovered.
+pub struct RaptorDecoder {
+    config: ObjectTransmissionInformation,
+    decoder: Decoder,
+    seen: HashSet<[u8; 4]>,
+    result: Option<Vec<u8>>,
+    /// Symbols that had arrived when the object was recovered.
+    symbols_used: Option<u32>,
+}
+
+impl std::fmt::Debug for RaptorDecoder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RaptorDecoder")
+            .field("config", &self.config)
+            .field("symbols", &self.seen.len())
+            .field("complete", &self.result.is_some())
+            .finish()
+    }
+}
+
+impl RaptorDecoder {
+    pub fn new(config: ObjectTransmissionInformation) -> Self {
+        Self {
+            config,
+            decoder: Decoder::new(config),
+            seen: HashSet::new(),
+            result: None,
+            symbols_used: None,
+        }
+    }
+
+    pub fn source_symbols(&self) -> u32 {
+        source_symbols(&self.config)
+    }
+
+    /// Distinct symbols received so far.
+    pub fn symbols(&self) -> u32 {
+        self.seen.len() as u32
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.result.is_some()
+    }
+
+    /// Symbols beyond the source symbol count that were needed to recover
+    /// the object, once it is recovered.
+    pub fn extra_symbols(&self) -> Option<u32> {
+        self.symbols_used
+            .map(|used| used.saturating_sub(self.source_symbols()))
+    }
+
+    pub fn add_packet(&mut self, bytes: &[u8]) -> PacketStatus {
+        if bytes.len() != 4 + self.config.symbol_size() as usize {
+            return PacketStatus::Invalid;
+        }
+        let payload_id: [u8; 4] = bytes[..4].try_into().unwrap();
+        if PayloadId::deserialize(&payload_id).source_block_number() >= self.config.source_blocks()
+        {
+            return PacketStatus::Invalid;
+        }
+        if !self.seen.insert(payload_id) {
+            return PacketStatus::Duplicate;
+        }
+
+        if self.result.is_none() {
+            self.result = self.decoder.decode(EncodingPacket::deserialize(bytes));
+            if self.result.is_some() {
+                self.symbols_used = Some(self.symbols());
+            }
+        }
+        PacketStatus::Accepted
+    }
+
+    pub fn finish(&self) -> Option<Vec<u8>> {
+        self.result.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload() -> Vec<u8> {
+        (0..5000u32).map(|i| (i * 13 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn recovers_from_repair_symbols_in_place_of_lost_ones() {
+        let data = payload();
+        let encoder = RaptorEncoder::new(&data, 132).unwrap();
+        let packets = encoder.packets(10);
+        assert!(packets.iter().all(|packet| packet.len() <= 132));
+        assert_eq!(packets.len() as u32, encoder.source_symbols() + 10);
+
+        let mut decoder = RaptorDecoder::new(parse_oti(&encoder.oti()).unwrap());
+        assert_eq!(decoder.source_symbols(), encoder.source_symbols());
+        // The first eight source symbols are lost.
+        for packet in &packets[8..] {
+            assert_eq!(decoder.add_packet(packet), PacketStatus::Accepted);
+        }
+        assert!(decoder.is_complete());
+        assert_eq!(decoder.finish().unwrap(), data);
+        assert!(decoder.extra_symbols().is_some());
+    }
+
+    #[test]
+    fn stays_incomplete_with_too_few_symbols() {
+        let data = payload();
+        let encoder = RaptorEncoder::new(&data, 132).unwrap();
+        let mut decoder = RaptorDecoder::new(parse_oti(&encoder.oti()).unwrap());
+        for packet in encoder.packets(0).iter().skip(1) {
+            decoder.add_packet(packet);
+        }
+        assert!(!decoder.is_complete());
+        assert_eq!(decoder.finish(), None);
+    }
+
+    #[test]
+    fn rejects_duplicate_and_malformed_packets() {
+        let encoder = RaptorEncoder::new(&payload(), 132).unwrap();
+        let packets = encoder.packets(0);
+        let mut decoder = RaptorDecoder::new(parse_oti(&encoder.oti()).unwrap());
+        assert_eq!(decoder.add_packet(&packets[0]), PacketStatus::Accepted);
+        assert_eq!(decoder.add_packet(&packets[0]), PacketStatus::Duplicate);
+        assert_eq!(decoder.add_packet(&packets[1][1..]), PacketStatus::Invalid);
+        let mut other_block = packets[1].clone();
+        other_block[0] = 9;
+        assert_eq!(decoder.add_packet(&other_block), PacketStatus::Invalid);
+        assert_eq!(decoder.symbols(), 1);
+    }
+
+    #[test]
+    fn validates_the_oti() {
+        assert!(parse_oti(&[0; 11]).is_none());
+        assert!(parse_oti(&[0; 12]).is_none());
+        assert!(RaptorEncoder::new(&[], 132).is_none());
+        assert!(RaptorEncoder::new(&payload(), 4).is_none());
+    }
+}
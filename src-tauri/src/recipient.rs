@@ -0,0 +1,230 @@
+//! Public-key encryption of a stream to one receiver.
+//!
+//! Every receiver has a long-lived X25519 keypair kept in the app data dir
+//! and shows its public key as a QR code. The sender generates an ephemeral
+//! keypair per stream, derives a ChaCha20-Poly1305 key from the shared
+//! secret with HKDF-SHA256 (salted with both public keys) and puts its
+//! ephemeral public key, the receiver's public key and the nonce in the
+//! stream header. Only the device holding the matching secret can open it.
+
+use std::fs;
+use std::path::Path;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::signature;
+
+pub const FILE_NAME: &str = "receiver.key";
+const INFO: &[u8] = b"streaming-qr x25519 chacha20-poly1305 v1";
+
+/// Header fields of a stream sealed to a receiver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Recipient {
+    /// Public key of the receiver the stream is for.
+    pub public_key: [u8; 32],
+    /// Sender's ephemeral public key.
+    pub ephemeral: [u8; 32],
+    pub nonce: [u8; 12],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenError {
+    /// The stream was sealed to another public key.
+    WrongReceiver,
+    /// Authentication failed.
+    Tampered,
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    getrandom::fill(&mut bytes).expect("system random source unavailable");
+    bytes
+}
+
+fn cipher(shared: &[u8; 32], ephemeral: &[u8; 32], receiver: &[u8; 32]) -> ChaCha20Poly1305 {
+    let mut salt = [0u8; 64];
+    salt[..32].copy_from_slice(ephemeral);
+    salt[32..].copy_from_slice(receiver);
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(INFO, &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    ChaCha20Poly1305::new(&Key::from(key))
+}
+
+impl Recipient {
+    /// Encrypts `plaintext` so only the holder of `receiver`'s secret key
+    /// can read it.
+    pub fn seal(receiver: &[u8; 32], plaintext: &[u8]) -> (Self, Vec<u8>) {
+        let ephemeral = StaticSecret::from(random_bytes::<32>());
+        let ephemeral_public = PublicKey::from(&ephemeral).to_bytes();
+        let shared = ephemeral.diffie_hellman(&PublicKey::from(*receiver));
+
+        let recipient = Self {
+            public_key: *receiver,
+            ephemeral: ephemeral_public,
+            nonce: random_bytes(),
+        };
+        let ciphertext = cipher(shared.as_bytes(), &ephemeral_public, receiver)
+            .encrypt(&Nonce::from(recipient.nonce), plaintext)
+            .expect("payload fits the cipher limits");
+        (recipient, ciphertext)
+    }
+}
+
+/// The receiver's own X25519 keypair.
+pub struct ReceiverKey {
+    secret: StaticSecret,
+}
+
+impl std::fmt::Debug for ReceiverKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReceiverKey")
+            .field("public_key", &hex::encode(self.public_key()))
+            .finish_non_exhaustive()
+    }
+}
+
+impl ReceiverKey {
+    pub fn generate() -> Self {
+        Self::from_bytes(random_bytes())
+    }
+
+    pub fn from_bytes(secret: [u8; 32]) -> Self {
+        Self {
+            secret: StaticSecret::from(secret),
+        }
+    }
+
+    /// Parses a secret key stored as hex, as in the key file.
+    pub fn from_hex(text: &str) -> Result<Self, String> {
+        let mut secret = [0u8; 32];
+        hex::decode_to_slice(text.trim(), &mut secret)
+            .map_err(|e| format!("Invalid receiver key file: {}", e))?;
+        Ok(Self::from_bytes(secret))
+    }
+
+    /// Reads the secret key at `path`, creating and storing a new one when
+    /// there is none yet.
+    pub fn load_or_generate(path: &Path) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::from_hex(&text),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let key = Self::generate();
+                key.save(path)
+                    .map_err(|e| format!("Failed to save receiver key: {}", e))?;
+                Ok(key)
+            }
+            Err(e) => Err(format!("Failed to read receiver key: {}", e)),
+        }
+    }
+
+    fn save(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        std::io::Write::write_all(
+            &mut options.open(path)?,
+            hex::encode(self.secret.as_bytes()).as_bytes(),
+        )
+    }
+
+    pub fn public_key(&self) -> [u8; 32] {
+        PublicKey::from(&self.secret).to_bytes()
+    }
+
+    /// Text to show as a QR code so senders can scan the public key.
+    pub fn share_text(&self) -> String {
+        serde_json::json!({
+            "type": "receiver",
+            "publicKey": BASE64.encode(self.public_key()),
+        })
+        .to_string()
+    }
+
+    pub fn open(&self, recipient: &Recipient, ciphertext: &[u8]) -> Result<Vec<u8>, OpenError> {
+        let public_key = self.public_key();
+        if recipient.public_key != public_key {
+            return Err(OpenError::WrongReceiver);
+        }
+        let shared = self
+            .secret
+            .diffie_hellman(&PublicKey::from(recipient.ephemeral));
+        if !shared.was_contributory() {
+            return Err(OpenError::Tampered);
+        }
+        cipher(shared.as_bytes(), &recipient.ephemeral, &public_key)
+            .decrypt(&Nonce::from(recipient.nonce), ciphertext)
+            .map_err(|_| OpenError::Tampered)
+    }
+}
+
+/// Reads a receiver public key from scanned share text (see
+/// [`ReceiverKey::share_text`]) or from bare hex or base64.
+pub fn parse_share_text(text: &str) -> Option<[u8; 32]> {
+    let key = match serde_json::from_str::<serde_json::Value>(text) {
+        Ok(value) => value.get("publicKey")?.as_str()?.to_string(),
+        Err(_) => text.to_string(),
+    };
+    signature::parse_public_key(&key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_with_the_receivers_key_only() {
+        let receiver = ReceiverKey::generate();
+        let (recipient, sealed) = Recipient::seal(&receiver.public_key(), b"for your eyes only");
+        assert_eq!(recipient.public_key, receiver.public_key());
+        assert_eq!(
+            receiver.open(&recipient, &sealed).unwrap(),
+            b"for your eyes only"
+        );
+        assert_eq!(
+            ReceiverKey::generate().open(&recipient, &sealed),
+            Err(OpenError::WrongReceiver)
+        );
+    }
+
+    #[test]
+    fn rejects_tampered_ciphertext_and_keys() {
+        let receiver = ReceiverKey::generate();
+        let (recipient, mut sealed) = Recipient::seal(&receiver.public_key(), b"payload");
+        let mut forged = recipient;
+        forged.ephemeral = [0; 32];
+        assert_eq!(receiver.open(&forged, &sealed), Err(OpenError::Tampered));
+        sealed[0] ^= 1;
+        assert_eq!(receiver.open(&recipient, &sealed), Err(OpenError::Tampered));
+    }
+
+    #[test]
+    fn keeps_the_key_file_and_shares_the_public_key() {
+        let dir = std::env::temp_dir().join(format!("streaming-qr-key-{}", std::process::id()));
+        let path = dir.join(FILE_NAME);
+        let created = ReceiverKey::load_or_generate(&path).unwrap();
+        let loaded = ReceiverKey::load_or_generate(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(created.public_key(), loaded.public_key());
+        assert_eq!(
+            parse_share_text(&created.share_text()),
+            Some(created.public_key())
+        );
+        assert_eq!(
+            parse_share_text(&hex::encode(created.public_key())),
+            Some(created.public_key())
+        );
+        assert_eq!(parse_share_text("not a key"), None);
+    }
+}
@@ -0,0 +1,236 @@
+//! Pure-Rust QR detection on grayscale images.
+//!
+//! Wraps `rqrr`, which binarizes the image, locates finder patterns and
+//! decodes every symbol it finds, so one frame can yield several chunks.
+//! Byte-mode content comes back unchanged, so binary frames and JSON chunks
+//! both go straight to
+//! [`StreamStore::process_payload`](crate::protocol::StreamStore::process_payload).
+
+use rqrr::BitGrid;
+use serde::Serialize;
+
+use crate::protocol::{ChunkOutcome, ProtocolError};
+
+/// Request headers carrying the dimensions of a raw frame sent to
+/// `scan_frame`.
+pub const WIDTH_HEADER: &str = "x-frame-width";
+pub const HEIGHT_HEADER: &str = "x-frame-height";
+
+/// One symbol found in an image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    /// Corners in image coordinates, clockwise from the top left of the
+    /// symbol (which need not be the top left of the image).
+    pub corners: [[i32; 2]; 4],
+    /// The symbol content, or why it could not be read.
+    pub content: Result<Vec<u8>, String>,
+}
+
+/// Where a symbol sits in an image, whether or not it decodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    /// Same as [`Symbol::corners`]. The detector places the far corners one
+    /// module beyond the symbol, so they span `size + 1` modules.
+    pub corners: [[i32; 2]; 4],
+    /// Modules per side.
+    pub size: usize,
+}
+
+/// What became of one symbol of a scanned camera frame.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScannedSymbol {
+    pub corners: [[i32; 2]; 4],
+    /// Base64 of the symbol content.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payload: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<ChunkOutcome>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ProtocolError>,
+}
+
+/// Most passes over one image; a further pass only runs when the previous
+/// one decoded some symbols but failed on others.
+const MAX_PASSES: usize = 16;
+
+/// Decodes every QR symbol in an 8-bit grayscale image stored row by row.
+///
+/// The detector pairs up finder patterns by geometry, and symbols laid out
+/// on a grid line their finder patterns up across neighbours, so a single
+/// pass often pairs patterns of different symbols. Every decoded symbol is
+/// therefore painted over and the image scanned again, until a pass
+/// decodes nothing new.
+pub fn scan(width: usize, height: usize, luma: &[u8]) -> Vec<Symbol> {
+    if width == 0 || height == 0 || luma.len() < width * height {
+        return Vec::new();
+    }
+    let mut image = luma[..width * height].to_vec();
+    let mut symbols = Vec::new();
+    for pass in 1..=MAX_PASSES {
+        let (decoded, failed): (Vec<Symbol>, Vec<Symbol>) = detect(width, height, &image)
+            .into_iter()
+            .partition(|symbol| symbol.content.is_ok());
+        let done = decoded.is_empty() || failed.is_empty() || pass == MAX_PASSES;
+        for symbol in decoded {
+            if !done {
+                erase(&mut image, width, height, &symbol.corners);
+            }
+            symbols.push(symbol);
+        }
+        if done {
+            symbols.extend(failed);
+            break;
+        }
+    }
+    symbols
+}
+
+/// Finds symbols by their finder patterns without decoding them, so even
+/// symbols whose data modules are unreadable are located.
+pub fn locate(width: usize, height: usize, luma: &[u8]) -> Vec<Location> {
+    if width == 0 || height == 0 || luma.len() < width * height {
+        return Vec::new();
+    }
+    let mut image =
+        rqrr::PreparedImage::prepare_from_greyscale(width, height, |x, y| luma[y * width + x]);
+    image
+        .detect_grids()
+        .into_iter()
+        .map(|grid| Location {
+            corners: grid.bounds.map(|point| [point.x, point.y]),
+            size: grid.grid.size(),
+        })
+        .collect()
+}
+
+fn detect(width: usize, height: usize, luma: &[u8]) -> Vec<Symbol> {
+    let mut image =
+        rqrr::PreparedImage::prepare_from_greyscale(width, height, |x, y| luma[y * width + x]);
+    image
+        .detect_grids()
+        .into_iter()
+        .map(|grid| {
+            let mut content = Vec::new();
+            Symbol {
+                corners: grid.bounds.map(|point| [point.x, point.y]),
+                content: grid
+                    .decode_to(&mut content)
+                    .map(|_| content)
+                    .map_err(|e| e.to_string()),
+            }
+        })
+        .collect()
+}
+
+/// Paints the quadrilateral `corners`, grown by a tenth around its centre,
+/// white.
+fn erase(luma: &mut [u8], width: usize, height: usize, corners: &[[i32; 2]; 4]) {
+    let centre = corners.iter().fold([0.0f64; 2], |sum, corner| {
+        [
+            sum[0] + corner[0] as f64 / 4.0,
+            sum[1] + corner[1] as f64 / 4.0,
+        ]
+    });
+    let grown = corners.map(|[x, y]| {
+        [
+            centre[0] + (x as f64 - centre[0]) * 1.1,
+            centre[1] + (y as f64 - centre[1]) * 1.1,
+        ]
+    });
+    let clamp = |value: f64, max: usize| value.clamp(0.0, max as f64 - 1.0) as usize;
+    let (left, right) = grown.iter().fold((f64::MAX, f64::MIN), |(lo, hi), point| {
+        (lo.min(point[0]), hi.max(point[0]))
+    });
+    let (top, bottom) = grown.iter().fold((f64::MAX, f64::MIN), |(lo, hi), point| {
+        (lo.min(point[1]), hi.max(point[1]))
+    });
+
+    // Inside a convex polygon the cross products with every edge share a
+    // sign, whichever way round the corners go.
+    let inside = |x: f64, y: f64| {
+        let sides = (0..4).map(|i| {
+            let ([ax, ay], [bx, by]) = (grown[i], grown[(i + 1) % 4]);
+            (bx - ax) * (y - ay) - (by - ay) * (x - ax)
+        });
+        let (mut positive, mut negative) = (false, false);
+        for side in sides {
+            positive |= side > 0.0;
+            negative |= side < 0.0;
+        }
+        !(positive && negative)
+    };
+    for y in clamp(top, height)..=clamp(bottom, height) {
+        for x in clamp(left, width)..=clamp(right, width) {
+            if inside(x as f64, y as f64) {
+                luma[y * width + x] = 255;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qr::{EccLevel, QrSymbol, QUIET_ZONE};
+
+    const SCALE: usize = 4;
+
+    fn rendered(data: &[u8]) -> (usize, Vec<u8>, QrSymbol) {
+        let symbol = QrSymbol::encode(data, 2, EccLevel::M).unwrap();
+        (symbol.image_size(SCALE), symbol.to_luma(SCALE), symbol)
+    }
+
+    #[test]
+    fn decodes_a_symbol_with_its_corners() {
+        let (size, luma, symbol) = rendered(b"corner test");
+        let near = (QUIET_ZONE * SCALE) as i32;
+        let far = near + ((symbol.width + 1) * SCALE) as i32;
+        let corners = [[near, near], [far, near], [far, far], [near, far]];
+
+        let symbols = scan(size, size, &luma);
+        assert_eq!(
+            symbols,
+            [Symbol {
+                corners,
+                content: Ok(b"corner test".to_vec()),
+            }]
+        );
+        let located = locate(size, size, &luma);
+        assert_eq!(
+            (located[0].corners, located[0].size),
+            (corners, symbol.width)
+        );
+    }
+
+    #[test]
+    fn corners_follow_the_symbol_when_rotated() {
+        let (size, luma, _) = rendered(b"rotated");
+        // A quarter turn clockwise puts the symbol's top left at the top right.
+        let rotated: Vec<u8> = (0..size * size)
+            .map(|i| luma[(size - 1 - i % size) * size + i / size])
+            .collect();
+        let symbols = scan(size, size, &rotated);
+        assert_eq!(symbols[0].content, Ok(b"rotated".to_vec()));
+        let [x, y] = symbols[0].corners[0];
+        assert!(x > size as i32 / 2 && y < size as i32 / 2);
+    }
+
+    #[test]
+    fn finds_nothing_in_blank_or_noisy_images() {
+        let (width, height) = (200, 150);
+        assert!(scan(width, height, &vec![255; width * height]).is_empty());
+        assert!(scan(width, height, &vec![0; width * height]).is_empty());
+        let mut state = 1u32;
+        let noise: Vec<u8> = (0..width * height)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as u8
+            })
+            .collect();
+        assert!(scan(width, height, &noise).is_empty());
+        assert!(scan(0, 0, &[]).is_empty());
+        assert!(scan(width, height, &noise[..100]).is_empty());
+        assert!(locate(width, height, &noise[..100]).is_empty());
+    }
+}
@@ -0,0 +1,468 @@
+//! Sender mode: payload to binary frames to QR symbols.
+//!
+//! With a fixed QR version every data frame is sized to fill exactly that
+//! version, so the receiver sees a steady symbol size. The header frame
+//! carries more metadata and uses the smallest version that fits, starting
+//! from the chosen one.
+//!
+//! In grid mode each screen shows up to `columns × rows` data symbols at
+//! once, filled in sequence order, and every data frame carries its cell
+//! index. The header keeps a screen of its own.
+//!
+//! In layered mode each data screen stacks three symbols (or three grids)
+//! into the red, green and blue channels, see [`color`](crate::color).
+//! The symbols of a screen are brought to one version so that their finder
+//! patterns line up.
+//!
+//! In fountain and RaptorQ mode the data frames are LT droplets or RaptorQ
+//! packets, see [`encoder`](crate::encoder). They repeat a few header
+//! fields, as do the frames of a stream with parity chunks, which is taken
+//! off the payload each frame holds.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::color::{ColorMosaic, LAYERS};
+use crate::compression::Compression;
+use crate::encoder::{self, EncodeOptions, ParityOptions};
+use crate::frame;
+use crate::metadata::FileMetadata;
+use crate::protocol::{Frame, Grid, StreamMode, MAX_GRID_SIDE};
+use crate::qr::{self, EccLevel, Mosaic, QrSymbol};
+use crate::recipient;
+
+/// Worst-case size of a binary data frame without payload: magic, version
+/// and flags, three 5-byte varints (id, seq, total) and the CRC.
+pub const DATA_FRAME_OVERHEAD: usize = 3 + 3 * 5 + 4;
+/// Extension block of a grid data frame: block length, tag, field length
+/// and three 1-byte varints (columns, rows, index).
+pub const CELL_EXTENSION_OVERHEAD: usize = 6;
+/// Header fields an LT data frame repeats: the mode (tag, length, value)
+/// and the payload length (tag, length, varint of up to 5 bytes).
+pub const LT_EXTENSION_OVERHEAD: usize = 3 + 7;
+/// Header fields a RaptorQ data frame repeats: the mode and the 12-byte OTI.
+pub const RAPTORQ_EXTENSION_OVERHEAD: usize = 3 + 14;
+/// Header fields a data frame with parity repeats: group size and parity
+/// (2-byte varints) and the payload length.
+pub const PARITY_EXTENSION_OVERHEAD: usize = 4 + 4 + 7;
+/// Pixels per module for PNG output.
+pub const DEFAULT_SCALE: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameKind {
+    Header,
+    Data,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageFormat {
+    #[default]
+    Png,
+    Svg,
+    /// Raw module matrix, for callers that draw the symbol themselves.
+    Modules,
+}
+
+/// Options of the `create_stream_frames` command.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SendOptions {
+    /// QR version 1–40; the smallest fitting one when unset.
+    pub version: Option<u8>,
+    pub ecc: EccLevel,
+    /// Payload bytes per frame, capped by what the version holds.
+    pub chunk_size: Option<usize>,
+    /// Plain chunks (the default), LT fountain droplets or RaptorQ symbols.
+    pub mode: Option<StreamMode>,
+    /// Extra fountain or RaptorQ frames in percent of the source blocks.
+    pub repair_overhead: Option<u32>,
+    /// Reed–Solomon parity chunks per group of data chunks.
+    pub parity: Option<ParityOptions>,
+    pub compression: Option<Compression>,
+    pub passphrase: Option<String>,
+    /// Receiver public key or scanned receiver QR text to seal the stream to.
+    pub recipient: Option<String>,
+    pub format: ImageFormat,
+    /// Pixels per module for PNG output.
+    pub scale: Option<usize>,
+    /// Shows several data symbols per screen.
+    pub grid: Option<Grid>,
+    /// Stacks three data symbols, or three grids, per screen in the red,
+    /// green and blue channels.
+    pub layered: bool,
+    /// Name, type and times of the file sent. Its size defaults to the
+    /// data size.
+    pub file: Option<FileMetadata>,
+}
+
+/// One frame of a stream with its QR symbol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SenderFrame {
+    pub kind: FrameKind,
+    pub sequence: Option<u32>,
+    pub bytes: Vec<u8>,
+    pub symbol: QrSymbol,
+}
+
+/// Symbols shown at the same time: a single symbol, or in grid mode up to
+/// `grid.cells()` data symbols, and in layered mode up to three times that.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Screen {
+    pub kind: FrameKind,
+    pub grid: Option<Grid>,
+    pub layered: bool,
+    pub frames: Vec<SenderFrame>,
+}
+
+impl Screen {
+    /// Symbols shown in one layer.
+    fn per_layer(&self) -> usize {
+        self.grid.map_or(1, |grid| grid.cells() as usize)
+    }
+
+    /// One mosaic per layer, red first; a single one unless layered.
+    pub fn layers(&self) -> Vec<Mosaic> {
+        let (columns, rows) = self
+            .grid
+            .map_or((1, 1), |grid| (grid.columns as usize, grid.rows as usize));
+        self.frames
+            .chunks(self.per_layer())
+            .map(|frames| {
+                let symbols: Vec<&QrSymbol> = frames.iter().map(|frame| &frame.symbol).collect();
+                Mosaic::new(&symbols, columns, rows)
+            })
+            .collect()
+    }
+
+    /// The screen as shown, layers stacked into colour channels.
+    pub fn picture(&self) -> ColorMosaic {
+        let layers = self.layers();
+        if self.layered {
+            ColorMosaic::layered(&layers)
+        } else {
+            ColorMosaic::from(&layers[0])
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenderedFrame {
+    pub kind: FrameKind,
+    /// Sequence of the (first) data symbol.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<u32>,
+    /// Grid and layered mode: the sequence of every data symbol, layer by
+    /// layer and row by row.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cells: Vec<u32>,
+    pub layered: bool,
+    pub version: u8,
+    pub ecc: EccLevel,
+    /// Modules per side of one symbol, without quiet zone.
+    pub width: usize,
+    /// Base64 PNG.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub png: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub svg: Option<String>,
+    /// Modules format of a black and white screen.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modules: Option<Vec<Vec<bool>>>,
+    /// Modules format of a layered screen: per module, bit 0, 1 and 2 are
+    /// set where the red, green and blue layer is dark.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub masks: Option<Vec<Vec<u8>>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SentStream {
+    pub stream_id: String,
+    pub size: usize,
+    pub frames: Vec<RenderedFrame>,
+}
+
+/// Largest payload per data frame that still fits `version` at `ecc`.
+pub fn max_chunk_size(version: u8, ecc: EccLevel, options: &EncodeOptions) -> usize {
+    qr::byte_capacity(version, ecc)
+        .saturating_sub(DATA_FRAME_OVERHEAD + extension_overhead(options))
+}
+
+/// Worst-case extension block of a data frame: the grid cell and the
+/// header fields the mode repeats, plus the block length.
+fn extension_overhead(options: &EncodeOptions) -> usize {
+    let cell = options.grid.map_or(0, |_| CELL_EXTENSION_OVERHEAD);
+    let repeated = match options.mode {
+        StreamMode::Chunks => 0,
+        StreamMode::Lt => LT_EXTENSION_OVERHEAD,
+        StreamMode::RaptorQ => RAPTORQ_EXTENSION_OVERHEAD,
+    } + options.parity.map_or(0, |_| PARITY_EXTENSION_OVERHEAD);
+    match repeated {
+        0 => cell,
+        _ => cell.max(1) + repeated,
+    }
+}
+
+/// Random numeric stream id, as binary frames require.
+pub fn random_stream_id() -> u32 {
+    let mut bytes = [0u8; 4];
+    getrandom::fill(&mut bytes).expect("system random source unavailable");
+    u32::from_le_bytes(bytes)
+}
+
+/// Encodes `data` as a stream and every frame as a QR symbol.
+pub fn build_frames(
+    stream_id: u32,
+    data: &[u8],
+    version: Option<u8>,
+    ecc: EccLevel,
+    options: &EncodeOptions,
+) -> Result<Vec<SenderFrame>, String> {
+    if options.grid.is_some_and(|grid| !grid.is_valid()) {
+        return Err(format!(
+            "A grid needs 1 to {} columns and rows",
+            MAX_GRID_SIDE
+        ));
+    }
+    let limit = max_chunk_size(version.unwrap_or(qr::MAX_VERSION), ecc, options);
+    if limit == 0 {
+        return Err(format!(
+            "QR version {:?} is too small for a data frame",
+            version
+        ));
+    }
+    let options = EncodeOptions {
+        chunk_size: options.chunk_size.min(limit),
+        ..options.clone()
+    };
+
+    encoder::encode_stream(&stream_id.to_string(), data, &options)?
+        .into_iter()
+        .map(|frame| {
+            let bytes = frame::encode(&frame).map_err(|e| e.to_string())?;
+            let (kind, sequence) = match &frame {
+                Frame::Data(chunk) => (FrameKind::Data, Some(chunk.sequence)),
+                Frame::Header { .. } => (FrameKind::Header, None),
+            };
+            let symbol = match (kind, version) {
+                (FrameKind::Data, Some(version)) => QrSymbol::encode(&bytes, version, ecc)?,
+                _ => QrSymbol::encode_fitting(&bytes, version.unwrap_or(qr::MIN_VERSION), ecc)?,
+            };
+            Ok(SenderFrame {
+                kind,
+                sequence,
+                bytes,
+                symbol,
+            })
+        })
+        .collect()
+}
+
+impl SendOptions {
+    pub fn scale(&self) -> usize {
+        self.scale.unwrap_or(DEFAULT_SCALE).max(1)
+    }
+}
+
+/// Groups frames into screens: the header alone, data frames
+/// `grid.cells()` at a time, three times as many when `layered`.
+pub fn screens(frames: Vec<SenderFrame>, grid: Option<Grid>, layered: bool) -> Vec<Screen> {
+    let layers = if layered { LAYERS } else { 1 };
+    let per_screen = grid.map_or(1, |grid| grid.cells() as usize) * layers;
+    let mut screens: Vec<Screen> = Vec::new();
+    for frame in frames {
+        match screens.last_mut() {
+            Some(screen)
+                if frame.kind == FrameKind::Data
+                    && screen.kind == FrameKind::Data
+                    && screen.frames.len() < per_screen =>
+            {
+                screen.frames.push(frame)
+            }
+            _ => screens.push(Screen {
+                kind: frame.kind,
+                grid: grid.filter(|_| frame.kind == FrameKind::Data),
+                layered: layered && frame.kind == FrameKind::Data,
+                frames: vec![frame],
+            }),
+        }
+    }
+    for screen in screens.iter_mut().filter(|screen| screen.layered) {
+        align_versions(&mut screen.frames);
+    }
+    screens
+}
+
+/// Re-encodes smaller symbols in the largest version among `frames`.
+fn align_versions(frames: &mut [SenderFrame]) {
+    let Some(version) = frames.iter().map(|frame| frame.symbol.version).max() else {
+        return;
+    };
+    for frame in frames {
+        if frame.symbol.version < version {
+            frame.symbol = QrSymbol::encode(&frame.bytes, version, frame.symbol.ecc)
+                .expect("a frame fits any larger version");
+        }
+    }
+}
+
+/// Runs [`build_frames`] for the command options under a new stream id
+/// and groups the frames into [`screens`].
+pub fn stream_screens(data: &[u8], options: &SendOptions) -> Result<(u32, Vec<Screen>), String> {
+    let recipient = match &options.recipient {
+        Some(text) => Some(
+            recipient::parse_share_text(text)
+                .ok_or_else(|| "Invalid receiver public key".to_string())?,
+        ),
+        None => None,
+    };
+    let encode = EncodeOptions {
+        chunk_size: options.chunk_size.unwrap_or(encoder::DEFAULT_CHUNK_SIZE),
+        mode: options.mode.unwrap_or(StreamMode::Chunks),
+        repair_overhead: options
+            .repair_overhead
+            .unwrap_or(encoder::DEFAULT_REPAIR_OVERHEAD),
+        parity: options.parity,
+        compression: options.compression,
+        passphrase: options.passphrase.clone(),
+        recipient,
+        grid: options.grid,
+        file: options.file.clone().map(|file| FileMetadata {
+            size: file.size.or(Some(data.len() as u64)),
+            ..file
+        }),
+        ..EncodeOptions::default()
+    };
+
+    let stream_id = random_stream_id();
+    let frames = build_frames(stream_id, data, options.version, options.ecc, &encode)?;
+    Ok((stream_id, screens(frames, options.grid, options.layered)))
+}
+
+/// Runs [`stream_screens`] and renders every screen.
+pub fn send(data: &[u8], options: &SendOptions) -> Result<SentStream, String> {
+    let (stream_id, screens) = stream_screens(data, options)?;
+    Ok(SentStream {
+        stream_id: stream_id.to_string(),
+        size: data.len(),
+        frames: screens
+            .iter()
+            .map(|screen| render(screen, options.format, options.scale()))
+            .collect(),
+    })
+}
+
+pub fn render(screen: &Screen, format: ImageFormat, scale: usize) -> RenderedFrame {
+    let symbol = &screen.frames[0].symbol;
+    let picture = screen.picture();
+    let modules = format == ImageFormat::Modules;
+    RenderedFrame {
+        kind: screen.kind,
+        sequence: screen.frames[0].sequence,
+        cells: if screen.grid.is_some() || screen.layered {
+            screen
+                .frames
+                .iter()
+                .filter_map(|frame| frame.sequence)
+                .collect()
+        } else {
+            Vec::new()
+        },
+        layered: screen.layered,
+        version: symbol.version,
+        ecc: symbol.ecc,
+        width: symbol.width,
+        png: (format == ImageFormat::Png).then(|| BASE64.encode(picture.to_png(scale))),
+        svg: (format == ImageFormat::Svg).then(|| picture.to_svg()),
+        modules: (modules && !screen.layered).then(|| screen.layers()[0].rows()),
+        masks: (modules && screen.layered).then(|| picture.rows()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::StreamStore;
+    use crate::scanner;
+
+    fn payload(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 % 253) as u8).collect()
+    }
+
+    #[test]
+    fn data_frames_fit_the_chosen_version() {
+        let parity = Some(ParityOptions {
+            group_size: 4,
+            parity: 1,
+        });
+        let grid = Some(Grid {
+            columns: 2,
+            rows: 2,
+        });
+        let modes = [
+            (StreamMode::Chunks, None, None),
+            (StreamMode::Chunks, parity, grid),
+            (StreamMode::Lt, None, grid),
+            (StreamMode::RaptorQ, None, None),
+        ];
+        let data = payload(3000);
+        for (version, ecc) in [(5, EccLevel::L), (12, EccLevel::Q), (20, EccLevel::H)] {
+            for (mode, parity, grid) in modes {
+                let options = EncodeOptions {
+                    chunk_size: usize::MAX,
+                    mode,
+                    parity,
+                    grid,
+                    ..EncodeOptions::default()
+                };
+                let limit = max_chunk_size(version, ecc, &options);
+                let frames = build_frames(u32::MAX, &data, Some(version), ecc, &options).unwrap();
+                let data_frames = frames.iter().filter(|frame| frame.kind == FrameKind::Data);
+                for frame in data_frames {
+                    assert_eq!(frame.symbol.version, version);
+                    assert!(frame.bytes.len() <= qr::byte_capacity(version, ecc));
+                }
+                // The first data frame carries a full chunk.
+                let first = frames.iter().find(|frame| frame.kind == FrameKind::Data);
+                assert!(first.unwrap().bytes.len() > limit);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_versions_too_small_for_a_data_frame() {
+        let options = EncodeOptions {
+            mode: StreamMode::RaptorQ,
+            ..EncodeOptions::default()
+        };
+        assert_eq!(max_chunk_size(1, EccLevel::H, &options), 0);
+        assert!(build_frames(1, b"data", Some(1), EccLevel::H, &options).is_err());
+    }
+
+    #[test]
+    fn rendered_screens_decode_to_the_payload() {
+        let data = payload(2000);
+        let options = SendOptions {
+            version: Some(8),
+            ..SendOptions::default()
+        };
+        let (stream_id, screens) = stream_screens(&data, &options).unwrap();
+        assert_eq!(screens[0].kind, FrameKind::Header);
+        let mut store = StreamStore::new();
+        for screen in &screens {
+            let layer = &screen.layers()[0];
+            let (width, height) = layer.image_size(3);
+            let symbols = scanner::scan(width, height, &layer.to_luma(3));
+            assert_eq!(symbols.len(), 1);
+            store
+                .process_payload(symbols[0].content.as_ref().unwrap())
+                .unwrap();
+        }
+        let stream = store.reconstruct(&stream_id.to_string()).unwrap();
+        assert_eq!(stream.data, data);
+    }
+}
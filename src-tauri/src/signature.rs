@@ -0,0 +1,89 @@
+//! Ed25519 sender signatures.
+//!
+//! The sender signs the 32-byte stream digest and sends the signature with
+//! its public key in the header chunk, next to the digest.
+//! Whether the signer is trusted is decided by the receiver's
+//! [`crate::keyring::Keyring`], not by the stream.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+
+pub const PUBLIC_KEY_LEN: usize = 32;
+pub const SIGNATURE_LEN: usize = 64;
+
+/// A signature over a stream digest and the key that made it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamSignature {
+    pub public_key: [u8; PUBLIC_KEY_LEN],
+    pub signature: [u8; SIGNATURE_LEN],
+}
+
+impl StreamSignature {
+    /// Signs `digest` with the 32-byte Ed25519 secret key `secret`.
+    pub fn sign(secret: &[u8; 32], digest: &[u8; 32]) -> Self {
+        let key = SigningKey::from_bytes(secret);
+        Self {
+            public_key: key.verifying_key().to_bytes(),
+            signature: key.sign(digest).to_bytes(),
+        }
+    }
+
+    pub fn verify(&self, digest: &[u8; 32]) -> bool {
+        let Ok(key) = VerifyingKey::from_bytes(&self.public_key) else {
+            return false;
+        };
+        let signature = ed25519_dalek::Signature::from_bytes(&self.signature);
+        key.verify_strict(digest, &signature).is_ok()
+    }
+}
+
+/// Public key for a secret key, e.g. to hand to receivers for their keyring.
+pub fn public_key(secret: &[u8; 32]) -> [u8; PUBLIC_KEY_LEN] {
+    SigningKey::from_bytes(secret).verifying_key().to_bytes()
+}
+
+/// Parses a public key written as 64 hex digits or as base64.
+pub fn parse_public_key(text: &str) -> Option<[u8; PUBLIC_KEY_LEN]> {
+    let text = text.trim();
+    let mut key = [0u8; PUBLIC_KEY_LEN];
+    if hex::decode_to_slice(text, &mut key).is_ok() {
+        return Some(key);
+    }
+    BASE64.decode(text).ok()?.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verifies_the_signed_digest_only() {
+        let digest = [9; 32];
+        let signed = StreamSignature::sign(&[1; 32], &digest);
+        assert_eq!(signed.public_key, public_key(&[1; 32]));
+        assert!(signed.verify(&digest));
+        assert!(!signed.verify(&[8; 32]));
+
+        let mut forged = signed;
+        forged.signature[0] ^= 1;
+        assert!(!forged.verify(&digest));
+        let other = StreamSignature {
+            public_key: public_key(&[2; 32]),
+            ..signed
+        };
+        assert!(!other.verify(&digest));
+    }
+
+    #[test]
+    fn parses_hex_and_base64_public_keys() {
+        let key = public_key(&[3; 32]);
+        assert_eq!(
+            parse_public_key(&format!(" {} ", hex::encode(key))),
+            Some(key)
+        );
+        assert_eq!(parse_public_key(&BASE64.encode(key)), Some(key));
+        assert_eq!(parse_public_key(&hex::encode(&key[..31])), None);
+        assert_eq!(parse_public_key("not a key"), None);
+    }
+}
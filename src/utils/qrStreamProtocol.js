@@ -6,9 +6,32 @@
  * - seq: sequence number (0-based)
  * - total: total number of chunks
  * - data: base64 encoded chunk data
- * - checksum: CRC32 checksum of the chunk data
+ * - checksum: CRC32 checksum of the decoded chunk data (8-digit hex)
  */
 
+const CRC32_TABLE = (() => {
+  const table = new Uint32Array(256);
+  for (let n = 0; n < 256; n++) {
+    let c = n;
+    for (let k = 0; k < 8; k++) {
+      c = c & 1 ? 0xedb88320 ^ (c >>> 1) : c >>> 1;
+    }
+    table[n] = c >>> 0;
+  }
+  return table;
+})();
+
+/**
+ * CRC32 of a binary string (one byte per character, as produced by atob)
+ */
+export function crc32(binaryString) {
+  let crc = 0xffffffff;
+  for (let i = 0; i < binaryString.length; i++) {
+    crc = CRC32_TABLE[(crc ^ binaryString.charCodeAt(i)) & 0xff] ^ (crc >>> 8);
+  }
+  return ((crc ^ 0xffffffff) >>> 0).toString(16).padStart(8, '0');
+}
+
 export class QRStreamProtocol {
   constructor() {
     this.streams = new Map(); // Map<streamId, StreamData>
@@ -215,7 +238,7 @@ export function createQRChunk(streamId, sequence, total, chunkData) {
     seq: sequence,
     total: total,
     data: btoa(chunkData), // base64 encode
-    checksum: null // Could add CRC32 here
+    checksum: crc32(chunkData)
   });
 }
 